@@ -1,17 +1,379 @@
 //! Abstract Syntax Tree
 
+use crate::error::{Error, Result};
 use crate::target::TargetInfo;
 
 use log::debug;
-use serde_derive::Serialize;
+use serde_derive::{Deserialize, Serialize};
+
+/// AST of an assembly file.
+#[derive(Debug, Clone)]
+pub struct Module {
+    /// Path of the assembly file.
+    pub path: ::std::path::PathBuf,
+    pub functions: Vec<Function>,
+    pub data: Vec<Data>,
+    pub sections: Vec<Section>,
+    /// Table of `.file` directives indexed by file index.
+    pub files: ::std::collections::HashMap<usize, File>,
+    /// Symbols indexed by their (mangled) name.
+    pub symbols: ::std::collections::HashMap<String, Symbol>,
+    /// Byte offset of the beginning of each line, and of the end of the
+    /// file.
+    pub line_offsets: Vec<u64>,
+}
+
+impl Module {
+    /// Returns the function with the demangled name `id`.
+    pub fn function(&self, id: &str) -> Option<&Function> {
+        self.functions.iter().find(|f| f.id == id)
+    }
+}
 
 /// AST of an asm function
 #[derive(Debug, Clone)]
 pub struct Function {
     pub id: String,
+    /// Label of the function, i.e., its mangled name.
+    pub label: String,
+    /// Lines of the function in the file, from its label to its end
+    /// (0-based, exclusive).
+    pub lines: ::std::ops::Range<usize>,
+    /// Assembly file of the function.
+    pub asm_file: ::std::path::PathBuf,
     pub file: Option<File>,
     pub loc: Option<Loc>,
+    /// Location of the function in its debugging information, if it has
+    /// been read. See `dwarf::declaration`.
+    pub decl: Option<Loc>,
     pub statements: Vec<Statement>,
+    /// Data objects referenced by the function.
+    pub data: Vec<Data>,
+}
+
+/// Data object: a label in a data section and the values that follow it,
+/// e.g., `.L__unnamed_1: .ascii "foo"`.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Data {
+    pub id: String,
+    pub section: String,
+    pub values: Vec<Value>,
+}
+
+impl Data {
+    /// Does the data contain floating-point constants?
+    fn is_float(&self) -> bool {
+        // LLVM puts floating-point constants in mergeable constant
+        // sections, but these can also contain integers (e.g. masks):
+        let in_constant_section = self.section.contains(".cst")
+            || self.section.contains("__literal");
+        in_constant_section
+            && self.values.iter().all(|v| match *v {
+                Value::Int { size: 4, value } => {
+                    let f = f32::from_bits(value as u32);
+                    f.is_normal() || f == 0.
+                }
+                Value::Int { size: 8, value } => {
+                    let f = f64::from_bits(value);
+                    f.is_normal() || f == 0.
+                }
+                _ => false,
+            })
+    }
+
+    /// Targets of a jump table, e.g., `.LJTI0_0: .long .LBB0_2-.LJTI0_0`.
+    ///
+    /// Returns `None` if the data is not a jump table.
+    pub fn jump_targets(&self) -> Option<Vec<String>> {
+        if !self.id.contains("JTI") || self.values.is_empty() {
+            return None;
+        }
+        self.values
+            .iter()
+            .map(|v| match *v {
+                Value::Symbol { ref name, .. } => {
+                    Some(first_symbol(name).to_string())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Short description of the decoded data, e.g., the string `"foo"`, a
+    /// floating-point constant, or the values of a lookup table.
+    pub fn describe(&self) -> String {
+        if let Some(targets) = self.jump_targets() {
+            return format!("jump table with {} entries", targets.len());
+        }
+        // Byte strings:
+        let bytes = self.values.iter().try_fold(Vec::new(), |mut b, v| {
+            match *v {
+                Value::Bytes(ref x) => b.extend(x),
+                Value::Zero(n) => b.resize(b.len() + n, 0),
+                Value::Int { size: 1, value } => b.push(value as u8),
+                _ => return None,
+            }
+            Some(b)
+        });
+        if let Some(s) = bytes.as_ref().and_then(|b| as_string(b)) {
+            return format!("{:?}", s);
+        }
+
+        let is_float = self.is_float();
+        let values = self
+            .values
+            .iter()
+            .map(|v| match *v {
+                Value::Int { size: 4, value } if is_float => {
+                    format!("{:?}", f32::from_bits(value as u32))
+                }
+                Value::Int { size: 8, value } if is_float => {
+                    format!("{:?}", f64::from_bits(value))
+                }
+                Value::Int { size, value } => {
+                    to_signed(value, size).to_string()
+                }
+                Value::Symbol { ref name, .. } => name.clone(),
+                Value::Bytes(ref b) => match as_string(b) {
+                    Some(s) => format!("{:?}", s),
+                    None => format!("{:?}", b),
+                },
+                Value::Zero(n) => format!("[0; {}]", n),
+            })
+            .collect::<Vec<_>>();
+        if values.len() == 1 {
+            values[0].clone()
+        } else {
+            format!("[{}]", values.join(", "))
+        }
+    }
+}
+
+/// First symbol of the expression `expr`, e.g., `.LBB0_2` in
+/// `.LBB0_2-.LJTI0_0` or `(.LBB0_2-.Ltmp0)>>2`.
+pub fn first_symbol(expr: &str) -> &str {
+    expr.trim_start_matches('(')
+        .split(&['-', '+', ')'][..])
+        .next()
+        .unwrap_or("")
+        .trim()
+}
+
+/// Interprets `bytes` as a printable string (ignoring the zero terminator).
+fn as_string(bytes: &[u8]) -> Option<&str> {
+    let bytes = match bytes.split_last() {
+        Some((0, rest)) => rest,
+        _ => bytes,
+    };
+    let s = ::std::str::from_utf8(bytes).ok()?;
+    if s.is_empty() || s.chars().any(|c| c.is_control() && !c.is_whitespace())
+    {
+        return None;
+    }
+    Some(s)
+}
+
+/// Sign-extends the integer `value` of `size` bytes.
+fn to_signed(value: u64, size: usize) -> i64 {
+    let shift = 64 - 8 * size.min(8) as u32;
+    ((value << shift) as i64) >> shift
+}
+
+/// Parses an integer literal: decimal, hexadecimal, octal, or binary.
+pub fn parse_int(s: &str) -> Option<i128> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+    let v = if let Some(h) =
+        s.strip_prefix("0x").or_else(|| s.strip_prefix("0X"))
+    {
+        i128::from_str_radix(h, 16).ok()?
+    } else if let Some(b) = s.strip_prefix("0b") {
+        i128::from_str_radix(b, 2).ok()?
+    } else if s.len() > 1 && s.starts_with('0') {
+        i128::from_str_radix(&s[1..], 8).ok()?
+    } else {
+        s.parse::<i128>().ok()?
+    };
+    Some(if negative { -v } else { v })
+}
+
+/// Value of a data directive.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum Value {
+    /// Bytes of a string: `.ascii`, `.asciz`, `.string` (including the
+    /// zero terminator).
+    Bytes(Vec<u8>),
+    /// Integer of `size` bytes: `.byte`, `.short`, `.long`, `.quad`.
+    Int { size: usize, value: u64 },
+    /// Symbol or expression of `size` bytes, e.g., `.quad .L__unnamed_1`.
+    Symbol { size: usize, name: String },
+    /// `n` zero bytes: `.zero n`, `.space n`.
+    Zero(usize),
+}
+
+impl ::std::fmt::Display for Value {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        fn directive(size: usize) -> &'static str {
+            match size {
+                1 => ".byte",
+                2 => ".short",
+                4 => ".long",
+                _ => ".quad",
+            }
+        }
+        match *self {
+            Value::Bytes(ref b) => match (as_string(b), b.last()) {
+                (Some(s), Some(0)) => write!(f, ".asciz {:?}", s),
+                (Some(s), _) => write!(f, ".ascii {:?}", s),
+                (None, _) => {
+                    let bytes = b
+                        .iter()
+                        .map(|b| format!("{:#04x}", b))
+                        .collect::<Vec<_>>();
+                    write!(f, ".byte {}", bytes.join(", "))
+                }
+            },
+            Value::Int { size, value } => {
+                write!(f, "{} {}", directive(size), to_signed(value, size))
+            }
+            Value::Symbol { size, ref name } => {
+                write!(f, "{} {}", directive(size), name)
+            }
+            Value::Zero(n) => write!(f, ".zero {}", n),
+        }
+    }
+}
+
+impl Value {
+    /// Parses the values of the data directive `s`.
+    ///
+    /// Returns `None` if `s` is not a data directive.
+    pub fn parse(s: &str) -> Option<Vec<Self>> {
+        let mut tokens = s.splitn(2, char::is_whitespace);
+        let directive = tokens.next()?;
+        let args = tokens.next().unwrap_or("").trim();
+        let size = match directive {
+            ".ascii" | ".asciz" | ".string" => {
+                let zero = directive != ".ascii";
+                let values = tokenize(args)
+                    .ok()?
+                    .into_iter()
+                    .filter_map(|t| match t {
+                        Token::Str(s) => {
+                            let mut b = unescape_bytes(s);
+                            if zero {
+                                b.push(0);
+                            }
+                            Some(Value::Bytes(b))
+                        }
+                        Token::Word(_) => None,
+                    })
+                    .collect();
+                return Some(values);
+            }
+            ".zero" | ".space" => {
+                let n = parse_int(args.split(',').next()?.trim())?;
+                return Some(vec![Value::Zero(n as usize)]);
+            }
+            ".byte" => 1,
+            ".short" | ".hword" | ".2byte" => 2,
+            ".long" | ".int" | ".4byte" => 4,
+            ".quad" | ".8byte" | ".xword" => 8,
+            _ => return None,
+        };
+        Some(
+            args.split(',')
+                .map(str::trim)
+                .filter(|a| !a.is_empty())
+                .map(|a| match parse_int(a) {
+                    Some(v) => Value::Int {
+                        size,
+                        value: (v as u64) & (u64::MAX >> (64 - 8 * size)),
+                    },
+                    None => Value::Symbol {
+                        size,
+                        name: a.to_string(),
+                    },
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Assembly section, e.g., `.section .rodata,"a",@progbits`.
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct Section {
+    pub name: String,
+}
+
+impl Section {
+    /// Parses section directives: `.section name,...`, `.text`, `.data`,
+    /// ...
+    pub fn new(s: &str) -> Option<Self> {
+        let mut tokens = s.splitn(2, char::is_whitespace);
+        let name = match tokens.next()? {
+            ".section" => tokens
+                .next()?
+                .split(',')
+                .next()?
+                .trim()
+                .trim_matches('"')
+                .to_string(),
+            d @ ".text" | d @ ".data" | d @ ".bss" | d @ ".rodata" => {
+                d.to_string()
+            }
+            _ => return None,
+        };
+        // On MacOSX the section name contains the segment name,
+        // e.g., __TEXT,__text:
+        let name = if name.starts_with("__") {
+            s.trim_start_matches(".section")
+                .split(',')
+                .take(2)
+                .map(str::trim)
+                .collect::<Vec<_>>()
+                .join(",")
+        } else {
+            name
+        };
+        Some(Self { name })
+    }
+    /// Does the section contain code?
+    pub fn is_text(&self) -> bool {
+        self.name.starts_with(".text") || self.name == "__TEXT,__text"
+    }
+}
+
+/// Kind of a symbol as specified by a `.type` directive.
+#[derive(PartialEq, Debug, Copy, Clone, Serialize)]
+pub enum SymbolKind {
+    Function,
+    Object,
+    Unknown,
+}
+
+/// Symbol attributes collected from `.globl`, `.type`, and `.size`
+/// directives.
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub global: bool,
+    /// Size expression, e.g., `.Lfunc_end0-foo` or `24`.
+    pub size: Option<String>,
+}
+
+impl Symbol {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            kind: SymbolKind::Unknown,
+            global: false,
+            size: None,
+        }
+    }
 }
 
 /// Statemets
@@ -53,14 +415,114 @@ pub enum Directive {
     Generic(GenericDirective),
 }
 
-#[derive(PartialEq, Debug, Clone, Serialize)]
+/// File directive, e.g., `.file 1 "/comp/dir" "src/lib.rs" md5 0x...`.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub struct File {
+    /// Path of the file, already joined with its directory.
     pub path: ::std::path::PathBuf,
     pub index: usize,
+    /// MD5 checksum of the file contents as lower-case hex digits.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+    /// Source code of the file embedded in the directive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+/// Token of a directive: either a word or the raw contents of a string.
+#[derive(PartialEq, Debug)]
+enum Token<'a> {
+    Word(&'a str),
+    Str(&'a str),
+}
+
+/// Splits a directive into whitespace separated words and quoted strings.
+fn tokenize(s: &str) -> Result<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() || c == ',' {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut end = None;
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => {
+                        chars.next();
+                    }
+                    '"' => {
+                        end = Some(i);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            let end =
+                end.ok_or_else(|| Error::parse(s, "unterminated string"))?;
+            tokens.push(Token::Str(&s[start + 1..end]));
+        } else {
+            let mut end = s.len();
+            while let Some(&(i, c)) = chars.peek() {
+                if c.is_whitespace() || c == ',' || c == '"' {
+                    end = i;
+                    break;
+                }
+                chars.next();
+            }
+            tokens.push(Token::Word(&s[start..end]));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Replaces the escape sequences of an assembly string.
+fn unescape(s: &str) -> String {
+    String::from_utf8_lossy(&unescape_bytes(s)).into_owned()
+}
+
+/// Replaces the escape sequences of an assembly string, returning its bytes.
+fn unescape_bytes(s: &str) -> Vec<u8> {
+    let mut r = Vec::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            r.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => r.push(b'\n'),
+            Some('t') => r.push(b'\t'),
+            Some('r') => r.push(b'\r'),
+            Some('b') => r.push(8),
+            Some('f') => r.push(12),
+            Some(d) if d.is_digit(8) => {
+                // Octal escape with up to three digits:
+                let mut v = d.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(d) => {
+                            v = v * 8 + d;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                r.push(v as u8);
+            }
+            Some(c) => {
+                let mut buf = [0; 4];
+                r.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+            None => r.push(b'\\'),
+        }
+    }
+    r
 }
 
 impl File {
-    pub fn new(s: &str, target: &TargetInfo) -> Option<Self> {
+    pub fn new(s: &str, target: &TargetInfo) -> Result<Option<Self>> {
         fn contains_file_label(s: &str, target: &TargetInfo) -> bool {
             if target.is_windows() {
                 s.starts_with(".cv_file") && !s.starts_with(".cv_filec")
@@ -70,35 +532,60 @@ impl File {
         }
 
         if !contains_file_label(s, target) {
-            return None;
+            return Ok(None);
         }
         debug!("parsing file directive: {}", s);
 
-        let file_path_index_index = 1;
-        let ws_tokens = s.split_whitespace().collect::<Vec<_>>();
+        let tokens = tokenize(s)?;
 
-        let file_path_index = 1;
-        let colon_tokens = s.split('"').collect::<Vec<_>>();
+        // A .file directive without an index (e.g. `.file "foo-cgu.0"`)
+        // names the object file and is not part of the debug file table:
+        let index = match tokens.get(1) {
+            Some(Token::Word(w)) => w.parse().map_err(|_| {
+                Error::parse(
+                    s,
+                    "could not get the file index of the directive",
+                )
+            })?,
+            _ => return Ok(None),
+        };
 
-        let path = colon_tokens
-            .get(file_path_index)
-            .unwrap_or_else(|| panic!("could not get file path of {} | file_path_index: {} | tokens: {:?}", s, file_path_index, &colon_tokens));
+        let strings = tokens
+            .iter()
+            .skip(2)
+            .take_while(|t| match t {
+                Token::Str(_) => true,
+                Token::Word(_) => false,
+            })
+            .map(|t| match t {
+                Token::Str(s) | Token::Word(s) => *s,
+            })
+            .collect::<Vec<_>>();
 
-        if colon_tokens.is_empty() {
-            return None;
-        }
-        // On Linux some files miss the file index:
-        let index = ws_tokens
-            .get(file_path_index_index)
-            .unwrap_or_else(|| panic!("could not get file index of {} | file_path_index_index: {} | tokens: {:?}", s, file_path_index_index, &ws_tokens))
-            .parse()
-            .unwrap_or(0);
-        if ws_tokens.is_empty() {
-            return None;
-        }
+        // Value of the `key value` pair following the strings:
+        let value = |key: &str| -> Option<&str> {
+            tokens
+                .iter()
+                .skip(2 + strings.len())
+                .skip_while(|t| **t != Token::Word(key))
+                .nth(1)
+                .map(|t| match t {
+                    Token::Str(s) | Token::Word(s) => *s,
+                })
+        };
 
-        let mut path_str = path.trim().to_string();
-        if target.is_windows() {
+        let (path_str, md5) = if target.is_windows() {
+            // .cv_file index "path" ["checksum" checksum_kind]
+            let path = strings.first().ok_or_else(|| {
+                Error::parse(s, "could not get the file path of the directive")
+            })?;
+            let checksum_kind = tokens.get(2 + strings.len());
+            let md5 = match (strings.get(1), checksum_kind) {
+                // Checksum kind 1 is MD5:
+                (Some(c), Some(Token::Word("1"))) => Some(c.to_lowercase()),
+                _ => None,
+            };
+            let mut path_str = path.trim().to_string();
             // Replace \\ with \ on windows
             replace_slashes(&mut path_str);
             // FIXME: on windows these paths do not follow the UNC, but we
@@ -108,11 +595,44 @@ impl File {
             // is not the same as the path of where the rust-src
             // component is installed in the user's machine, and the rust-src
             // component does not necessarily need to be installed.
-        }
+            (path_str, md5)
+        } else {
+            // .file index ["directory"] "name" [md5 0x...] [source "..."]
+            let (dir, name) = match strings.as_slice() {
+                [name] => (None, name),
+                [dir, name] => (Some(dir), name),
+                _ => {
+                    return Err(Error::parse(
+                        s,
+                        "could not get the file path of the directive",
+                    ))
+                }
+            };
+            let name = ::std::path::PathBuf::from(unescape(name));
+            let path = match dir {
+                Some(dir) if !dir.is_empty() && !name.is_absolute() => {
+                    ::std::path::PathBuf::from(unescape(dir)).join(name)
+                }
+                _ => name,
+            };
+            let md5 = value("md5").map(|v| {
+                v.trim_start_matches("0x")
+                    .trim_start_matches("0X")
+                    .to_lowercase()
+            });
+            (path.to_string_lossy().into_owned(), md5)
+        };
+        let source = value("source").map(unescape);
+
         let path = ::std::path::PathBuf::from(path_str);
         debug!("parsed file path: {}", path.display());
 
-        Some(Self { path, index })
+        Ok(Some(Self {
+            path,
+            index,
+            md5,
+            source,
+        }))
     }
     pub fn rust_loc(&self) -> Option<Loc> {
         None
@@ -127,17 +647,17 @@ pub struct Loc {
 }
 
 impl Loc {
-    pub fn new(s: &str, target: &TargetInfo) -> Option<Self> {
+    pub fn new(s: &str, target: &TargetInfo) -> Result<Option<Self>> {
         fn contains_loc_label(s: &str, target: &TargetInfo) -> bool {
             if target.is_windows() {
-                s.contains(".cv_loc")
+                s.starts_with(".cv_loc")
             } else {
-                s.contains(".loc")
+                s.starts_with(".loc ") || s.starts_with(".loc\t")
             }
         }
 
         if !contains_loc_label(s, target) {
-            return None;
+            return Ok(None);
         }
 
         let file_index_index = if target.is_windows() {
@@ -163,16 +683,31 @@ impl Loc {
         };
 
         let tokens = s.split_whitespace().collect::<Vec<_>>();
-        let file_index = tokens[file_index_index];
-        let file_line = tokens[file_line_index];
+        let token = |idx: usize, what: &str| -> Result<usize> {
+            tokens
+                .get(idx)
+                .ok_or_else(|| {
+                    Error::parse(s, format!("missing {} of location", what))
+                })?
+                .parse()
+                .map_err(|_| {
+                    Error::parse(s, format!("invalid {} of location", what))
+                })
+        };
+        let file_index = token(file_index_index, "file index")?;
+        let file_line = token(file_line_index, "line")?;
         // On Linux the file-column is not emitted so we just set it to zero
         // here.
-        let file_column = tokens.get(file_column_index).unwrap_or(&"0");
-        Some(Self {
-            file_index: file_index.parse().unwrap(),
-            file_line: file_line.parse().unwrap(),
-            file_column: file_column.parse().unwrap(),
-        })
+        let file_column = if tokens.get(file_column_index).is_some() {
+            token(file_column_index, "column")?
+        } else {
+            0
+        };
+        Ok(Some(Self {
+            file_index,
+            file_line,
+            file_column,
+        }))
     }
     pub fn rust_loc(&self) -> Option<Self> {
         Some(*self)
@@ -212,19 +747,17 @@ impl GenericDirective {
 }
 
 impl Directive {
-    pub fn new(s: &str, target: &TargetInfo) -> Option<Self> {
+    pub fn new(s: &str, target: &TargetInfo) -> Result<Option<Self>> {
         if is_directive(s) {
-            if let Some(file) = File::new(s, target) {
-                return Some(Directive::File(file));
+            if let Some(file) = File::new(s, target)? {
+                return Ok(Some(Directive::File(file)));
             }
-            if let Some(loc) = Loc::new(s, target) {
-                return Some(Directive::Loc(loc));
+            if let Some(loc) = Loc::new(s, target)? {
+                return Ok(Some(Directive::Loc(loc)));
             }
-            return Some(Directive::Generic(
-                GenericDirective::new(s).unwrap(),
-            ));
+            return Ok(GenericDirective::new(s).map(Directive::Generic));
         }
-        None
+        Ok(None)
     }
     pub fn rust_loc(&self) -> Option<Loc> {
         match *self {
@@ -254,8 +787,8 @@ pub struct Comment {
 }
 
 impl Comment {
-    pub fn new(s: &str) -> Option<Self> {
-        if s.starts_with(';') {
+    pub fn new(s: &str, target: &TargetInfo) -> Option<Self> {
+        if target.comment_delimiters().iter().any(|d| s.starts_with(d)) {
             return Some(Self {
                 string: s.trim().to_string(),
             });
@@ -273,6 +806,12 @@ pub struct Instruction {
     pub instr: String,
     pub args: Vec<String>,
     rust_loc: Option<Loc>,
+    /// Description of the data referenced by the instruction.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// Labels that an indirect jump through a jump table can jump to.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub jump_targets: Vec<String>,
 }
 
 impl Instruction {
@@ -294,6 +833,8 @@ impl Instruction {
             instr,
             args,
             rust_loc,
+            data: None,
+            jump_targets: Vec::new(),
         };
         v.demangle_args(&target);
         Some(v)
@@ -320,6 +861,24 @@ impl Instruction {
             false
         }
     }
+    /// Is the instruction a jump to an address in a register or in memory?
+    pub fn is_indirect_jump(&self, target: &TargetInfo) -> bool {
+        let is_label = |a: &String| a.starts_with('.') || a.starts_with('L');
+        if target.is_x86()
+            || target.is_i386()
+            || target.is_i586()
+            || target.is_i686()
+        {
+            self.instr.starts_with("jmp") && !self.args.iter().any(is_label)
+        } else if target.is_aarch64() {
+            self.instr == "br"
+        } else if target.is_arm() {
+            let first = self.args.first().map(String::as_str);
+            (self.instr == "bx" && first != Some("lr")) || first == Some("pc")
+        } else {
+            false
+        }
+    }
     pub fn is_call(&self, target: &TargetInfo) -> bool {
         if target.is_x86()
             || target.is_i386()
@@ -336,6 +895,69 @@ impl Instruction {
         }
     }
 
+    /// Targets of the jump table of the instruction, with the indices of
+    /// their entries.
+    pub fn jump_table(&self) -> Vec<(&str, Vec<usize>)> {
+        let mut targets: Vec<(&str, Vec<usize>)> = Vec::new();
+        for (idx, t) in self.jump_targets.iter().enumerate() {
+            match targets.iter_mut().find(|(l, _)| *l == t) {
+                Some((_, entries)) => entries.push(idx),
+                None => targets.push((t, vec![idx])),
+            }
+        }
+        targets
+    }
+
+    /// Bytes that the instruction allocates on the stack, e.g., 24 for
+    /// `sub rsp, 24` or 8 for `push rbx` on x86_64.
+    pub fn stack_allocation(&self, target: &TargetInfo) -> u64 {
+        // Immediate operand, e.g., `24`, `$24` (AT&T), or `#-16]!` (ARM):
+        let immediate = |arg: &str| {
+            let arg = arg.trim_start_matches(&['$', '#'][..]);
+            let arg = arg.trim_end_matches(&[']', '!'][..]);
+            parse_int(arg).map(|v| v.unsigned_abs() as u64)
+        };
+        let args = self.args.iter().map(String::as_str).collect::<Vec<_>>();
+        if target.is_intel() {
+            let word = if target.is_x86() { 8 } else { 4 };
+            let sp = |arg: &str| {
+                arg.trim_start_matches('%') == "rsp"
+                    || arg.trim_start_matches('%') == "esp"
+            };
+            if self.instr.starts_with("push") {
+                return word;
+            }
+            if self.instr.starts_with("sub") && args.len() == 2 {
+                // Intel `sub rsp, 24` or AT&T `subq $24, %rsp`:
+                let (dst, src) = if args[0].starts_with('$') {
+                    (args[1], args[0])
+                } else {
+                    (args[0], args[1])
+                };
+                if sp(dst) {
+                    return immediate(src).unwrap_or(0);
+                }
+            }
+        } else if target.is_aarch64() || target.is_arm() {
+            match (self.instr.as_str(), &args[..]) {
+                ("sub", ["sp", "sp", n]) => return immediate(n).unwrap_or(0),
+                ("sub", ["sp", "sp", n, "lsl", s]) => {
+                    let shift = immediate(s).unwrap_or(0);
+                    return immediate(n).unwrap_or(0) << shift;
+                }
+                // Pre-indexed stores, e.g., `stp x29, x30, [sp, #-16]!`:
+                ("stp", [.., "[sp", n]) | ("str", [_, "[sp", n])
+                    if n.ends_with('!') && n.contains('-') =>
+                {
+                    return immediate(n).unwrap_or(0);
+                }
+                ("push", regs) => return 4 * regs.len() as u64,
+                _ => {}
+            }
+        }
+        0
+    }
+
     fn demangle_args(&mut self, target: &TargetInfo) {
         if target.is_mips() {
             // On mips we need to inspect every argument of every instruction.
@@ -358,9 +980,9 @@ impl Instruction {
         } else if self.is_call(&target) {
             // Typically, we just check if the instruction is a call
             // instruction, and the mangle the first argument.
-            let demangled_function =
-                crate::demangle::demangle(&self.args[0], &target);
-            self.args[0] = demangled_function;
+            if let Some(arg) = self.args.first_mut() {
+                *arg = crate::demangle::demangle(arg, target);
+            }
         }
     }
 
@@ -396,4 +1018,88 @@ mod tests {
         super::replace_slashes(&mut windows_path);
         assert_eq!(windows_path_norm, windows_path);
     }
+
+    #[test]
+    fn dwarf5_file() {
+        use std::path::PathBuf;
+        let target = crate::target::TargetInfo::new_from_triple(
+            "x86_64-unknown-linux-gnu".to_string(),
+        );
+        let f = |s: &str| super::File::new(s, &target).unwrap();
+
+        assert!(f(".file \"lib_crate.1234-cgu.0\"").is_none());
+
+        let file = f(".file 2 \"src/lib.rs\"").unwrap();
+        assert_eq!((file.index, file.path), (2, PathBuf::from("src/lib.rs")));
+
+        let file =
+            f(".file 0 \"/comp/dir\" \"src/lib.rs\" md5 0xB06A9E5D0E1F39E0")
+                .unwrap();
+        assert_eq!(file.index, 0);
+        assert_eq!(file.path, PathBuf::from("/comp/dir/src/lib.rs"));
+        assert_eq!(file.md5, Some("b06a9e5d0e1f39e0".to_string()));
+        assert!(file.source.is_none());
+
+        let file = f(
+            ".file 1 \"/comp/dir\" \"/abs/bar.rs\" source \"a\\n\\\"b\\\"\"",
+        )
+        .unwrap();
+        assert_eq!(file.path, PathBuf::from("/abs/bar.rs"));
+        assert_eq!(file.source, Some("a\n\"b\"".to_string()));
+    }
+
+    #[test]
+    fn malformed_loc() {
+        let target = crate::target::TargetInfo::new_from_triple(
+            "x86_64-unknown-linux-gnu".to_string(),
+        );
+        assert!(super::Loc::new(".loc 1", &target).is_err());
+        assert!(super::Loc::new(".loc a 2 3", &target).is_err());
+        let loc = super::Loc::new(".loc 1 2 3", &target).unwrap().unwrap();
+        assert_eq!(
+            (loc.file_index, loc.file_line, loc.file_column),
+            (1, 2, 3)
+        );
+    }
+
+    #[test]
+    fn data() {
+        use super::{Data, Value};
+        assert_eq!(
+            Value::parse(".ascii \"x \\303\\251\\n\""),
+            Some(vec![Value::Bytes("x é\n".as_bytes().to_vec())])
+        );
+        assert_eq!(
+            Value::parse(".quad -1, .L__unnamed_1"),
+            Some(vec![
+                Value::Int {
+                    size: 8,
+                    value: u64::MAX
+                },
+                Value::Symbol {
+                    size: 8,
+                    name: ".L__unnamed_1".to_string()
+                }
+            ])
+        );
+        assert_eq!(Value::parse(".p2align 4"), None);
+
+        let data = |section: &str, directives: &[&str]| Data {
+            id: ".LCPI0_0".to_string(),
+            section: section.to_string(),
+            values: directives
+                .iter()
+                .flat_map(|d| Value::parse(d).unwrap())
+                .collect(),
+        };
+        assert_eq!(data(".rodata", &[".asciz \"foo\""]).describe(), "\"foo\"");
+        assert_eq!(
+            data(".rodata.cst8", &[".quad 0x3ff8000000000000"]).describe(),
+            "1.5"
+        );
+        assert_eq!(
+            data(".rodata", &[".long 1", ".long -2"]).describe(),
+            "[1, -2]"
+        );
+    }
 }
@@ -0,0 +1,108 @@
+//! Budgets of the instructions, calls, and stack of a function.
+
+use super::ast::{Function, Statement};
+use crate::target::TargetInfo;
+
+/// Resources used by a function.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Usage {
+    pub instructions: usize,
+    pub calls: usize,
+    /// Bytes allocated on the stack by the function (estimated from the
+    /// pushes and the stack pointer adjustments).
+    pub stack: u64,
+}
+
+impl Usage {
+    pub fn new(function: &Function, target: &TargetInfo) -> Self {
+        let mut usage = Self {
+            instructions: 0,
+            calls: 0,
+            stack: 0,
+        };
+        for s in &function.statements {
+            if let Statement::Instruction(i) = s {
+                usage.instructions += 1;
+                usage.calls += usize::from(i.is_call(target));
+                usage.stack += i.stack_allocation(target);
+            }
+        }
+        usage
+    }
+}
+
+/// Budgets that the `usage` exceeds, and by how much.
+pub fn exceeded(
+    usage: Usage,
+    max_instructions: Option<usize>,
+    max_calls: Option<usize>,
+    max_stack: Option<u64>,
+) -> Vec<String> {
+    let budgets = [
+        (
+            "instructions",
+            usage.instructions as u64,
+            max_instructions.map(|m| m as u64),
+        ),
+        ("calls", usage.calls as u64, max_calls.map(|m| m as u64)),
+        ("stack bytes", usage.stack, max_stack),
+    ];
+    budgets
+        .iter()
+        .filter_map(|&(name, used, max)| {
+            let max = max?;
+            if used <= max {
+                return None;
+            }
+            Some(format!(
+                "{}: {} (budget {}, exceeded by {})",
+                name,
+                used,
+                max,
+                used - max
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::ast::Instruction;
+
+    #[test]
+    fn stack_and_budgets() {
+        let x86 = TargetInfo::new_from_triple(
+            "x86_64-unknown-linux-gnu".to_string(),
+        );
+        let aarch64 = TargetInfo::new_from_triple(
+            "aarch64-unknown-linux-gnu".to_string(),
+        );
+        let stack = |s: &str, target: &TargetInfo| {
+            Instruction::new(s, None, target)
+                .unwrap()
+                .stack_allocation(target)
+        };
+        assert_eq!(stack("push rbx", &x86), 8);
+        assert_eq!(stack("sub rsp, 40", &x86), 40);
+        assert_eq!(stack("subq $0x28, %rsp", &x86), 40);
+        assert_eq!(stack("sub rax, 40", &x86), 0);
+        assert_eq!(stack("stp x29, x30, [sp, #-32]!", &aarch64), 32);
+        assert_eq!(stack("stp x29, x30, [sp, #16]", &aarch64), 0);
+        assert_eq!(stack("sub sp, sp, #1, lsl #12", &aarch64), 4096);
+
+        let usage = Usage {
+            instructions: 10,
+            calls: 2,
+            stack: 48,
+        };
+        assert!(exceeded(usage, Some(10), Some(2), Some(48)).is_empty());
+        assert_eq!(
+            exceeded(usage, Some(8), None, Some(32)),
+            [
+                "instructions: 10 (budget 8, exceeded by 2)",
+                "stack bytes: 48 (budget 32, exceeded by 16)"
+            ]
+        );
+    }
+}
@@ -0,0 +1,1151 @@
+//! Functions inlined into other functions, read from the DWARF debugging
+//! information that the assembly emits as data in its `.debug_*` sections.
+//!
+//! The values computed by the assembler, like addresses, are kept as
+//! expressions of labels, e.g., `.Ltmp3-.Lfunc_begin0`, so the ranges of the
+//! inlined functions are ranges of labels of the assembly.
+
+use super::ast::{Function, Loc, Statement};
+use crate::error::Error;
+use crate::target::TargetInfo;
+use std::collections::HashMap;
+
+/// Function inlined into another function.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Inlined {
+    /// Demangled name of the inlined function.
+    pub function: String,
+    /// Number of inlined functions that this one is inlined into.
+    pub depth: usize,
+    /// Ranges of the inlined code, from a label to another label.
+    pub ranges: Vec<(String, String)>,
+}
+
+/// Debugging information of an assembly file.
+#[derive(Default, Debug)]
+pub struct Info {
+    pub inlined: Vec<Inlined>,
+    /// Declarations of the functions, by their linkage name, e.g.,
+    /// `_ZN1a1f17h0123456789abcdefE`.
+    pub declarations: HashMap<String, Loc>,
+}
+
+/// Data of a section.
+#[derive(Debug)]
+enum Piece {
+    Byte(u8),
+    /// Value of a size in bytes computed by the assembler.
+    Expr(usize, String),
+    /// ULEB128 or SLEB128 value computed by the assembler.
+    Leb(String),
+}
+
+#[derive(Default, Debug)]
+struct Section {
+    pieces: Vec<Piece>,
+    /// Index of the piece that follows each label.
+    labels: HashMap<String, usize>,
+    /// Offset in bytes of each piece.
+    offsets: Vec<usize>,
+}
+
+impl Section {
+    fn push(&mut self, piece: Piece) {
+        let offset = match self.pieces.last() {
+            Some(Piece::Expr(size, _)) => self.offsets.last().unwrap() + size,
+            Some(_) => self.offsets.last().unwrap() + 1,
+            None => 0,
+        };
+        self.offsets.push(offset);
+        self.pieces.push(piece);
+    }
+
+    fn push_int(&mut self, value: i128, size: usize) {
+        for i in 0..size {
+            self.push(Piece::Byte((value >> (8 * i)) as u8));
+        }
+    }
+
+    fn push_leb(&mut self, mut value: i128, signed: bool) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            let done = if signed {
+                (value == 0 && byte & 0x40 == 0)
+                    || (value == -1 && byte & 0x40 != 0)
+            } else {
+                value == 0
+            };
+            if done {
+                self.push(Piece::Byte(byte));
+                return;
+            }
+            self.push(Piece::Byte(byte | 0x80));
+        }
+    }
+
+    /// Index of the piece at `offset` bytes.
+    fn at_offset(&self, offset: usize) -> Option<usize> {
+        self.offsets.binary_search(&offset).ok()
+    }
+
+    /// Index of the piece at the label of the expression `expr`.
+    fn at_label(&self, expr: &str) -> Option<usize> {
+        if expr.starts_with(".debug_") {
+            // The start of the section:
+            return Some(0);
+        }
+        self.labels.get(label(expr)).copied()
+    }
+
+    /// Index of the piece of the `value`, a label or an offset.
+    fn at(&self, value: &Value) -> Option<usize> {
+        match value {
+            Value::Num(n) => self.at_offset(*n as usize),
+            Value::Expr(e) => self.at_label(e),
+            Value::Str(_) => None,
+        }
+    }
+}
+
+/// First label of the expression `expr`, e.g., `.Ltmp3` for
+/// `.Ltmp3-.Lfunc_begin0`.
+fn label(expr: &str) -> &str {
+    expr.split(['-', '+']).next().unwrap_or("").trim()
+}
+
+/// Unescapes the contents of the string literal that starts `s`.
+fn unescape(s: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut chars = s.trim_start().trim_start_matches('"').bytes().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            b'"' => break,
+            b'\\' => match chars.next() {
+                Some(b'n') => bytes.push(b'\n'),
+                Some(b't') => bytes.push(b'\t'),
+                Some(b'r') => bytes.push(b'\r'),
+                Some(b'b') => bytes.push(8),
+                Some(b'f') => bytes.push(12),
+                Some(b'x') => {
+                    let mut v = 0_u8;
+                    while let Some(d) =
+                        chars.peek().and_then(|&d| (d as char).to_digit(16))
+                    {
+                        v = v.wrapping_mul(16).wrapping_add(d as u8);
+                        chars.next();
+                    }
+                    bytes.push(v);
+                }
+                Some(d @ b'0'..=b'7') => {
+                    let mut v = d - b'0';
+                    for _ in 0..2 {
+                        match chars.peek() {
+                            Some(&d @ b'0'..=b'7') => {
+                                v = v.wrapping_mul(8).wrapping_add(d - b'0');
+                                chars.next();
+                            }
+                            _ => break,
+                        }
+                    }
+                    bytes.push(v);
+                }
+                Some(c) => bytes.push(c),
+                None => break,
+            },
+            c => bytes.push(c),
+        }
+    }
+    bytes
+}
+
+/// The `.debug_*` sections of the assembly `asm`.
+fn sections(asm: &str, target: &TargetInfo) -> HashMap<String, Section> {
+    let mut sections = HashMap::<String, Section>::new();
+    let mut current: Option<String> = None;
+    for line in asm.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix(".section") {
+            let name = rest
+                .trim()
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .next()
+                .unwrap_or("");
+            current =
+                Some(name.to_string()).filter(|n| n.starts_with(".debug_"));
+            continue;
+        }
+        if [".text", ".data", ".bss"].contains(&line) {
+            current = None;
+            continue;
+        }
+        let section = match current {
+            Some(ref name) => sections.entry(name.clone()).or_default(),
+            None => continue,
+        };
+
+        let (directive, args) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+        if let Some(label) = directive.strip_suffix(':') {
+            section
+                .labels
+                .insert(label.to_string(), section.pieces.len());
+            continue;
+        }
+        if let ".ascii" | ".asciz" | ".string" = directive {
+            for b in unescape(args) {
+                section.push(Piece::Byte(b));
+            }
+            if directive != ".ascii" {
+                section.push(Piece::Byte(0));
+            }
+            continue;
+        }
+        let args = target
+            .comment_delimiters()
+            .iter()
+            .fold(args, |args, d| args.split(d).next().unwrap_or(""));
+        let size = match directive {
+            ".byte" => 1,
+            ".short" | ".hword" | ".2byte" => 2,
+            ".long" | ".int" | ".4byte" => 4,
+            ".quad" | ".8byte" => 8,
+            ".uleb128" | ".sleb128" => {
+                match super::ast::parse_int(args.trim()) {
+                    Some(v) => section.push_leb(v, directive == ".sleb128"),
+                    None => section.push(Piece::Leb(args.trim().to_string())),
+                }
+                continue;
+            }
+            ".zero" | ".space" => {
+                let n = super::ast::parse_int(args.trim()).unwrap_or(0);
+                section.push_int(0, n as usize);
+                continue;
+            }
+            _ => continue,
+        };
+        for arg in args.split(',').map(str::trim) {
+            match super::ast::parse_int(arg) {
+                Some(v) => section.push_int(v, size),
+                None => section.push(Piece::Expr(size, arg.to_string())),
+            }
+        }
+    }
+    sections
+}
+
+/// Value of an attribute.
+#[derive(Clone, PartialEq, Debug)]
+enum Value {
+    Num(u64),
+    /// Expression computed by the assembler.
+    Expr(String),
+    Str(String),
+}
+
+impl Value {
+    fn num(&self) -> Option<u64> {
+        match self {
+            Value::Num(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the data of a section.
+struct Reader<'a> {
+    section: &'a Section,
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        match self.section.pieces.get(self.pos)? {
+            Piece::Byte(b) => {
+                self.pos += 1;
+                Some(*b)
+            }
+            _ => None,
+        }
+    }
+
+    /// Reads a little-endian value of `size` bytes.
+    fn value(&mut self, size: usize) -> Option<Value> {
+        if let Some(Piece::Expr(s, e)) = self.section.pieces.get(self.pos) {
+            if *s != size {
+                return None;
+            }
+            self.pos += 1;
+            return Some(Value::Expr(e.clone()));
+        }
+        let mut v = 0_u64;
+        for i in 0..size {
+            v |= u64::from(self.byte()?) << (8 * i);
+        }
+        Some(Value::Num(v))
+    }
+
+    fn uleb(&mut self) -> Option<Value> {
+        if let Some(Piece::Leb(e)) = self.section.pieces.get(self.pos) {
+            self.pos += 1;
+            return Some(Value::Expr(e.clone()));
+        }
+        let mut v = 0_u64;
+        let mut shift = 0;
+        loop {
+            let b = self.byte()?;
+            if shift < 64 {
+                v |= u64::from(b & 0x7f) << shift;
+            }
+            shift += 7;
+            if b & 0x80 == 0 {
+                return Some(Value::Num(v));
+            }
+        }
+    }
+
+    fn sleb(&mut self) -> Option<i64> {
+        let mut v = 0_i64;
+        let mut shift = 0;
+        loop {
+            let b = self.byte()?;
+            if shift < 64 {
+                v |= i64::from(b & 0x7f) << shift;
+            }
+            shift += 7;
+            if b & 0x80 == 0 {
+                if shift < 64 && b & 0x40 != 0 {
+                    v |= -1 << shift;
+                }
+                return Some(v);
+            }
+        }
+    }
+
+    fn cstring(&mut self) -> Option<String> {
+        let mut bytes = Vec::new();
+        loop {
+            match self.byte()? {
+                0 => return Some(String::from_utf8_lossy(&bytes).to_string()),
+                b => bytes.push(b),
+            }
+        }
+    }
+
+    fn skip(&mut self, n: u64) -> Option<()> {
+        for _ in 0..n {
+            self.byte()?;
+        }
+        Some(())
+    }
+}
+
+const DW_TAG_INLINED_SUBROUTINE: u64 = 0x1d;
+const DW_TAG_SUBPROGRAM: u64 = 0x2e;
+/// Tags whose names are part of the path of the functions they contain.
+const DW_TAG_SCOPES: [u64; 5] = [
+    0x02, // class_type
+    0x04, // enumeration_type
+    0x13, // structure_type
+    0x17, // union_type
+    0x39, // namespace
+];
+const DW_AT_NAME: u64 = 0x03;
+const DW_AT_LOW_PC: u64 = 0x11;
+const DW_AT_HIGH_PC: u64 = 0x12;
+const DW_AT_ABSTRACT_ORIGIN: u64 = 0x31;
+const DW_AT_DECL_FILE: u64 = 0x3a;
+const DW_AT_DECL_LINE: u64 = 0x3b;
+const DW_AT_SPECIFICATION: u64 = 0x47;
+const DW_AT_RANGES: u64 = 0x55;
+const DW_AT_LINKAGE_NAME: u64 = 0x6e;
+const DW_AT_MIPS_LINKAGE_NAME: u64 = 0x2007;
+const DW_AT_STR_OFFSETS_BASE: u64 = 0x72;
+const DW_AT_ADDR_BASE: u64 = 0x73;
+const DW_AT_RNGLISTS_BASE: u64 = 0x74;
+const DW_FORM_ADDR: u64 = 0x01;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_STRP: u64 = 0x0e;
+const DW_FORM_REF_ADDR: u64 = 0x10;
+const DW_FORM_INDIRECT: u64 = 0x16;
+const DW_FORM_IMPLICIT_CONST: u64 = 0x21;
+const DW_FORM_RNGLISTX: u64 = 0x23;
+
+/// Abbreviation of a debugging information entry.
+struct Abbrev {
+    tag: u64,
+    children: bool,
+    /// Attributes, forms, and values of the implicit constants.
+    attrs: Vec<(u64, u64, i64)>,
+}
+
+/// Reads the abbreviations at the piece `pos` of `.debug_abbrev`.
+fn abbrevs(section: &Section, pos: usize) -> HashMap<u64, Abbrev> {
+    let mut abbrevs = HashMap::new();
+    let mut r = Reader { section, pos };
+    while let Some(code) = r.uleb().and_then(|c| c.num()) {
+        if code == 0 {
+            break;
+        }
+        let tag = match r.uleb().and_then(|t| t.num()) {
+            Some(tag) => tag,
+            None => break,
+        };
+        let children = r.byte() == Some(1);
+        let mut attrs = Vec::new();
+        loop {
+            let attr = r.uleb().and_then(|a| a.num());
+            let form = r.uleb().and_then(|f| f.num());
+            match (attr, form) {
+                (Some(0), Some(0)) | (None, _) | (_, None) => break,
+                (Some(attr), Some(form)) => {
+                    let value = if form == DW_FORM_IMPLICIT_CONST {
+                        r.sleb().unwrap_or(0)
+                    } else {
+                        0
+                    };
+                    attrs.push((attr, form, value));
+                }
+            }
+        }
+        abbrevs.insert(
+            code,
+            Abbrev {
+                tag,
+                children,
+                attrs,
+            },
+        );
+    }
+    abbrevs
+}
+
+/// Reads the value of an attribute of `form`.
+fn form(
+    r: &mut Reader,
+    form: u64,
+    implicit: i64,
+    addr_size: usize,
+) -> Option<Value> {
+    let size = match form {
+        DW_FORM_ADDR => addr_size,
+        0x0b | 0x0c | 0x11 | 0x25 | 0x29 => 1,
+        0x05 | 0x12 | 0x26 | 0x2a => 2,
+        0x27 | 0x2b => 3,
+        0x06 | DW_FORM_STRP | DW_FORM_REF_ADDR | 0x13 | 0x17 | 0x1c | 0x1d
+        | 0x1f | 0x28 | 0x2c => 4,
+        0x07 | 0x14 | 0x20 => 8,
+        DW_FORM_STRING => return r.cstring().map(Value::Str),
+        0x0d => return r.sleb().map(|v| Value::Num(v as u64)),
+        0x0f | 0x15 | 0x1a | 0x1b | 0x22 | DW_FORM_RNGLISTX => {
+            return r.uleb()
+        }
+        0x19 => return Some(Value::Num(1)),
+        DW_FORM_IMPLICIT_CONST => return Some(Value::Num(implicit as u64)),
+        0x03 | 0x04 | 0x09 | 0x0a | 0x18 | 0x1e => {
+            let len = match form {
+                0x03 => r.value(2)?.num()?,
+                0x04 => r.value(4)?.num()?,
+                0x0a => u64::from(r.byte()?),
+                0x1e => 16,
+                _ => r.uleb()?.num()?,
+            };
+            r.skip(len)?;
+            return Some(Value::Num(0));
+        }
+        DW_FORM_INDIRECT => {
+            let f = r.uleb()?.num()?;
+            return self::form(r, f, implicit, addr_size);
+        }
+        _ => return None,
+    };
+    r.value(size)
+}
+
+/// Debugging information entry.
+#[derive(Default)]
+struct Die {
+    name: Option<(u64, Value)>,
+    linkage_name: Option<(u64, Value)>,
+    /// Offset of the entry of the abstract origin or the specification.
+    origin: Option<usize>,
+    low_pc: Option<(u64, Value)>,
+    high_pc: Option<(u64, Value)>,
+    ranges: Option<(u64, Value)>,
+    decl_file: Option<u64>,
+    decl_line: Option<u64>,
+}
+
+/// Reads the DWARF sections and returns the inlined functions.
+struct Dwarf<'a> {
+    sections: &'a HashMap<String, Section>,
+    target: &'a TargetInfo,
+    version: u64,
+    addr_size: usize,
+    /// Labels of the bases of the string offsets, the addresses, and the
+    /// range lists of the unit.
+    str_offsets_base: Option<String>,
+    addr_base: Option<String>,
+    rnglists_base: Option<String>,
+}
+
+impl<'a> Dwarf<'a> {
+    fn section(&self, name: &str) -> Option<&'a Section> {
+        self.sections.get(name)
+    }
+
+    /// Reads the `index`-th entry of `size` bytes after the `base` label
+    /// of the section `name`.
+    fn entry(
+        &self,
+        name: &str,
+        base: &Option<String>,
+        default_base: &str,
+        index: u64,
+        size: usize,
+    ) -> Option<Value> {
+        let section = self.section(name)?;
+        let pos = match base {
+            Some(base) => section.at_label(base)?,
+            None => {
+                let (_, &pos) = section
+                    .labels
+                    .iter()
+                    .filter(|(l, _)| l.starts_with(default_base))
+                    .min_by_key(|(_, &pos)| pos)?;
+                pos
+            }
+        };
+        let mut r = Reader { section, pos };
+        for _ in 0..index {
+            r.value(size)?;
+        }
+        r.value(size)
+    }
+
+    fn string(&self, (form, value): &(u64, Value)) -> Option<String> {
+        let value = match (*form, value) {
+            (_, Value::Str(s)) => return Some(s.clone()),
+            (DW_FORM_STRP, v) => v.clone(),
+            // strx, strx1-4:
+            (0x1a, v) | (0x25..=0x28, v) => self.entry(
+                ".debug_str_offsets",
+                &self.str_offsets_base,
+                ".Lstr_offsets_base",
+                v.num()?,
+                4,
+            )?,
+            _ => return None,
+        };
+        let section = self.section(".debug_str")?;
+        Reader {
+            section,
+            pos: section.at(&value)?,
+        }
+        .cstring()
+    }
+
+    /// Label of an address.
+    fn address(&self, (form, value): &(u64, Value)) -> Option<String> {
+        let value = match *form {
+            DW_FORM_ADDR => value.clone(),
+            // addrx, addrx1-4:
+            0x1b | 0x29..=0x2c => self.entry(
+                ".debug_addr",
+                &self.addr_base,
+                ".Laddr_table_base",
+                value.num()?,
+                self.addr_size,
+            )?,
+            _ => return None,
+        };
+        match value {
+            Value::Expr(e) => Some(label(&e).to_string()),
+            _ => None,
+        }
+    }
+
+    /// Ranges of labels of the entry `die`.
+    fn ranges(&self, die: &Die) -> Vec<(String, String)> {
+        if let (Some(low), Some(high)) = (&die.low_pc, &die.high_pc) {
+            let start = self.address(low);
+            // The high PC is either an address or an offset from the low
+            // PC, e.g., `.Ltmp7-.Ltmp5`:
+            let end = match high {
+                (DW_FORM_ADDR, _) => self.address(high),
+                (_, Value::Expr(e)) => Some(label(e).to_string()),
+                _ => None,
+            };
+            return start.into_iter().zip(end).collect();
+        }
+        let (form, value) = match die.ranges {
+            Some(ref ranges) => ranges,
+            None => return Vec::new(),
+        };
+        if self.version < 5 {
+            return self.range_list(value).unwrap_or_default();
+        }
+        let value = if *form == DW_FORM_RNGLISTX {
+            match self.entry(
+                ".debug_rnglists",
+                &self.rnglists_base,
+                ".Lrnglists_table_base",
+                value.num().unwrap_or(u64::MAX),
+                4,
+            ) {
+                Some(v) => v,
+                None => return Vec::new(),
+            }
+        } else {
+            value.clone()
+        };
+        self.rnglist(&value).unwrap_or_default()
+    }
+
+    /// Reads a range list of `.debug_ranges` (DWARF 4).
+    fn range_list(&self, at: &Value) -> Option<Vec<(String, String)>> {
+        let section = self.section(".debug_ranges")?;
+        let mut r = Reader {
+            section,
+            pos: section.at(at)?,
+        };
+        let mut ranges = Vec::new();
+        loop {
+            let start = r.value(self.addr_size)?;
+            let end = r.value(self.addr_size)?;
+            match (start, end) {
+                (Value::Num(0), Value::Num(0)) => return Some(ranges),
+                (Value::Expr(s), Value::Expr(e)) => {
+                    ranges.push((label(&s).to_string(), label(&e).to_string()))
+                }
+                // Base address selection:
+                _ => {}
+            }
+        }
+    }
+
+    /// Reads a range list of `.debug_rnglists` (DWARF 5).
+    fn rnglist(&self, at: &Value) -> Option<Vec<(String, String)>> {
+        let section = self.section(".debug_rnglists")?;
+        let mut r = Reader {
+            section,
+            pos: section.at(at)?,
+        };
+        let mut ranges = Vec::new();
+        let mut push = |s: Value, e: Value| {
+            if let (Value::Expr(s), Value::Expr(e)) = (s, e) {
+                ranges.push((label(&s).to_string(), label(&e).to_string()));
+            }
+        };
+        loop {
+            match r.byte()? {
+                // DW_RLE_end_of_list:
+                0 => return Some(ranges),
+                // DW_RLE_base_addressx:
+                1 => {
+                    r.uleb()?;
+                }
+                // DW_RLE_startx_endx, DW_RLE_startx_length:
+                2 | 3 => {
+                    r.uleb()?;
+                    r.uleb()?;
+                }
+                // DW_RLE_offset_pair:
+                4 => {
+                    let s = r.uleb()?;
+                    push(s, r.uleb()?);
+                }
+                // DW_RLE_base_address:
+                5 => {
+                    r.value(self.addr_size)?;
+                }
+                // DW_RLE_start_end:
+                6 => {
+                    let s = r.value(self.addr_size)?;
+                    push(s, r.value(self.addr_size)?);
+                }
+                // DW_RLE_start_length:
+                7 => {
+                    let s = r.value(self.addr_size)?;
+                    push(s, r.uleb()?);
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// Reads the units of `.debug_info` and returns the inlined functions
+    /// and the declarations of the functions.
+    fn info(&mut self) -> Option<Info> {
+        let info = self.section(".debug_info")?;
+        let abbrev_section = self.section(".debug_abbrev")?;
+
+        // Names of the entries by offset, and the entries that refer to
+        // the entries that have the names:
+        let mut names = HashMap::<usize, String>::new();
+        let mut origins = HashMap::<usize, usize>::new();
+        // Abstract origins, depths, and ranges of the inlined functions:
+        let mut inlined = Vec::<(usize, usize, Vec<(String, String)>)>::new();
+        // Linkage names, files, and lines of the entries that have them,
+        // and the entries of the functions:
+        let mut linkage_names = HashMap::<usize, String>::new();
+        let mut decls = HashMap::<usize, (u64, u64)>::new();
+        let mut functions = Vec::<usize>::new();
+
+        let mut r = Reader {
+            section: info,
+            pos: 0,
+        };
+        while r.pos < info.pieces.len() {
+            let unit = r.pos;
+            let unit_offset = info.offsets[unit];
+            let end = match r.value(4)? {
+                Value::Expr(e) => info.labels.get(label(&e)).copied()?,
+                Value::Num(n) => info
+                    .at_offset(unit_offset + 4 + n as usize)
+                    .unwrap_or(info.pieces.len()),
+                Value::Str(_) => return None,
+            };
+            self.version = r.value(2)?.num()?;
+            let abbrev_offset = if self.version >= 5 {
+                let unit_type = r.byte()?;
+                self.addr_size = usize::from(r.byte()?);
+                let abbrev_offset = r.value(4)?;
+                // Only compile units:
+                if unit_type != 1 {
+                    r.pos = end;
+                    continue;
+                }
+                abbrev_offset
+            } else {
+                let abbrev_offset = r.value(4)?;
+                self.addr_size = usize::from(r.byte()?);
+                abbrev_offset
+            };
+            let abbrevs =
+                abbrevs(abbrev_section, abbrev_section.at(&abbrev_offset)?);
+
+            // Names of the scopes and number of inlined functions of the
+            // entries with children:
+            let mut stack = Vec::<(Option<String>, usize)>::new();
+            while r.pos < end {
+                let offset = info.offsets[r.pos];
+                let code = r.uleb()?.num()?;
+                if code == 0 {
+                    stack.pop();
+                    continue;
+                }
+                let abbrev = abbrevs.get(&code)?;
+                let mut die = Die::default();
+                for &(attr, f, implicit) in &abbrev.attrs {
+                    let value = form(&mut r, f, implicit, self.addr_size)?;
+                    let reference = || match (f, &value) {
+                        (DW_FORM_REF_ADDR, Value::Num(n)) => Some(*n as usize),
+                        (0x11..=0x15, Value::Num(n)) => {
+                            Some(unit_offset + *n as usize)
+                        }
+                        _ => None,
+                    };
+                    match attr {
+                        DW_AT_NAME => die.name = Some((f, value)),
+                        DW_AT_LINKAGE_NAME | DW_AT_MIPS_LINKAGE_NAME => {
+                            die.linkage_name = Some((f, value))
+                        }
+                        DW_AT_ABSTRACT_ORIGIN | DW_AT_SPECIFICATION => {
+                            die.origin = reference()
+                        }
+                        DW_AT_LOW_PC => die.low_pc = Some((f, value)),
+                        DW_AT_HIGH_PC => die.high_pc = Some((f, value)),
+                        DW_AT_RANGES => die.ranges = Some((f, value)),
+                        DW_AT_DECL_FILE => die.decl_file = value.num(),
+                        DW_AT_DECL_LINE => die.decl_line = value.num(),
+                        DW_AT_STR_OFFSETS_BASE => {
+                            self.str_offsets_base = expr(value)
+                        }
+                        DW_AT_ADDR_BASE => self.addr_base = expr(value),
+                        DW_AT_RNGLISTS_BASE => {
+                            self.rnglists_base = expr(value)
+                        }
+                        _ => {}
+                    }
+                }
+
+                let name = die.name.as_ref().and_then(|n| self.string(n));
+                let depth = stack.last().map_or(0, |&(_, depth)| depth);
+                if let Some(linkage_name) =
+                    die.linkage_name.as_ref().and_then(|n| self.string(n))
+                {
+                    names.insert(
+                        offset,
+                        crate::demangle::demangle(&linkage_name, self.target),
+                    );
+                    linkage_names.insert(offset, linkage_name);
+                } else if let Some(ref name) = name {
+                    let mut path = stack
+                        .iter()
+                        .filter_map(|(scope, _)| scope.clone())
+                        .collect::<Vec<_>>();
+                    path.push(name.clone());
+                    names.insert(offset, path.join("::"));
+                }
+                if let Some(origin) = die.origin {
+                    origins.insert(offset, origin);
+                }
+                if let (Some(file), Some(line)) =
+                    (die.decl_file, die.decl_line)
+                {
+                    decls.insert(offset, (file, line));
+                }
+                // Only the functions with code, not their declarations:
+                if abbrev.tag == DW_TAG_SUBPROGRAM
+                    && !self.ranges(&die).is_empty()
+                {
+                    functions.push(offset);
+                }
+                let is_inlined = abbrev.tag == DW_TAG_INLINED_SUBROUTINE;
+                if is_inlined {
+                    if let Some(origin) = die.origin {
+                        inlined.push((origin, depth, self.ranges(&die)));
+                    }
+                }
+                if abbrev.children {
+                    let scope = if DW_TAG_SCOPES.contains(&abbrev.tag) {
+                        name
+                    } else {
+                        None
+                    };
+                    stack.push((scope, depth + usize::from(is_inlined)));
+                }
+            }
+            r.pos = end;
+        }
+
+        // The names and the declaration of an entry might be in its
+        // abstract origin or its specification:
+        fn find<T: Clone>(
+            map: &HashMap<usize, T>,
+            origins: &HashMap<usize, usize>,
+            mut offset: usize,
+        ) -> Option<T> {
+            for _ in 0..8 {
+                if let Some(value) = map.get(&offset) {
+                    return Some(value.clone());
+                }
+                offset = *origins.get(&offset)?;
+            }
+            None
+        }
+        Some(Info {
+            inlined: inlined
+                .into_iter()
+                .filter_map(|(origin, depth, ranges)| {
+                    Some(Inlined {
+                        function: find(&names, &origins, origin)?,
+                        depth,
+                        ranges,
+                    })
+                })
+                .collect(),
+            declarations: functions
+                .into_iter()
+                .filter_map(|offset| {
+                    let linkage_name = find(&linkage_names, &origins, offset)?;
+                    let (file, line) = find(&decls, &origins, offset)?;
+                    let loc = Loc {
+                        file_index: file as usize,
+                        file_line: line as usize,
+                        file_column: 0,
+                    };
+                    Some((linkage_name, loc))
+                })
+                .collect(),
+        })
+    }
+}
+
+fn expr(value: Value) -> Option<String> {
+    match value {
+        Value::Expr(e) => Some(e),
+        _ => None,
+    }
+}
+
+/// Reads the debugging information of the assembly `asm`.
+fn info_in(asm: &str, target: &TargetInfo) -> Info {
+    let sections = sections(asm, target);
+    Dwarf {
+        sections: &sections,
+        target,
+        version: 4,
+        addr_size: 8,
+        str_offsets_base: None,
+        addr_base: None,
+        rnglists_base: None,
+    }
+    .info()
+    .unwrap_or_default()
+}
+
+/// Reads the debugging information of the assembly `file`.
+pub fn info(
+    file: &::std::path::Path,
+    target: &TargetInfo,
+) -> Result<Info, Error> {
+    let asm =
+        ::std::fs::read_to_string(file).map_err(|e| Error::io(file, e))?;
+    Ok(info_in(&asm, target))
+}
+
+/// Declaration of `function`: the file and the line of the function in its
+/// debugging information. Unlike the first `.loc` of the function, which
+/// often belongs to inlined code, this is the function's own source.
+pub fn declaration(function: &Function, info: &Info) -> Option<Loc> {
+    let label = function.label.as_str();
+    // Mach-O symbols have an additional leading underscore:
+    let unprefixed = label.strip_prefix('_').unwrap_or(label);
+    info.declarations
+        .get(label)
+        .or_else(|| info.declarations.get(unprefixed))
+        .copied()
+}
+
+/// Function that each statement of `function` was inlined from, if any:
+/// the innermost inlined function whose ranges contain the statement.
+pub fn origins(
+    function: &Function,
+    inlined: &[Inlined],
+) -> Vec<Option<String>> {
+    let mut starts = HashMap::<&str, Vec<usize>>::new();
+    let mut ends = HashMap::<&str, Vec<usize>>::new();
+    for (i, f) in inlined.iter().enumerate() {
+        for (start, end) in f.ranges.iter().filter(|(s, e)| s != e) {
+            starts.entry(start).or_default().push(i);
+            ends.entry(end).or_default().push(i);
+        }
+    }
+
+    let mut active = Vec::<usize>::new();
+    function
+        .statements
+        .iter()
+        .map(|s| {
+            if let Statement::Label(ref l) = s {
+                for i in ends.get(l.id.as_str()).into_iter().flatten() {
+                    if let Some(j) = active.iter().position(|a| a == i) {
+                        active.remove(j);
+                    }
+                }
+                active.extend(starts.get(l.id.as_str()).into_iter().flatten());
+            }
+            active
+                .iter()
+                .max_by_key(|&&i| inlined[i].depth)
+                .map(|&i| inlined[i].function.clone())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `a::f`, declared in line 3 of `lib.rs`, inlines `a::helper`, which
+    /// inlines `a::helper` in the range of `.Ltmp2` to `.Ltmp3`. Its first
+    /// `.loc` is code inlined from `non_null.rs`.
+    const ASM: &str = r#"
+	.file	1 "/src/lib.rs"
+	.file	2 "/rustc/abc/library/core/src/ptr/non_null.rs"
+	.section	.text._ZN1a1f17h0123456789abcdefE,"ax",@progbits
+	.globl	_ZN1a1f17h0123456789abcdefE
+	.type	_ZN1a1f17h0123456789abcdefE,@function
+_ZN1a1f17h0123456789abcdefE:
+.Lfunc_begin0:
+	.loc	2 100 5
+	push	rbx
+.Ltmp1:
+	.loc	1 4 5
+	mov	eax, edi
+.Ltmp2:
+	imul	eax, eax
+.Ltmp3:
+	pop	rbx
+.Ltmp4:
+	ret
+.Lfunc_end0:
+	.size	_ZN1a1f17h0123456789abcdefE, .Lfunc_end0-_ZN1a1f17h0123456789abcdefE
+	.section	.debug_abbrev,"",@progbits
+	.byte	1
+	.byte	17
+	.byte	1
+	.byte	0
+	.byte	0
+	.byte	2
+	.byte	57
+	.byte	1
+	.byte	3
+	.byte	8
+	.byte	0
+	.byte	0
+	.byte	3
+	.byte	46
+	.byte	0
+	.byte	3
+	.byte	14
+	.byte	0
+	.byte	0
+	.byte	4
+	.byte	46
+	.byte	1
+	.byte	17
+	.byte	1
+	.byte	18
+	.byte	6
+	.byte	3
+	.byte	8
+	.byte	110
+	.byte	8
+	.byte	58
+	.byte	11
+	.byte	59
+	.byte	11
+	.byte	0
+	.byte	0
+	.byte	5
+	.byte	29
+	.byte	1
+	.byte	49
+	.byte	19
+	.byte	17
+	.byte	1
+	.byte	18
+	.byte	6
+	.byte	0
+	.byte	0
+	.byte	6
+	.byte	29
+	.byte	0
+	.byte	49
+	.byte	19
+	.byte	85
+	.byte	23
+	.byte	0
+	.byte	0
+	.byte	0
+	.section	.debug_info,"",@progbits
+.Lcu_begin0:
+	.long	.Ldebug_info_end0-.Ldebug_info_start0
+.Ldebug_info_start0:
+	.short	4
+	.long	.debug_abbrev
+	.byte	8
+	.byte	1
+	.byte	2
+	.asciz	"a"
+	.byte	3
+	.long	.Linfo_string0
+	.byte	4
+	.quad	.Lfunc_begin0
+	.long	.Lfunc_end0-.Lfunc_begin0
+	.asciz	"f"
+	.asciz	"_ZN1a1f17h0123456789abcdefE"
+	.byte	1
+	.byte	3
+	.byte	5
+	.long	15
+	.quad	.Ltmp1
+	.long	.Ltmp4-.Ltmp1
+	.byte	6 # DW_TAG_inlined_subroutine
+	.long	15
+	.long	.Ldebug_ranges0
+	.byte	0
+	.byte	0
+	.byte	0
+	.byte	0
+.Ldebug_info_end0:
+	.section	.debug_ranges,"",@progbits
+.Ldebug_ranges0:
+	.quad	.Ltmp2-.Lfunc_begin0
+	.quad	.Ltmp3-.Lfunc_begin0
+	.quad	0
+	.quad	0
+	.section	.debug_str,"MS",@progbits,1
+.Linfo_string0:
+	.asciz	"helper"
+"#;
+
+    #[test]
+    fn inlined_functions() {
+        let target = TargetInfo::new_from_triple(
+            "x86_64-unknown-linux-gnu".to_string(),
+        );
+        let path = ::std::env::temp_dir().join("cargo_asm_dwarf_inlined.s");
+        ::std::fs::write(&path, ASM).unwrap();
+        let inlined = info(&path, &target).unwrap().inlined;
+        let module = crate::asm::parse::module(&path, &target).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+        let range = |s: &str, e: &str| (s.to_string(), e.to_string());
+        assert_eq!(
+            inlined,
+            [
+                Inlined {
+                    function: "a::helper".to_string(),
+                    depth: 0,
+                    ranges: vec![range(".Ltmp1", ".Ltmp4")],
+                },
+                Inlined {
+                    function: "a::helper".to_string(),
+                    depth: 1,
+                    ranges: vec![range(".Ltmp2", ".Ltmp3")],
+                },
+            ]
+        );
+
+        let origins = origins(&module.functions[0], &inlined);
+        let origins = module.functions[0]
+            .statements
+            .iter()
+            .zip(&origins)
+            .filter_map(|(s, o)| match s {
+                Statement::Instruction(i) => {
+                    Some((i.instr.as_str(), o.is_some()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            origins,
+            [
+                ("push", false),
+                ("mov", true),
+                ("imul", true),
+                ("pop", true),
+                ("ret", false)
+            ]
+        );
+    }
+
+    #[test]
+    fn declaration_of_function() {
+        let target = TargetInfo::new_from_triple(
+            "x86_64-unknown-linux-gnu".to_string(),
+        );
+        let path = ::std::env::temp_dir().join("cargo_asm_dwarf_decl.s");
+        ::std::fs::write(&path, ASM).unwrap();
+        let info = info(&path, &target).unwrap();
+        let module = crate::asm::parse::module(&path, &target).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+        let function = &module.functions[0];
+        // The first location is the inlined code, not the function:
+        assert_eq!(function.loc.map(|l| l.file_index), Some(2));
+        assert_eq!(
+            declaration(function, &info),
+            Some(Loc {
+                file_index: 1,
+                file_line: 3,
+                file_column: 0,
+            })
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Call graph of the functions in the assembly files.
+
+use serde_derive::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Output format of the call graph.
+#[derive(Copy, Clone, Debug)]
+pub enum Format {
+    Dot,
+    Json,
+}
+
+impl ::std::str::FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(Format::Dot),
+            "json" => Ok(Format::Json),
+            v => Err(format!(
+                "\"{}\" is not a valid call graph format. Try \"dot\" or \"json\"",
+                v
+            )),
+        }
+    }
+}
+
+/// How a function refers to another function.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeKind {
+    /// Call or tail call.
+    Call,
+    /// Any other reference, e.g., taking the address of the function.
+    Reference,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub kind: EdgeKind,
+}
+
+/// Call graph: the functions are identified by their demangled names.
+#[derive(Clone, Default, Debug, Serialize)]
+pub struct CallGraph {
+    pub nodes: Vec<String>,
+    pub edges: Vec<Edge>,
+}
+
+impl CallGraph {
+    /// Adds the edges from `from` to the functions that it `calls` and
+    /// `references`.
+    pub fn add(
+        &mut self,
+        from: &str,
+        calls: &[String],
+        references: &[String],
+    ) {
+        let edges = calls
+            .iter()
+            .map(|c| (c, EdgeKind::Call))
+            .chain(references.iter().map(|r| (r, EdgeKind::Reference)));
+        for (to, kind) in edges {
+            self.edges.push(Edge {
+                from: from.to_string(),
+                to: to.clone(),
+                kind,
+            });
+        }
+        self.nodes.push(from.to_string());
+    }
+
+    /// Sorts the nodes and edges, removing duplicates, and adds the nodes
+    /// of functions that are only the target of edges.
+    pub fn finish(&mut self) {
+        self.nodes.extend(self.edges.iter().map(|e| e.to.clone()));
+        self.nodes.sort();
+        self.nodes.dedup();
+        self.edges.sort_by(|a, b| {
+            (&a.from, &a.to, a.kind as u8).cmp(&(&b.from, &b.to, b.kind as u8))
+        });
+        self.edges.dedup();
+    }
+
+    /// Functions reachable from `root` in at most `depth` steps (following
+    /// the edges backwards if `reverse`), in breadth-first order with their
+    /// distance to the root. No `depth` is unlimited.
+    pub fn reachable(
+        &self,
+        root: &str,
+        depth: Option<usize>,
+        reverse: bool,
+    ) -> Vec<(String, usize)> {
+        let mut adjacent = HashMap::<&str, Vec<&str>>::new();
+        for e in &self.edges {
+            let (from, to) = if reverse {
+                (e.to.as_str(), e.from.as_str())
+            } else {
+                (e.from.as_str(), e.to.as_str())
+            };
+            adjacent.entry(from).or_default().push(to);
+        }
+        let mut visited = HashSet::new();
+        visited.insert(root);
+        let mut queue = VecDeque::new();
+        queue.push_back((root, 0));
+        let mut result = Vec::new();
+        while let Some((f, d)) = queue.pop_front() {
+            result.push((f.to_string(), d));
+            if depth.is_some_and(|depth| d >= depth) {
+                continue;
+            }
+            for &next in adjacent.get(f).into_iter().flatten() {
+                if visited.insert(next) {
+                    queue.push_back((next, d + 1));
+                }
+            }
+        }
+        result
+    }
+
+    /// Subgraph of the functions reachable from `root` in at most `depth`
+    /// steps, if any.
+    pub fn rooted(&self, root: &str, depth: Option<usize>) -> Self {
+        let reachable = self.reachable(root, depth, false);
+        let nodes = reachable
+            .iter()
+            .map(|(f, _)| f.as_str())
+            .collect::<HashSet<_>>();
+        // Edges out of the functions at the maximum depth are cut:
+        let inner = reachable
+            .iter()
+            .filter(|(_, d)| depth.is_none_or(|depth| *d < depth))
+            .map(|(f, _)| f.as_str())
+            .collect::<HashSet<_>>();
+        let mut graph = Self {
+            nodes: reachable.iter().map(|(f, _)| f.clone()).collect(),
+            edges: self
+                .edges
+                .iter()
+                .filter(|e| {
+                    inner.contains(e.from.as_str())
+                        && nodes.contains(e.to.as_str())
+                })
+                .cloned()
+                .collect(),
+        };
+        graph.finish();
+        graph
+    }
+
+    /// Graphviz representation of the graph.
+    pub fn to_dot(&self) -> String {
+        fn quote(s: &str) -> String {
+            format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+        let mut dot = String::from("digraph \"call graph\" {\n");
+        for n in &self.nodes {
+            dot.push_str(&format!("    {};\n", quote(n)));
+        }
+        for e in &self.edges {
+            let style = match e.kind {
+                EdgeKind::Call => "",
+                EdgeKind::Reference => " [style=dashed]",
+            };
+            dot.push_str(&format!(
+                "    {} -> {}{};\n",
+                quote(&e.from),
+                quote(&e.to),
+                style
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CallGraph;
+
+    #[test]
+    fn call_graph() {
+        let s =
+            |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let mut g = CallGraph::default();
+        g.add("a", &s(&["b", "c"]), &s(&["d"]));
+        g.add("b", &s(&["c"]), &[]);
+        g.add("c", &s(&["e"]), &[]);
+        g.finish();
+        assert_eq!(g.nodes, s(&["a", "b", "c", "d", "e"]));
+
+        let r = g.rooted("b", Some(1));
+        assert_eq!(r.nodes, s(&["b", "c"]));
+        assert_eq!(r.edges.len(), 1);
+
+        let callers = g.reachable("c", None, true);
+        assert_eq!(
+            callers,
+            vec![
+                ("c".to_string(), 0),
+                ("a".to_string(), 1),
+                ("b".to_string(), 1)
+            ]
+        );
+        assert_eq!(g.reachable("c", Some(0), true), [("c".to_string(), 0)]);
+        assert!(g.to_dot().contains("\"a\" -> \"d\" [style=dashed];"));
+    }
+}
@@ -1,5 +1,10 @@
 pub mod ast;
+pub mod budget;
+pub mod dwarf;
+pub mod graph;
 pub mod parse;
+pub mod sizes;
+use crate::error::Error;
 use crate::options::*;
 use crate::target::TargetInfo;
 use log::{debug, error};
@@ -21,65 +26,450 @@ impl ::std::str::FromStr for Style {
     }
 }
 
-fn parse_files(
+/// Stores the index of the `module` parsed from the assembly `file`, and
+/// returns its entries.
+fn store_index(
+    file: &::std::path::Path,
+    module: &ast::Module,
+    target: &TargetInfo,
+) -> Vec<crate::cache::Entry> {
+    // The sizes of the functions are label differences (e.g.
+    // `.Lfunc_end0-foo`) that only the assembler resolves, so they are read
+    // from the object file emitted with the assembly:
+    let bytes = self::sizes::object_sizes(&file.with_extension("o"));
+    let entries = module
+        .functions
+        .iter()
+        .map(|f| {
+            let offset = module.line_offsets[f.lines.start];
+            let (calls, references) =
+                self::parse::references(f, &module.symbols, target);
+            crate::cache::Entry {
+                name: f.id.clone(),
+                offset,
+                len: module.line_offsets[f.lines.end] - offset,
+                line: f.lines.start,
+                calls,
+                references,
+                instructions: f
+                    .statements
+                    .iter()
+                    .filter(|s| matches!(s, ast::Statement::Instruction(_)))
+                    .count(),
+                bytes: bytes.get(&f.label).copied(),
+            }
+        })
+        .collect::<Vec<_>>();
+    if let Some(index) = crate::cache::Index::new(
+        file,
+        target,
+        entries.clone(),
+        module.files.clone(),
+        module.data.clone(),
+    ) {
+        index.store();
+    }
+    entries
+}
+
+/// Parses the assembly file `file` and looks up the function `path` in it.
+///
+/// If the file has been parsed before, the function is read directly using
+/// the index of the file.
+fn parse_file(
+    file: &::std::path::Path,
+    path: Option<&str>,
+    target: &TargetInfo,
+) -> ::std::result::Result<parse::Result, Error> {
+    use self::parse::Result;
+
+    if let Some(index) = crate::cache::Index::load(file, target) {
+        let entry = match path.and_then(|path| index.find(path)) {
+            Some(entry) => entry,
+            None => return Ok(Result::NotFound(index.names())),
+        };
+        let lines = index.read(entry)?;
+        let mut function =
+            self::parse::function_at(file, &lines, entry.line, target)?;
+        self::parse::resolve_file(&mut function, &index.files);
+        self::parse::check_locations(&function, &index.files, file)?;
+        self::parse::resolve_data(&mut function, &index.data);
+        self::parse::resolve_jump_tables(&mut function, &index.data, target);
+        return Ok(Result::Found(Box::new(function), index.files));
+    }
+
+    let module = self::parse::module(file, target)?;
+    store_index(file, &module, target);
+
+    if let Some(path) = path {
+        if let Some(function) = self::parse::function(&module, path, target)? {
+            return Ok(Result::Found(Box::new(function), module.files));
+        }
+    }
+    Ok(Result::NotFound(
+        module.functions.into_iter().map(|f| f.id).collect(),
+    ))
+}
+
+/// Looks up the function `path` in the assembly `files`.
+pub fn parse_files(
     files: &[::std::path::PathBuf],
+    path: Option<&str>,
     target: &TargetInfo,
-) -> parse::Result {
+) -> ::std::result::Result<parse::Result, Error> {
     use self::parse::Result;
+
+    // Parse the files concurrently until the function is found:
+    let results = crate::parallel::map_until(files, |f| {
+        let r = parse_file(f, path, target);
+        let done = match r {
+            Ok(Result::Found(..)) | Err(_) => true,
+            Ok(Result::NotFound(_)) => false,
+        };
+        (r, done)
+    });
+
+    let mut function_table = Vec::<String>::new();
+    for r in results {
+        match r? {
+            Result::Found(function, files) => {
+                return Ok(Result::Found(function, files))
+            }
+            Result::NotFound(mut table) => function_table.append(&mut table),
+        }
+    }
+    function_table.sort();
+    function_table.dedup();
+    Ok(Result::NotFound(function_table))
+}
+
+/// Sets the declaration of the `function` and its file from the debugging
+/// `info`.
+pub fn resolve_declaration(
+    function: &mut ast::Function,
+    info: &dwarf::Info,
+    file_table: &parse::FileTable,
+) {
+    function.decl = self::dwarf::declaration(function, info);
+    if let Some(file) =
+        function.decl.and_then(|d| file_table.get(&d.file_index))
+    {
+        function.file = Some(file.clone());
+    }
+}
+
+/// Prints the `function` using the Rust code of the files in `file_table`,
+/// or adds it to the HTML `report`.
+fn print_function(
+    function: &mut ast::Function,
+    file_table: &parse::FileTable,
+    target: &TargetInfo,
+    report: Option<&mut crate::html::Report>,
+) -> ::std::result::Result<(), Error> {
+    let rust = crate::rust::parse(function, file_table)?;
+
+    // The debugging information locates the function itself, whose first
+    // location is often inlined code, and the functions inlined into it:
+    let info = if opts.inlined()
+        || opts.annotate_source()
+        || opts.std() != crate::display::Foreign::Show
+        || (opts.rust() && opts.layout() == crate::display::Layout::SideBySide)
+    {
+        Some(self::dwarf::info(&function.asm_file, target)?)
+    } else {
+        None
+    };
+    if let Some(ref info) = info {
+        resolve_declaration(function, info, file_table);
+    }
+
+    if opts.json() || opts.debug_mode() {
+        if let Some(s) = crate::display::to_json(function, &rust) {
+            crate::pager::print(format_args!("{}\n", s));
+        } else {
+            error!("failed to emit json output");
+        }
+    }
+
+    if opts.annotate_source() {
+        crate::display::annotate_source(function, rust)?;
+    } else if !opts.json() {
+        let origins = match info {
+            Some(ref info) if opts.inlined() => {
+                Some(self::dwarf::origins(function, &info.inlined))
+            }
+            _ => None,
+        };
+        match report {
+            Some(report) => report.add(function, rust, origins),
+            None => crate::display::print(function, rust, target, origins),
+        }
+    }
+    Ok(())
+}
+
+/// Prints the functions called by `function` recursively up to `depth`, or
+/// adds them to the HTML `report`.
+///
+/// Every function is printed at most once. Callees that are not found in
+/// the assembly `files` (e.g. because they were inlined) are skipped.
+fn print_callees(
+    function: &ast::Function,
+    files: &[::std::path::PathBuf],
+    depth: usize,
+    target: &TargetInfo,
+    mut report: Option<&mut crate::html::Report>,
+) -> ::std::result::Result<(), Error> {
+    let mut printed = vec![function.id.clone()];
+    let mut queue = self::parse::callees(function, target)
+        .into_iter()
+        .map(|c| (c, 1))
+        .collect::<::std::collections::VecDeque<_>>();
+    while let Some((callee, d)) = queue.pop_front() {
+        if printed.contains(&callee)
+            || (!opts.inline_std() && self::parse::is_std(&callee))
+        {
+            continue;
+        }
+        printed.push(callee.clone());
+        let (mut function, file_table) =
+            match parse_files(files, Some(&callee), target)? {
+                self::parse::Result::Found(function, file_table) => {
+                    (function, file_table)
+                }
+                self::parse::Result::NotFound(_) => {
+                    debug!("callee {} not found", callee);
+                    continue;
+                }
+            };
+        if d < depth {
+            queue.extend(
+                self::parse::callees(&function, target)
+                    .into_iter()
+                    .map(|c| (c, d + 1)),
+            );
+        }
+        if report.is_none() {
+            crate::pager::print(format_args!("\n"));
+        }
+        print_function(
+            &mut function,
+            &file_table,
+            target,
+            report.as_deref_mut(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Index entries of all functions in the assembly `files`.
+pub fn index_files(
+    files: &[::std::path::PathBuf],
+    target: &TargetInfo,
+) -> ::std::result::Result<Vec<crate::cache::Entry>, Error> {
+    let results = crate::parallel::map_until(files, |f| {
+        let entries = match crate::cache::Index::load(f, target) {
+            Some(index) => Ok(index.functions),
+            None => self::parse::module(f, target)
+                .map(|module| store_index(f, &module, target)),
+        };
+        (entries, false)
+    });
+    let mut entries = Vec::new();
+    for r in results {
+        entries.append(&mut r?);
+    }
+    Ok(entries)
+}
+
+/// Prints the number of copies and the instructions of the generic
+/// functions whose path starts with the function path, if any.
+fn print_monomorphizations(
+    files: &[::std::path::PathBuf],
+    target: &TargetInfo,
+) -> ::std::result::Result<(), Error> {
+    let mut entries = index_files(files, target)?;
+    if let Some(path) = opts.path() {
+        entries.retain(|e| e.name.starts_with(&path));
+    }
+    let sizes = self::sizes::monomorphizations(&entries);
+    self::sizes::print_monomorphizations(&sizes);
+    Ok(())
+}
+
+/// Builds the call graph of all functions in the assembly `files`.
+fn call_graph(
+    files: &[::std::path::PathBuf],
+    target: &TargetInfo,
+) -> ::std::result::Result<self::graph::CallGraph, Error> {
+    let mut graph = self::graph::CallGraph::default();
+    for e in index_files(files, target)? {
+        graph.add(&e.name, &e.calls, &e.references);
+    }
+    graph.finish();
+    Ok(graph)
+}
+
+/// Prints the sizes of the functions whose path starts with the function
+/// path, if any.
+fn print_sizes(
+    files: &[::std::path::PathBuf],
+    target: &TargetInfo,
+) -> ::std::result::Result<(), Error> {
+    let mut entries = index_files(files, target)?;
+    if let Some(path) = opts.path() {
+        entries.retain(|e| e.name.starts_with(&path));
+    }
+    let mut sizes = self::sizes::group(&entries, opts.group_by());
+    self::sizes::sort(&mut sizes, opts.sort_by());
+    self::sizes::print(&sizes, opts.group_by());
+    Ok(())
+}
+
+/// Prints the call graph, rooted at the function `path` if any.
+fn print_call_graph(
+    files: &[::std::path::PathBuf],
+    format: self::graph::Format,
+    target: &TargetInfo,
+) -> ::std::result::Result<(), Error> {
+    let mut graph = call_graph(files, target)?;
+    if let Some(path) = opts.path() {
+        if !graph.nodes.contains(&path) {
+            crate::display::write_error(&format!(
+                "could not find function at path \"{}\" in the call graph.\n",
+                path
+            ));
+            ::std::process::exit(1);
+        }
+        graph = graph.rooted(&path, opts.depth());
+    }
+    match format {
+        self::graph::Format::Dot => {
+            crate::pager::print(format_args!("{}", graph.to_dot()))
+        }
+        self::graph::Format::Json => {
+            match ::serde_json::to_string_pretty(&graph) {
+                Ok(s) => crate::pager::print(format_args!("{}\n", s)),
+                Err(e) => error!("failed to emit json output: {}", e),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints the functions that call the function `path`, recursively up to
+/// `--depth`, if any.
+fn print_callers(
+    files: &[::std::path::PathBuf],
+    path: &str,
+    target: &TargetInfo,
+) -> ::std::result::Result<(), Error> {
+    let graph = call_graph(files, target)?;
+    let callers = graph.reachable(path, opts.depth(), true);
+    if callers.len() == 1 {
+        crate::display::write_error(&format!(
+            "could not find any function that calls \"{}\".\n",
+            path
+        ));
+        ::std::process::exit(1);
+    }
+    for (f, d) in callers.into_iter().skip(1) {
+        crate::pager::print(format_args!("{}{}\n", "  ".repeat(d - 1), f));
+    }
+    Ok(())
+}
+
+/// In debug mode, dumps the raw assembly of the `files`.
+fn dump_files(
+    files: &[::std::path::PathBuf],
+) -> ::std::result::Result<(), Error> {
     use std::io::BufRead;
     if opts.debug_mode() {
         // In debug mode dump all the raw assembly that we could find.
         for f in files {
             debug!("raw file dump {}:", f.display());
-            let fh = ::std::fs::File::open(f).unwrap();
+            let fh = ::std::fs::File::open(f).map_err(|e| Error::io(f, e))?;
             let file_buf = ::std::io::BufReader::new(&fh);
             for l in file_buf.lines() {
-                debug!("{}", l.unwrap());
+                debug!("{}", l.map_err(|e| Error::io(f, e))?);
             }
         }
     }
-    let mut function_table = Vec::<String>::new();
-    for f in files {
-        assert!(f.exists(), "path does not exist: {}", f.display());
-        match self::parse::function(f.as_path(), &target) {
-            Result::Found(function, files) => {
-                return Result::Found(function, files)
-            }
-            Result::NotFound(table) => {
-                for f in table {
-                    function_table.push(f);
-                }
+    Ok(())
+}
+
+pub fn run(
+    files: &[::std::path::PathBuf],
+    target: &TargetInfo,
+) -> ::std::result::Result<(), Error> {
+    dump_files(files)?;
+
+    if opts.sizes() {
+        return print_sizes(files, target);
+    }
+    if opts.monomorphizations() {
+        return print_monomorphizations(files, target);
+    }
+    if let Some(format) = opts.call_graph() {
+        return print_call_graph(files, format, target);
+    }
+    if opts.callers() {
+        return match opts.path() {
+            Some(path) => print_callers(files, &path, target),
+            None => {
+                crate::display::write_error(
+                    "--callers requires the path of a function.\n",
+                );
+                ::std::process::exit(1);
             }
-        }
+        };
     }
-    function_table.sort();
-    function_table.dedup();
-    Result::NotFound(function_table)
-}
 
-pub fn run(files: &[::std::path::PathBuf], target: &TargetInfo) {
     // Parse the files
-    match parse_files(&files, &target) {
+    match parse_files(files, opts.path().as_deref(), target)? {
         self::parse::Result::Found(mut function, file_table) => {
             // If we found the assembly for the path, we parse the assembly:
-            let rust = crate::rust::parse(&function, &file_table);
-
-            if opts.json() || opts.debug_mode() {
-                if let Some(s) = crate::display::to_json(&function, &rust) {
-                    println!("{}", s);
-                } else {
-                    error!("failed to emit json output");
+            let mut report = match opts.format() {
+                crate::display::Format::Text => None,
+                crate::display::Format::Html => {
+                    Some(crate::html::Report::default())
                 }
+            };
+            print_function(
+                &mut function,
+                &file_table,
+                target,
+                report.as_mut(),
+            )?;
+            if let Some(depth) = opts.depth() {
+                print_callees(
+                    &function,
+                    files,
+                    depth,
+                    target,
+                    report.as_mut(),
+                )?;
             }
-
-            if !opts.json() {
-                crate::display::print(&mut function, rust.clone(), &target);
+            if let Some(report) = report {
+                crate::pager::print(format_args!(
+                    "{}",
+                    report.to_html(target)
+                ));
+            }
+            let exceeded = budget::exceeded(
+                budget::Usage::new(&function, target),
+                opts.max_instructions(),
+                opts.max_calls(),
+                opts.max_stack(),
+            );
+            if !exceeded.is_empty() {
+                return Err(Error::Budget { exceeded });
             }
         }
         self::parse::Result::NotFound(mut table) => match opts.path() {
             None => {
                 for f in table {
-                    println!("{}", f);
+                    crate::pager::print(format_args!("{}\n", f));
                 }
             }
             Some(path) => {
@@ -126,4 +516,5 @@ Tips:
             }
         },
     }
+    Ok(())
 }
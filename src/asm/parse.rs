@@ -1,21 +1,30 @@
 use self::ast::*;
 use super::ast;
-use crate::options::{opts, Ext};
+use crate::error::Error;
 use crate::target::TargetInfo;
 
 use log::{debug, error};
 
-/// Parses the body of a function `path` from the `function_line`
+/// Parses the body of a function `path` from the `function_lines`.
+///
+/// The `function_lines` start at line `first_line` of `file`.
 fn function_body(
     function_lines: Vec<String>,
     path: &str,
+    file: &::std::path::Path,
+    first_line: usize,
     target: &TargetInfo,
-) -> ast::Function {
+) -> ::std::result::Result<ast::Function, Error> {
     let mut function = Function {
         id: path.to_string(),
+        label: String::new(),
+        lines: 0..0,
+        asm_file: file.to_path_buf(),
         file: None,
         loc: None,
+        decl: None,
         statements: Vec::new(),
+        data: Vec::new(),
     };
 
     let mut current_loc: Option<Loc> = None;
@@ -24,303 +33,1046 @@ fn function_body(
     //
     // The first line corresponds to the function path, we skip it since we
     // already know it.
-    for (line_off, line) in function_lines
+    for (line_no, line) in function_lines
         .into_iter()
-        .skip(1)
-        .map(|l| l.trim().to_string())
-        .filter(|l| !l.is_empty())
         .enumerate()
+        .skip(1)
+        .map(|(i, l)| (first_line + i, l.trim().to_string()))
+        .filter(|(_, l)| !l.is_empty())
     {
         debug!("parsing line: {}", line);
 
         // If the line contains a comment, split the line at the comment.
-        let (node_str, comment_str) =
-            if let Some(comment_start) = line.find(';') {
-                debug!(" * contains a comment at: {:?}", comment_start);
-                let (node_str, comment_str) = line.split_at(comment_start);
-                (node_str, comment_str)
-            } else {
-                (line.as_str(), "")
-            };
+        let (node_str, comment_str) = split_comment(&line, target);
 
         // If the line contains a comment, we parse that first:
-        if let Some(comment) = Comment::new(comment_str) {
+        if let Some(comment) = Comment::new(comment_str, target) {
             debug!(" * parsing comment: {:?}", comment);
             function.statements.push(Statement::Comment(comment));
         }
 
-        // Then we parse the AST statements.
-
-        if let Some(directive) = Directive::new(node_str, target) {
-            debug!(" * parsed directive: {:?}", directive);
-
-            // We set the first .file directive we parse as the functions file
-            // path:
-            if function.file.is_none() {
-                if let Some(file) = directive.file() {
-                    debug!(" * file directive: {:?}", file);
-
-                    // If there is a function location already set, we set the
-                    // file only if the file index matches the location. Many
-                    // functions don't have a .file directive set at the
-                    // beginnin (only a location) but they contain .file
-                    // directives in the body when code from other files gets
-                    // inlined:
-                    if let Some(ref loc) = &function.loc {
-                        if loc.file_index == file.index {
+        // Then we parse the AST statements. A line can contain multiple
+        // statements:
+        for node_str in split_statements(node_str, target) {
+            if let Some(directive) = Directive::new(node_str, target)
+                .map_err(|e| e.at(file, line_no))?
+            {
+                debug!(" * parsed directive: {:?}", directive);
+
+                // We set the first .file directive we parse as the functions file
+                // path:
+                if function.file.is_none() {
+                    if let Some(file) = directive.file() {
+                        debug!(" * file directive: {:?}", file);
+
+                        // If there is a function location already set, we set the
+                        // file only if the file index matches the location. Many
+                        // functions don't have a .file directive set at the
+                        // beginnin (only a location) but they contain .file
+                        // directives in the body when code from other files gets
+                        // inlined:
+                        if let Some(ref loc) = &function.loc {
+                            if loc.file_index == file.index {
+                                function.file = Some(file);
+                            }
+                        } else {
+                            // If no location is set, the .file directive likely
+                            // belongs to the function: when a .file directive for
+                            // the function is generated these come before the
+                            // first .loc directive.
                             function.file = Some(file);
                         }
-                    } else {
-                        // If no location is set, the .file directive likely
-                        // belongs to the function: when a .file directive for
-                        // the function is generated these come before the
-                        // first .loc directive.
-                        function.file = Some(file);
                     }
                 }
-            }
 
-            // If we find a .loc directive we parse the loc offset and set its
-            // value as the current one:
-            if let Some(new_loc) = directive.loc() {
-                debug!(" * loc directive: {:?}", new_loc);
+                // If we find a .loc directive we parse the loc offset and set its
+                // value as the current one:
+                if let Some(new_loc) = directive.loc() {
+                    debug!(" * loc directive: {:?}", new_loc);
 
-                current_loc = Some(new_loc);
+                    current_loc = Some(new_loc);
 
-                // The function location is the first location that we find
-                // while parsing the function body:
-                if function.loc.is_none() {
-                    // If there is a function file already set, we check
-                    // that the new location matches the file idx.
-                    if let Some(ref file) = &function.file {
-                        assert_eq!(new_loc.file_index, file.index);
+                    // The function location is the first location that we find
+                    // while parsing the function body:
+                    if function.loc.is_none() {
+                        // If there is a function file already set, we check
+                        // that the new location matches the file idx, otherwise
+                        // the file is the one of the location:
+                        if let Some(ref f) = &function.file {
+                            if new_loc.file_index != f.index {
+                                debug!(
+                                    " * file does not match location: {:?}",
+                                    f
+                                );
+                                function.file = None;
+                            }
+                        }
+                        function.loc = Some(new_loc);
                     }
-                    function.loc = Some(new_loc);
                 }
+                let dir = Statement::Directive(directive);
+                debug!(" * appending directive: {:?}", dir);
+
+                function.statements.push(dir);
+                continue;
             }
-            let dir = Statement::Directive(directive);
-            debug!(" * appending directive: {:?}", dir);
 
-            function.statements.push(dir);
-            continue;
-        }
+            if let Some(label) = Label::new(node_str, current_loc) {
+                debug!(" * parsed label: {:?}", label);
 
-        if let Some(label) = Label::new(node_str, current_loc) {
-            debug!(" * parsed label: {:?}", label);
+                function.statements.push(Statement::Label(label));
+                continue;
+            }
 
-            function.statements.push(Statement::Label(label));
-            continue;
-        }
+            if let Some(instruction) =
+                Instruction::new(node_str, current_loc, target)
+            {
+                debug!(" * parsed instruction: {:?}", instruction);
 
-        if let Some(instruction) =
-            Instruction::new(node_str, current_loc, &target)
-        {
-            debug!(" * parsed instruction: {:?}", instruction);
+                function
+                    .statements
+                    .push(Statement::Instruction(instruction));
+                continue;
+            }
 
-            function
-                .statements
-                .push(Statement::Instruction(instruction));
+            return Err(Error::Parse {
+                file: Some(file.to_path_buf()),
+                line: Some(line_no),
+                text: line.clone(),
+                msg: format!("cannot parse line of function {}", path),
+            });
+        }
+    }
+    Ok(function)
+}
+
+/// Returns the byte index and length of the first of the `patterns` in
+/// `line` that is not within a string literal.
+fn find_unquoted(line: &str, patterns: &[&str]) -> Option<(usize, usize)> {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        if c == '"' {
+            in_string = true;
             continue;
         }
+        if let Some(p) = patterns.iter().find(|p| line[i..].starts_with(*p)) {
+            return Some((i, p.len()));
+        }
+    }
+    None
+}
 
-        panic!(
-            "cannot parse function: {}\n  line off: {}\n{}",
-            path, line_off, line
-        );
+/// Splits `line` into the code and the comment using the comment syntax
+/// of the target.
+pub fn split_comment<'a>(
+    line: &'a str,
+    target: &TargetInfo,
+) -> (&'a str, &'a str) {
+    match find_unquoted(line, target.comment_delimiters()) {
+        Some((i, _)) => (line[..i].trim_end(), &line[i..]),
+        None => (line, ""),
     }
-    function
+}
+
+/// Splits the code of a line into its statements.
+fn split_statements<'a>(line: &'a str, target: &TargetInfo) -> Vec<&'a str> {
+    let separator = [target.statement_separator()];
+    let mut statements = Vec::new();
+    let mut rest = line;
+    while let Some((i, len)) = find_unquoted(rest, &separator) {
+        statements.push(rest[..i].trim());
+        rest = &rest[i + len..];
+    }
+    statements.push(rest.trim());
+    statements.retain(|s| !s.is_empty());
+    statements
 }
 
 /// Result of parsing a function, either a match, or a table of functions in
 /// the file.
 pub enum Result {
-    Found(ast::Function, ::std::collections::HashMap<usize, ast::File>),
+    Found(Box<ast::Function>, FileTable),
     NotFound(Vec<String>),
 }
 
-/// Parses the assembly function at `path` from the file `file`.
-#[allow(clippy::use_debug, clippy::cognitive_complexity)]
-pub fn function(file: &::std::path::Path, target: &TargetInfo) -> Result {
-    use std::{
-        collections::HashMap,
-        fs::File,
-        io::{BufRead, BufReader},
-    };
+/// Table mapping `.file` directive indices to files.
+pub type FileTable = ::std::collections::HashMap<usize, ast::File>;
 
-    let path = if let Some(path) = opts.path() {
-        path
-    } else {
-        "".to_owned()
-    };
+/// Builds the table of all `.file` directives in the assembly `lines` of
+/// `file`.
+///
+/// The `.loc` directives of a function can refer to `.file` directives that
+/// appear anywhere in the file (e.g. after the function, or in the body of
+/// other functions), so the table is built for the whole file before
+/// resolving any location.
+pub fn file_table(
+    lines: &[String],
+    file: &::std::path::Path,
+    target: &TargetInfo,
+) -> ::std::result::Result<FileTable, Error> {
+    let mut table = FileTable::new();
+    for (line_idx, line) in lines.iter().enumerate() {
+        let file_directive = match ast::File::new(line, target)
+            .map_err(|e| e.at(file, line_idx + 1))?
+        {
+            Some(f) => f,
+            None => continue,
+        };
+        debug!("found file directive: {:?}", file_directive);
 
-    let fh = File::open(file).unwrap();
-    let file_buf = BufReader::new(&fh);
+        // If the file directive is already in the table, check that
+        // the paths match:
+        if let Some(f) = table.get(&file_directive.index) {
+            if f.path != file_directive.path {
+                return Err(Error::Parse {
+                    file: Some(file.to_path_buf()),
+                    line: Some(line_idx + 1),
+                    text: line.clone(),
+                    msg: format!(
+                        "the .file directive {} was already defined as {}",
+                        f.index,
+                        f.path.display()
+                    ),
+                });
+            }
+            continue;
+        }
+        table.insert(file_directive.index, file_directive);
+    }
+    Ok(table)
+}
 
-    // We keep here the file ids of the already parsed files:
-    let mut file_directive_table = HashMap::<usize, ast::File>::new();
+/// Reads the lines of the assembly file `file` and the byte offset at which
+/// each line begins.
+///
+/// The offsets contain one more element: the offset of the end of the file.
+pub fn read_lines(
+    file: &::std::path::Path,
+) -> ::std::result::Result<(Vec<String>, Vec<u64>), Error> {
+    use std::io::BufRead;
+    let fh = ::std::fs::File::open(file).map_err(|e| Error::io(file, e))?;
+    let mut file_buf = ::std::io::BufReader::new(&fh);
+    let mut lines = Vec::new();
+    let mut offsets = vec![0];
+    let mut offset = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let len = file_buf
+            .read_line(&mut line)
+            .map_err(|e| Error::io(file, e))?;
+        if len == 0 {
+            break;
+        }
+        offset += len as u64;
+        offsets.push(offset);
+        lines.push(line.trim().to_string());
+    }
+    Ok((lines, offsets))
+}
+
+/// Pattern at the beginning of an assembly label that identifies the label
+/// as a function.
+fn function_label_pattern(target: &TargetInfo) -> &'static str {
+    if target.is_apple() {
+        "__"
+    } else {
+        "_"
+    }
+}
 
-    // This is the AST of the function we are looking for:
-    let mut function: Option<ast::Function> = None;
+/// Pattern that we match to know that we have finished parsing a function.
+fn function_end_pattern(target: &TargetInfo) -> &'static str {
+    if target.is_windows() {
+        ".seh_endproc" // TODO: does this work with panic=abort ?
+    } else {
+        ".cfi_endproc"
+    }
+}
 
-    let mut line_iter = file_buf.lines();
+fn is_function_label(line: &str, target: &TargetInfo) -> bool {
+    line.starts_with(function_label_pattern(target)) && line.ends_with(':')
+}
 
-    let mut function_table = Vec::<String>::new();
+/// Parses the function whose label is `lines[0]`.
+///
+/// The label is the line `first_line` (0-based) of `file`. The function body
+/// ends at the end pattern, or at the beginning of the next function if
+/// there is none.
+pub fn function_at(
+    file: &::std::path::Path,
+    lines: &[String],
+    first_line: usize,
+    target: &TargetInfo,
+) -> ::std::result::Result<ast::Function, Error> {
+    let label = lines
+        .first()
+        .and_then(|l| Label::new(split_comment(l, target).0, None))
+        .ok_or_else(|| {
+            let text = lines.first().cloned().unwrap_or_default();
+            Error::parse(text, "expected a function label")
+                .at(file, first_line + 1)
+        })?;
+    let body = lines[1..]
+        .iter()
+        .take_while(|l| {
+            !l.starts_with(function_end_pattern(target))
+                && !is_function_label(split_comment(l, target).0, target)
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    let body_len = body.len();
+    // The end pattern is part of the function:
+    let end = match lines.get(1 + body_len) {
+        Some(l) if l.starts_with(function_end_pattern(target)) => 1,
+        _ => 0,
+    };
 
-    // This is the pattern at the beginning of an assembly label
-    // that identifies the label as a function:
-    let function_label_pattern = {
-        if target.is_apple() {
-            "__"
-        } else {
-            "_"
+    let id = crate::demangle::demangle(&label.id, target);
+    debug!("Function found: {}", id);
+    if log::log_enabled!(log::Level::Debug) {
+        for l in &body {
+            debug!("## {}", l);
         }
-    };
+    }
+
+    // Line numbers start at 1, and the body starts after the label:
+    let mut function = function_body(body, &id, file, first_line + 2, target)?;
+    function.label = label.id;
+    function.lines = first_line..first_line + 1 + body_len + end;
+    Ok(function)
+}
 
-    // This is the pattern that we match to know that we have finished
-    // searching the function
-    let function_end_pattern = {
-        if target.is_windows() {
-            ".seh_endproc" // TODO: does this work with panic=abort ?
-        } else {
-            ".cfi_endproc"
+/// If the function does not contain a .file directive, its file is the one
+/// of its first location.
+pub fn resolve_file(function: &mut ast::Function, files: &FileTable) {
+    if function.file.is_none() {
+        if let Some(loc) = function.loc {
+            function.file = files.get(&loc.file_index).cloned();
         }
-    };
+    }
+}
 
-    while let Some(line) = line_iter.next() {
-        let line = line.unwrap().trim().to_string();
-
-        if function.is_none() && line.starts_with(function_label_pattern) {
-            // We haven't found the function yet:
-            //
-            // Assembly functions are labels that start with `_` or `__`
-            // and have mangled names.
-            if let Some(label) = ast::Label::new(&line, None) {
-                let demangled_function_name =
-                    crate::demangle::demangle(&label.id, &target);
-                function_table.push(demangled_function_name.clone());
-                if demangled_function_name != path {
-                    continue;
-                }
-                // We have found the function, collect its lines and build
-                // an AST:
-                let mut lines = Vec::<String>::new();
-                while let Some(l) = line_iter.next() {
-                    let l = l.unwrap().trim().to_string();
-                    if l.starts_with(function_end_pattern) {
-                        break;
-                    }
-                    lines.push(l);
-                }
-                debug!("Function found: {}", path);
-                if opts.debug_mode() {
-                    for l in &lines {
-                        debug!("## {}", l);
+/// Symbols that an instruction argument refers to, e.g., `.L__unnamed_1`
+/// in `[rip + .L__unnamed_1]` or `:lo12:.Lanon.0`.
+pub(crate) fn referenced_symbols(arg: &str) -> impl Iterator<Item = &str> {
+    arg.split(|c: char| {
+        !(c.is_alphanumeric() || c == '_' || c == '.' || c == '$')
+    })
+    .map(|s| s.trim_start_matches('$'))
+    .filter(|s| !s.is_empty())
+}
+
+/// Annotates the instructions of the function with the data that they
+/// reference, and collects the referenced data objects.
+///
+/// Data referenced by the collected data (e.g. the file name of a panic
+/// location) is collected as well.
+pub fn resolve_data(function: &mut ast::Function, data: &[ast::Data]) {
+    let data = data
+        .iter()
+        .map(|d| (d.id.as_str(), d))
+        .collect::<::std::collections::HashMap<_, _>>();
+    let mut referenced = Vec::<&ast::Data>::new();
+    for s in &mut function.statements {
+        let i = match s {
+            Statement::Instruction(ref mut i) => i,
+            _ => continue,
+        };
+        let d = match i
+            .args
+            .iter()
+            .flat_map(|a| referenced_symbols(a))
+            .find_map(|s| data.get(s))
+        {
+            Some(d) => *d,
+            None => continue,
+        };
+        i.data = Some(d.describe());
+        if !referenced.iter().any(|r| r.id == d.id) {
+            referenced.push(d);
+        }
+    }
+    let mut idx = 0;
+    while idx < referenced.len() {
+        for v in &referenced[idx].values {
+            if let ast::Value::Symbol { ref name, .. } = v {
+                for s in referenced_symbols(name) {
+                    if let Some(d) = data.get(s) {
+                        if !referenced.iter().any(|r| r.id == d.id) {
+                            referenced.push(d);
+                        }
                     }
                 }
+            }
+        }
+        idx += 1;
+    }
+    function.data = referenced.into_iter().cloned().collect();
+}
 
-                function = Some(function_body(lines, &path, &target));
-                // If the function contained a .file directive, we are
-                // done:
-                if let Some(ref function) = &function {
-                    if function.file.is_some() {
-                        break;
+/// Associates the jump tables referenced by the function with the indirect
+/// jumps that use them.
+///
+/// The jump tables are either `data` objects, or are embedded in the body
+/// of the function (e.g. on MacOSX, where the entries refer to `.set`
+/// aliases).
+pub fn resolve_jump_tables(
+    function: &mut ast::Function,
+    data: &[ast::Data],
+    target: &TargetInfo,
+) {
+    let mut tables = data
+        .iter()
+        .filter_map(|d| Some((d.id.clone(), d.jump_targets()?)))
+        .collect::<::std::collections::HashMap<_, _>>();
+    let mut sets = ::std::collections::HashMap::new();
+    let mut table: Option<ast::Data> = None;
+    for s in &function.statements {
+        if let Statement::Directive(Directive::Generic(ref g)) = s {
+            // `.set L0_0_set_2, LBB0_2-LJTI0_0`
+            if let Some(set) = g.string.strip_prefix(".set") {
+                let mut args = set.splitn(2, ',').map(str::trim);
+                if let (Some(name), Some(expr)) = (args.next(), args.next()) {
+                    sets.insert(name, ast::first_symbol(expr));
+                }
+            }
+        }
+        match s {
+            Statement::Label(ref l) if l.id.contains("JTI") => {
+                table = Some(ast::Data {
+                    id: l.id.clone(),
+                    section: String::new(),
+                    values: Vec::new(),
+                });
+            }
+            Statement::Directive(Directive::Generic(ref g)) => {
+                if let Some(ref mut t) = table {
+                    if let Some(mut values) = ast::Value::parse(&g.string) {
+                        t.values.append(&mut values);
                     }
                 }
-
-                // If the function did not contain a .loc directive
-                // either, we can't finde its
-                // corresponding Rust code so we are done:
-                if let Some(ref function) = &function {
-                    if function.loc.is_none() {
-                        break;
+            }
+            Statement::Comment(_) => {}
+            _ => {
+                if let Some(t) = table.take() {
+                    if let Some(targets) = t.jump_targets() {
+                        tables.insert(t.id, targets);
                     }
                 }
-
-                // Otherwise we continue parsing the assembly file to try
-                // to find a .file directive for the
-                // function
-                continue;
             }
-            panic!(
-                "line starts with _ but we failed to parse the label: {}",
-                line
-            );
         }
-
-        // If the line does not begin an assembly function try to parse the
-        // line as a .file directive.
-        if let Some(file) = ast::File::new(&line, &target) {
-            debug!("found file directive: {:?}", file);
-            let idx = file.index;
-
-            // If the file directive is already in the table, check that
-            // the paths match:
-            if file_directive_table.contains_key(&idx) {
-                let f = &file_directive_table[&idx];
-                assert_eq!(f.path, file.path);
-                continue;
+    }
+    if let Some(t) = table.take() {
+        if let Some(targets) = t.jump_targets() {
+            tables.insert(t.id, targets);
+        }
+    }
+    if tables.is_empty() {
+        return;
+    }
+    for targets in tables.values_mut() {
+        for t in targets.iter_mut() {
+            if let Some(aliased) = sets.get(t.as_str()) {
+                *t = aliased.to_string();
             }
-
-            // The file directive is not in the table: insert it:
-            file_directive_table.insert(idx, file);
         }
+    }
 
-        // If we have found the function but landed here, the function contains
-        // at least one .loc directive but we haven't found its corresponding
-        // file yet, so we see if its present in the HashMap:
-        if let Some(ref mut function) = function {
-            assert!(function.file.is_none());
-            assert!(function.loc.is_some());
-            let file_index = function.loc.unwrap().file_index;
-            if let Some(file) = file_directive_table.remove(&file_index) {
-                function.file = Some(file);
-                break;
+    // The table is loaded before the indirect jump that uses it:
+    let mut loaded = None;
+    for s in &mut function.statements {
+        let i = match s {
+            Statement::Instruction(ref mut i) => i,
+            _ => continue,
+        };
+        if i.is_indirect_jump(target) {
+            if let Some(targets) = loaded.take() {
+                i.jump_targets = targets;
             }
+            continue;
+        }
+        if let Some(targets) = i
+            .args
+            .iter()
+            .flat_map(|a| referenced_symbols(a))
+            .find_map(|s| tables.get(s))
+        {
+            loaded = Some(targets.clone());
         }
     }
+}
 
-    if function.is_none() {
-        // If the function is not found we have visited the whole file so the
-        // function table is complete.
-        return Result::NotFound(function_table);
+/// Is `arg` an x86 general-purpose register, e.g., `rax` or `r11`?
+fn is_x86_register(arg: &str) -> bool {
+    const LEGACY: [&str; 8] = ["ax", "bx", "cx", "dx", "si", "di", "bp", "sp"];
+    let arg = arg.trim_start_matches(&['*', '%'][..]);
+    match arg.strip_prefix('r').or_else(|| arg.strip_prefix('e')) {
+        Some(r) => {
+            LEGACY.contains(&r)
+                || r.trim_end_matches(&['d', 'w', 'b'][..])
+                    .parse::<u8>()
+                    .is_ok_and(|n| n < 16)
+        }
+        None => false,
     }
+}
 
-    let function = function.unwrap();
-
-    // Add all local .file directives in the body of the function to the table:
-    if let Some(ref f) = &function.file {
-        file_directive_table
-            .entry(f.index)
-            .or_insert_with(|| f.clone());
+/// Is the `symbol` referenced by an instruction a function? The symbols of
+/// the module have a type. The other symbols are functions unless they are
+/// local labels: the symbols relocated through the PLT or the GOT (`foo@PLT`),
+/// the Rust and C symbols (`_ZN...`, `__rust_alloc`), and the `direct`
+/// targets of calls and jumps (`memcpy`) other than registers.
+fn is_function_symbol(
+    symbol: &str,
+    relocated: bool,
+    direct: bool,
+    symbols: &::std::collections::HashMap<String, ast::Symbol>,
+    target: &TargetInfo,
+) -> bool {
+    match symbols.get(symbol).map(|s| s.kind) {
+        Some(ast::SymbolKind::Function) => return true,
+        Some(ast::SymbolKind::Object) => return false,
+        _ => {}
+    }
+    if symbol.is_empty() || symbol.starts_with('.') || symbol.starts_with('L')
+    {
+        return false;
     }
+    relocated
+        || symbol.starts_with('_')
+        || symbol.contains("::")
+        || (direct
+            && !(target.is_intel() && is_x86_register(symbol))
+            && !symbol.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+/// Demangled names of the functions that `function` calls (including tail
+/// calls), and of the other functions that it references (e.g. function
+/// pointers), in order and without duplicates. The `symbols` of the module
+/// tell the functions from the data.
+pub fn references(
+    function: &ast::Function,
+    symbols: &::std::collections::HashMap<String, ast::Symbol>,
+    target: &TargetInfo,
+) -> (Vec<String>, Vec<String>) {
+    let mut calls = Vec::<String>::new();
+    let mut references = Vec::<String>::new();
     for s in &function.statements {
-        if let Statement::Directive(Directive::File(ref f)) = s {
-            file_directive_table
-                .entry(f.index)
-                .or_insert_with(|| f.clone());
+        let i = match s {
+            Statement::Instruction(ref i) => i,
+            _ => continue,
+        };
+        let is_call = i.is_call(target)
+            || i.is_jump(target)
+            || i.is_indirect_jump(target);
+        let names = if is_call { &mut calls } else { &mut references };
+        // The target of a direct call or jump is its only argument, and is
+        // already demangled (e.g. `foo::bar` or `memcpy`). The other
+        // symbols are within the arguments, e.g., `[rip + foo@GOTPCREL]`:
+        let direct =
+            (i.is_call(target) || i.is_jump(target)) && i.args.len() == 1;
+        for arg in &i.args {
+            let symbols_of_arg = if direct {
+                vec![arg.as_str()]
+            } else {
+                arg.split(|c: char| {
+                    !(c.is_alphanumeric()
+                        || c == '_'
+                        || c == '.'
+                        || c == '$'
+                        || c == '@')
+                })
+                .map(|s| s.trim_start_matches('$'))
+                .collect()
+            };
+            for s in symbols_of_arg {
+                let (symbol, relocated) = match s.split_once('@') {
+                    Some((symbol, _)) => (symbol, true),
+                    None => (s, false),
+                };
+                if !is_function_symbol(
+                    symbol, relocated, direct, symbols, target,
+                ) {
+                    continue;
+                }
+                let name = crate::demangle::demangle(symbol, target);
+                if name != function.id && !names.contains(&name) {
+                    names.push(name);
+                }
+            }
         }
     }
+    references.retain(|r| !calls.contains(r));
+    (calls, references)
+}
+
+/// Demangled names of the functions called by `function`, in call order and
+/// without duplicates.
+pub fn callees(function: &ast::Function, target: &TargetInfo) -> Vec<String> {
+    references(function, &::std::collections::HashMap::new(), target).0
+}
 
-    // Check that we have found all .file directives for all .loc statements
-    // within the function:
-    let mut done = true;
+/// Is `name` the demangled name of a function of std, core, or alloc?
+pub fn is_std(name: &str) -> bool {
+    let krate = crate::demangle::crate_name(name);
+    krate == "std" || krate == "core" || krate == "alloc"
+}
+
+/// Checks that all .loc statements of the function have a .file directive.
+#[allow(clippy::use_debug)]
+pub fn check_locations(
+    function: &ast::Function,
+    files: &FileTable,
+    file: &::std::path::Path,
+) -> ::std::result::Result<(), Error> {
     for s in &function.statements {
         if let Statement::Directive(Directive::Loc(ref l)) = s {
-            if !file_directive_table.contains_key(&l.file_index) {
-                done = false;
+            if !files.contains_key(&l.file_index) {
                 error!(
                     "File directive for location not found! Location: {:?}",
                     l
                 );
+                return Err(Error::FileTable {
+                    file: file.to_path_buf(),
+                    function: function.id.clone(),
+                    index: l.file_index,
+                });
             }
         }
     }
+    Ok(())
+}
 
-    if done {
-        return Result::Found(function, file_directive_table);
+/// Updates the `symbols` table with the symbol directive `s`: `.globl`,
+/// `.type`, `.size`, and on Windows `.def ...; .type 32; .endef`.
+///
+/// Returns `false` if `s` is not a symbol directive.
+fn symbol_directive(
+    s: &str,
+    symbols: &mut ::std::collections::HashMap<String, ast::Symbol>,
+    last_def: &mut Option<String>,
+) -> bool {
+    let mut tokens = s.splitn(2, char::is_whitespace);
+    let directive = tokens.next().unwrap_or("");
+    let args = tokens
+        .next()
+        .unwrap_or("")
+        .trim()
+        .trim_end_matches(';')
+        .split(',')
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    fn entry<'a>(
+        symbols: &'a mut ::std::collections::HashMap<String, ast::Symbol>,
+        name: &str,
+    ) -> &'a mut ast::Symbol {
+        symbols
+            .entry(name.to_string())
+            .or_insert_with(|| ast::Symbol::new(name))
+    }
+    match (directive, args.as_slice()) {
+        (".globl", [name]) | (".global", [name]) => {
+            entry(symbols, name).global = true;
+        }
+        (".def", [name]) => {
+            entry(symbols, name);
+            *last_def = Some(name.to_string());
+        }
+        // Windows: `.type 32` within a `.def` block declares a function.
+        (".type", [kind]) => {
+            if let Some(name) = last_def.as_ref() {
+                if *kind == "32" {
+                    entry(symbols, name).kind = ast::SymbolKind::Function;
+                }
+            }
+        }
+        (".endef", _) => *last_def = None,
+        (".type", [name, kind]) => {
+            entry(symbols, name).kind =
+                match kind.trim_start_matches(&['@', '%', '#'][..]) {
+                    "function" => ast::SymbolKind::Function,
+                    "object" | "tls_object" => ast::SymbolKind::Object,
+                    _ => ast::SymbolKind::Unknown,
+                };
+        }
+        (".size", [name, size]) => {
+            entry(symbols, name).size = Some(size.to_string());
+        }
+        _ => return false,
     }
+    true
+}
+
+/// Parses the assembly file `file` into a module.
+#[allow(clippy::use_debug)]
+pub fn module(
+    file: &::std::path::Path,
+    target: &TargetInfo,
+) -> ::std::result::Result<ast::Module, Error> {
+    let (lines, line_offsets) = read_lines(file)?;
+
+    // First pass: collect the .file directives of the whole file.
+    let files = file_table(&lines, file, target)?;
+
+    let mut module = ast::Module {
+        path: file.to_path_buf(),
+        functions: Vec::new(),
+        data: Vec::new(),
+        sections: Vec::new(),
+        files,
+        symbols: ::std::collections::HashMap::new(),
+        line_offsets,
+    };
+
+    // Assembly starts in the text section:
+    let mut section = ast::Section {
+        name: ".text".to_string(),
+    };
+    let mut last_def = None;
+    let mut data: Option<ast::Data> = None;
+
+    // Second pass: parse the functions, data, and symbols.
+    let mut line_idx = 0;
+    while line_idx < lines.len() {
+        let line = split_comment(&lines[line_idx], target).0;
+        line_idx += 1;
+
+        if let Some(new_section) = ast::Section::new(line) {
+            module.data.extend(data.take());
+            if !module.sections.contains(&new_section) {
+                module.sections.push(new_section.clone());
+            }
+            section = new_section;
+            continue;
+        }
+
+        if symbol_directive(line, &mut module.symbols, &mut last_def) {
+            continue;
+        }
 
-    unimplemented!(
-        "TODO: need to continue scanning the file for file directives"
-    )
+        let label = match ast::Label::new(line, None) {
+            Some(label) => label,
+            None => {
+                // Anything else belongs to the current data object:
+                if let Some(ref mut data) = data {
+                    if let Some(mut values) = ast::Value::parse(line) {
+                        data.values.append(&mut values);
+                    }
+                }
+                continue;
+            }
+        };
+
+        // Assembly functions are labels that start with `_` or `__`
+        // and have mangled names.
+        let is_function = is_function_label(line, target)
+            && match module.symbols.get(&label.id).map(|s| s.kind) {
+                Some(ast::SymbolKind::Function) => true,
+                Some(ast::SymbolKind::Object) => false,
+                _ => section.is_text(),
+            };
+
+        module.data.extend(data.take());
+
+        if !is_function {
+            // Labels in data sections start data objects:
+            if !section.is_text() {
+                data = Some(ast::Data {
+                    id: label.id,
+                    section: section.name.clone(),
+                    values: Vec::new(),
+                });
+            }
+            continue;
+        }
+
+        let label_idx = line_idx - 1;
+        let mut function =
+            function_at(file, &lines[label_idx..], label_idx, target)?;
+        resolve_file(&mut function, &module.files);
+        line_idx = function.lines.end;
+        module.functions.push(function);
+    }
+    module.data.extend(data.take());
+
+    Ok(module)
+}
+
+/// Returns the function `path` of the `module` if it is found.
+///
+/// Fails if the function contains locations without a `.file` directive.
+pub fn function(
+    module: &ast::Module,
+    path: &str,
+    target: &TargetInfo,
+) -> ::std::result::Result<Option<ast::Function>, Error> {
+    let function = match module.function(path) {
+        Some(function) => function,
+        None => return Ok(None),
+    };
+    check_locations(function, &module.files, &module.path)?;
+    let mut function = function.clone();
+    resolve_data(&mut function, &module.data);
+    resolve_jump_tables(&mut function, &module.data, target);
+    Ok(Some(function))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn module() {
+        let target = crate::target::TargetInfo::new_from_triple(
+            "x86_64-unknown-linux-gnu".to_string(),
+        );
+        let asm = r#"
+	.text
+	.file	"foo.1234-cgu.0"
+	.section	.text._ZN3foo3bar17h0123456789abcdefE,"ax",@progbits
+	.globl	_ZN3foo3bar17h0123456789abcdefE
+	.p2align	4, 0x90
+	.type	_ZN3foo3bar17h0123456789abcdefE,@function
+_ZN3foo3bar17h0123456789abcdefE:
+	.cfi_startproc
+	.loc	1 3 0
+	lea	rdi, [rip + .L__unnamed_1]
+	ret
+.Lfunc_end0:
+	.size	_ZN3foo3bar17h0123456789abcdefE, .Lfunc_end0-_ZN3foo3bar17h0123456789abcdefE
+	.cfi_endproc
+
+	.type	.L__unnamed_1,@object
+	.section	.rodata..L__unnamed_1,"a",@progbits
+.L__unnamed_1:
+	.ascii	"foo"
+	.size	.L__unnamed_1, 3
+
+	.file	1 "/comp/dir" "src/lib.rs"
+"#;
+        let path = ::std::env::temp_dir().join("cargo_asm_parse_module.s");
+        ::std::fs::write(&path, asm).unwrap();
+        let module = super::module(&path, &target).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(module.functions.len(), 1);
+        let f = super::function(&module, "foo::bar", &target)
+            .unwrap()
+            .unwrap();
+        assert_eq!(f.label, "_ZN3foo3bar17h0123456789abcdefE");
+        assert_eq!(
+            f.file.as_ref().unwrap().path,
+            ::std::path::PathBuf::from("/comp/dir/src/lib.rs")
+        );
+
+        assert_eq!(module.data.len(), 1);
+        assert_eq!(module.data[0].id, ".L__unnamed_1");
+        assert_eq!(module.data[0].section, ".rodata..L__unnamed_1");
+        assert_eq!(
+            module.data[0].values,
+            vec![super::ast::Value::Bytes(b"foo".to_vec())]
+        );
+        assert_eq!(f.data, module.data);
+
+        let symbol = &module.symbols["_ZN3foo3bar17h0123456789abcdefE"];
+        assert!(symbol.global);
+        assert_eq!(symbol.kind, super::ast::SymbolKind::Function);
+        assert_eq!(module.symbols[".L__unnamed_1"].size, Some("3".into()));
+        assert_eq!(module.sections.len(), 3);
+    }
+
+    #[test]
+    fn file_table() {
+        let target = crate::target::TargetInfo::new_from_triple(
+            "x86_64-unknown-linux-gnu".to_string(),
+        );
+        let lines = [
+            "_ZN3foo3bar17h0123456789abcdefE:",
+            ".loc 2 10 0",
+            "ret",
+            ".cfi_endproc",
+            ".file 1 \"src/lib.rs\"",
+            ".file 2 \"src/bar.rs\"",
+            ".file 2 \"src/bar.rs\"",
+        ]
+        .iter()
+        .map(|l| l.to_string())
+        .collect::<Vec<_>>();
+        let path = ::std::path::Path::new("foo.s");
+        let table = super::file_table(&lines, path, &target).unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table[&2].path, ::std::path::PathBuf::from("src/bar.rs"));
+
+        let mut lines = lines;
+        lines.push(".file 2 \"src/baz.rs\"".to_string());
+        assert!(super::file_table(&lines, path, &target).is_err());
+    }
+
+    #[test]
+    fn comments() {
+        use crate::target::TargetInfo;
+        let x86 = TargetInfo::new_from_triple(
+            "x86_64-unknown-linux-gnu".to_string(),
+        );
+        let arm = TargetInfo::new_from_triple(
+            "armv7-unknown-linux-gnueabihf".to_string(),
+        );
+        let aarch64 = TargetInfo::new_from_triple(
+            "aarch64-unknown-linux-gnu".to_string(),
+        );
+        let apple =
+            TargetInfo::new_from_triple("aarch64-apple-darwin".to_string());
+
+        assert_eq!(
+            super::split_comment("mov eax, 1 # foo", &x86),
+            ("mov eax, 1", "# foo")
+        );
+        assert_eq!(
+            super::split_comment(".ascii \"a # b\" # c", &x86),
+            (".ascii \"a # b\"", "# c")
+        );
+        assert_eq!(
+            super::split_comment("mov r0, #1 @ foo", &arm),
+            ("mov r0, #1", "@ foo")
+        );
+        assert_eq!(
+            super::split_comment("mov x0, #1 // foo", &aarch64),
+            ("mov x0, #1", "// foo")
+        );
+        assert_eq!(
+            super::split_comment("mov x0, #1 ; foo", &apple),
+            ("mov x0, #1", "; foo")
+        );
+
+        assert_eq!(
+            super::split_statements("nop; .ascii \";\" ;ret", &x86),
+            vec!["nop", ".ascii \";\"", "ret"]
+        );
+        assert_eq!(
+            super::split_statements("nop %% ret", &apple),
+            vec!["nop", "ret"]
+        );
+    }
+
+    #[test]
+    fn jump_tables() {
+        let target = crate::target::TargetInfo::new_from_triple(
+            "x86_64-apple-darwin".to_string(),
+        );
+        let lines = [
+            "__ZN3foo3bar17h0123456789abcdefE:",
+            ".cfi_startproc",
+            "leaq LJTI0_0(%rip), %rcx",
+            "movslq (%rcx,%rax,4), %rax",
+            "addq %rcx, %rax",
+            "jmpq *%rax",
+            "LBB0_1:",
+            "retq",
+            "LBB0_2:",
+            "jmp LBB0_1",
+            ".p2align 2, 0x90",
+            ".data_region jt32",
+            ".set L0_0_set_1, LBB0_1-LJTI0_0",
+            ".set L0_0_set_2, LBB0_2-LJTI0_0",
+            "LJTI0_0:",
+            ".long L0_0_set_2",
+            ".long L0_0_set_1",
+            ".long L0_0_set_2",
+            ".end_data_region",
+            ".cfi_endproc",
+        ]
+        .iter()
+        .map(|l| l.to_string())
+        .collect::<Vec<_>>();
+        let path = ::std::path::Path::new("foo.s");
+        let mut f = super::function_at(path, &lines, 0, &target).unwrap();
+        super::resolve_jump_tables(&mut f, &[], &target);
+        let targets = f
+            .statements
+            .iter()
+            .filter_map(|s| match s {
+                super::Statement::Instruction(ref i) => Some(&i.jump_targets),
+                _ => None,
+            })
+            .find(|t| !t.is_empty())
+            .unwrap();
+        assert_eq!(targets, &["LBB0_2", "LBB0_1", "LBB0_2"]);
+    }
+
+    #[test]
+    fn references() {
+        let target = crate::target::TargetInfo::new_from_triple(
+            "x86_64-unknown-linux-gnu".to_string(),
+        );
+        let asm = r#"
+	.text
+	.globl	_ZN3foo3bar17h0123456789abcdefE
+	.type	_ZN3foo3bar17h0123456789abcdefE,@function
+_ZN3foo3bar17h0123456789abcdefE:
+	.cfi_startproc
+	call	memcpy@PLT
+	call	qword ptr [rip + __rust_alloc@GOTPCREL]
+	call	no_mangle_fn
+	call	rax
+	lea	rdi, [rip + FOO]
+	lea	rsi, [rip + callback]
+	test	rdi, rdi
+	jne	.LBB0_1
+	jmp	_ZN3foo3baz17h0123456789abcdefE
+.LBB0_1:
+	ret
+.Lfunc_end0:
+	.cfi_endproc
+
+	.type	callback,@function
+callback:
+	.cfi_startproc
+	ret
+.Lfunc_end1:
+	.cfi_endproc
+
+	.type	FOO,@object
+	.section	.rodata.FOO,"a",@progbits
+FOO:
+	.ascii	"foo"
+	.size	FOO, 3
+"#;
+        let path = ::std::env::temp_dir().join("cargo_asm_parse_references.s");
+        ::std::fs::write(&path, asm).unwrap();
+        let module = super::module(&path, &target).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+
+        let f = super::function(&module, "foo::bar", &target)
+            .unwrap()
+            .unwrap();
+        let (calls, references) =
+            super::references(&f, &module.symbols, &target);
+        assert_eq!(
+            calls,
+            ["memcpy", "__rust_alloc", "no_mangle_fn", "foo::baz"]
+        );
+        assert_eq!(references, ["callback"]);
+    }
+
+    #[test]
+    fn is_std() {
+        assert!(super::is_std("core::slice::sort::stable::drift::sort"));
+        assert!(super::is_std(
+            "<alloc::vec::Vec<T> as core::ops::Drop>::drop"
+        ));
+        assert!(super::is_std(
+            "<&mut std::io::Stdout as std::io::Write>::flush"
+        ));
+        assert!(!super::is_std("<foo::Foo as core::ops::Drop>::drop"));
+        assert!(!super::is_std("stdx::foo"));
+    }
 }
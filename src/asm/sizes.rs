@@ -0,0 +1,340 @@
+//! Sizes of the functions in the assembly files.
+
+use crate::cache::Entry;
+
+/// Key to sort the sizes by (in decreasing order).
+#[derive(Copy, Clone, Debug)]
+pub enum SortBy {
+    Instructions,
+    Bytes,
+}
+
+impl ::std::str::FromStr for SortBy {
+    type Err = String;
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "instructions" => Ok(SortBy::Instructions),
+            "bytes" => Ok(SortBy::Bytes),
+            v => Err(format!(
+                "\"{}\" is not a valid sort key. Try \"instructions\" or \"bytes\"",
+                v
+            )),
+        }
+    }
+}
+
+/// How to group the functions.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GroupBy {
+    Function,
+    Crate,
+    Module,
+    /// All monomorphizations of a generic function.
+    Generic,
+}
+
+impl ::std::str::FromStr for GroupBy {
+    type Err = String;
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "function" => Ok(GroupBy::Function),
+            "crate" => Ok(GroupBy::Crate),
+            "module" => Ok(GroupBy::Module),
+            "generic" => Ok(GroupBy::Generic),
+            v => Err(format!(
+                "\"{}\" is not a valid grouping. Try \"function\", \"crate\", \"module\", or \"generic\"",
+                v
+            )),
+        }
+    }
+}
+
+/// Size of a function or of a group of functions.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Size {
+    pub name: String,
+    /// Number of functions in the group.
+    pub functions: usize,
+    pub instructions: usize,
+    /// Size in bytes, if it is known for all functions of the group.
+    pub bytes: Option<u64>,
+}
+
+/// Sizes in bytes of the functions of the object `file`, by symbol.
+///
+/// ELF objects record the size of each symbol. On Mach-O and COFF a
+/// function ends where the next symbol of its section begins.
+pub fn object_sizes(
+    file: &::std::path::Path,
+) -> ::std::collections::HashMap<String, u64> {
+    use object::{Object, ObjectSection, ObjectSymbol};
+
+    let mut sizes = ::std::collections::HashMap::new();
+    let data = match ::std::fs::read(file) {
+        Ok(data) => data,
+        Err(_) => return sizes,
+    };
+    let object = match object::File::parse(&*data) {
+        Ok(object) => object,
+        Err(e) => {
+            log::debug!("failed to parse {}: {}", file.display(), e);
+            return sizes;
+        }
+    };
+    // (section, address) of the symbols, and the ends of the sections:
+    let mut starts = object
+        .symbols()
+        .filter_map(|s| Some((s.section_index()?, s.address())))
+        .chain(
+            object
+                .sections()
+                .map(|s| (s.index(), s.address() + s.size())),
+        )
+        .collect::<Vec<_>>();
+    starts.sort_unstable_by_key(|&(section, address)| (section.0, address));
+    for s in object.symbols() {
+        let section = match s.section_index() {
+            Some(section) if s.kind() == object::SymbolKind::Text => section,
+            _ => continue,
+        };
+        let name = match s.name() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        let size = if s.size() > 0 {
+            s.size()
+        } else {
+            starts
+                .iter()
+                .find(|&&(i, address)| i == section && address > s.address())
+                .map_or(0, |&(_, end)| end - s.address())
+        };
+        sizes.insert(name.to_string(), size);
+    }
+    sizes
+}
+
+/// Sums the sizes of the functions of each group.
+pub fn group(entries: &[Entry], group_by: GroupBy) -> Vec<Size> {
+    let mut groups =
+        ::std::collections::HashMap::<String, Size>::with_capacity(
+            entries.len(),
+        );
+    for e in entries {
+        let name = match group_by {
+            GroupBy::Function => e.name.clone(),
+            GroupBy::Crate => crate::demangle::crate_name(&e.name).to_string(),
+            GroupBy::Module => crate::demangle::module_path(&e.name),
+            GroupBy::Generic => crate::demangle::generic_name(&e.name),
+        };
+        let size = groups.entry(name.clone()).or_insert_with(|| Size {
+            name,
+            functions: 0,
+            instructions: 0,
+            bytes: Some(0),
+        });
+        size.functions += 1;
+        size.instructions += e.instructions;
+        size.bytes = size.bytes.and_then(|b| Some(b + e.bytes?));
+    }
+    groups.into_values().collect()
+}
+
+/// Sorts the sizes in decreasing order: unknown sizes come last.
+pub fn sort(sizes: &mut [Size], sort_by: SortBy) {
+    sizes.sort_by(|a, b| {
+        let key = |s: &Size| match sort_by {
+            SortBy::Instructions => (Some(s.instructions as u64), s.bytes),
+            SortBy::Bytes => (s.bytes, Some(s.instructions as u64)),
+        };
+        key(b).cmp(&key(a)).then_with(|| a.name.cmp(&b.name))
+    });
+}
+
+/// Prints the sizes as a table.
+pub fn print(sizes: &[Size], group_by: GroupBy) {
+    let grouped = group_by != GroupBy::Function;
+    if grouped {
+        crate::pager::print(format_args!(
+            "{:>12} {:>10} {:>10}  Name\n",
+            "Instructions", "Bytes", "Functions"
+        ));
+    } else {
+        crate::pager::print(format_args!(
+            "{:>12} {:>10}  Name\n",
+            "Instructions", "Bytes"
+        ));
+    }
+    for s in sizes {
+        let bytes = s.bytes.map_or_else(|| "?".to_string(), |b| b.to_string());
+        if grouped {
+            crate::pager::print(format_args!(
+                "{:>12} {:>10} {:>10}  {}\n",
+                s.instructions, bytes, s.functions, s.name
+            ));
+        } else {
+            crate::pager::print(format_args!(
+                "{:>12} {:>10}  {}\n",
+                s.instructions, bytes, s.name
+            ));
+        }
+    }
+    let instructions = sizes.iter().map(|s| s.instructions).sum::<usize>();
+    let functions = sizes.iter().map(|s| s.functions).sum::<usize>();
+    crate::pager::print(format_args!(
+        "{:>12} {:>10}  Total ({} functions)\n",
+        instructions,
+        sizes
+            .iter()
+            .map(|s| s.bytes)
+            .sum::<Option<u64>>()
+            .map_or_else(|| "?".to_string(), |b| b.to_string()),
+        functions
+    ));
+}
+
+/// Generic functions with more than one copy, i.e., the groups of
+/// `GroupBy::Generic` sizes with more than one function, in decreasing
+/// order of instructions.
+pub fn monomorphizations(entries: &[Entry]) -> Vec<Size> {
+    // Monomorphizations differ only in their generic arguments (v0
+    // mangling), or in their hash (legacy mangling), which is not part of
+    // the demangled name:
+    let mut sizes = group(entries, GroupBy::Generic);
+    sizes.retain(|s| s.functions > 1);
+    sort(&mut sizes, SortBy::Instructions);
+    sizes
+}
+
+/// Prints the copies and the instructions of the generic functions.
+pub fn print_monomorphizations(sizes: &[Size]) {
+    crate::pager::print(format_args!(
+        "{:>8} {:>12}  Generic function\n",
+        "Copies", "Instructions"
+    ));
+    for s in sizes {
+        crate::pager::print(format_args!(
+            "{:>8} {:>12}  {}\n",
+            s.functions, s.instructions, s.name
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, instructions: usize, bytes: Option<u64>) -> Entry {
+        Entry {
+            name: name.to_string(),
+            offset: 0,
+            len: 0,
+            line: 0,
+            calls: Vec::new(),
+            references: Vec::new(),
+            instructions,
+            bytes,
+        }
+    }
+
+    #[test]
+    fn group_and_sort() {
+        let entries = vec![
+            entry("foo::bar::<u8>", 10, Some(40)),
+            entry("foo::bar::<u16>", 12, None),
+            entry("foo::baz", 30, Some(80)),
+            entry("core::ptr::drop_in_place::<foo::Foo>", 5, Some(8)),
+        ];
+
+        let mut sizes = group(&entries, GroupBy::Generic);
+        sort(&mut sizes, SortBy::Instructions);
+        let names = sizes.iter().map(|s| s.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["foo::baz", "foo::bar", "core::ptr::drop_in_place"]
+        );
+        assert_eq!((sizes[1].functions, sizes[1].instructions), (2, 22));
+        assert_eq!(sizes[1].bytes, None);
+
+        let mut sizes = group(&entries, GroupBy::Crate);
+        sort(&mut sizes, SortBy::Bytes);
+        assert_eq!(sizes[0].name, "core");
+        assert_eq!(sizes[1].bytes, None);
+    }
+
+    #[test]
+    fn generic_copies() {
+        let target = crate::target::TargetInfo::new_from_triple(
+            "x86_64-unknown-linux-gnu".to_string(),
+        );
+        // Legacy symbols of two copies of `foo::bar`, which differ only in
+        // their hash:
+        let legacy = |symbol| crate::demangle::demangle(symbol, &target);
+        let entries = vec![
+            entry(&legacy("_ZN3foo3bar17h0123456789abcdefE"), 10, Some(40)),
+            entry(&legacy("_ZN3foo3bar17hfedcba9876543210E"), 12, Some(48)),
+            entry("foo::baz::<u8>", 3, Some(8)),
+            entry("foo::baz::<u16>", 4, None),
+            entry("foo::qux", 30, Some(80)),
+            entry("foo::quux::<u8>", 50, Some(200)),
+        ];
+        assert_eq!(
+            monomorphizations(&entries),
+            [
+                Size {
+                    name: "foo::bar".to_string(),
+                    functions: 2,
+                    instructions: 22,
+                    bytes: Some(88),
+                },
+                Size {
+                    name: "foo::baz".to_string(),
+                    functions: 2,
+                    instructions: 7,
+                    bytes: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn object_sizes() {
+        let dir = ::std::env::temp_dir().join("cargo_asm_object_sizes");
+        ::std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("lib.rs");
+        ::std::fs::write(
+            &source,
+            "#[no_mangle] pub extern \"C\" fn cargo_asm_ret() {}\n\
+             #[no_mangle] pub extern \"C\" fn cargo_asm_add(a: u32, b: u32) -> u32 { a.wrapping_add(b) }\n",
+        )
+        .unwrap();
+        let rustc =
+            ::std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+        let status = ::std::process::Command::new(rustc)
+            .args(["--crate-type=lib", "--emit=obj", "-C", "opt-level=3"])
+            .arg("--out-dir")
+            .arg(&dir)
+            .arg(&source)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let sizes = super::object_sizes(&dir.join("lib.o"));
+        ::std::fs::remove_dir_all(&dir).unwrap();
+
+        // Mach-O symbols have a leading underscore:
+        let size = |name: &str| {
+            sizes
+                .get(name)
+                .or_else(|| sizes.get(&format!("_{}", name)))
+                .copied()
+        };
+        assert!(size("cargo_asm_ret").is_some_and(|b| b > 0));
+        assert!(size("cargo_asm_add").is_some_and(|b| b > 0));
+        if cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+            // `ret`, and `lea eax, [rdi + rsi]; ret`:
+            assert_eq!(size("cargo_asm_ret"), Some(1));
+            assert_eq!(size("cargo_asm_add"), Some(4));
+        }
+        assert!(super::object_sizes(&dir.join("lib.o")).is_empty());
+    }
+}
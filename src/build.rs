@@ -38,6 +38,9 @@ pub fn project() -> Vec<::std::path::PathBuf> {
 
     // Compile project generating assembly output:
     let mut cargo_build = Command::new("cargo");
+    if let Some(toolchain) = opts.toolchain() {
+        cargo_build.arg(format!("+{}", toolchain));
+    }
     // TODO: unclear if `cargo build` + `RUSTFLAGS` should be used,
     // or instead one should use `cargo rustc -- --emit asm`
     cargo_build.arg("build");
@@ -52,6 +55,9 @@ pub fn project() -> Vec<::std::path::PathBuf> {
         cargo_build.arg("--release");
     }
     cargo_build.arg("--verbose");
+    // The artifacts built with other flags (e.g. `RUSTFLAGS`) are in the same
+    // directories, so the messages are used to find the current ones:
+    cargo_build.arg("--message-format=json-render-diagnostics");
 
     if !opts.features().is_empty() {
         cargo_build.arg(&format!("--features={}", opts.features().join(",")));
@@ -74,6 +80,7 @@ pub fn project() -> Vec<::std::path::PathBuf> {
     }
 
     let ti = crate::target::TargetInfo::new_from_target();
+    let extra_rustflags = opts.rustflags().unwrap_or_default();
 
     match *opts.read() {
         crate::options::Options::Asm(ref o) => {
@@ -84,17 +91,19 @@ pub fn project() -> Vec<::std::path::PathBuf> {
                 _ => "",
             };
 
-            let debug_info = if o.rust || o.debug_info {
-                "-C debuginfo=2"
-            } else {
-                ""
-            };
+            let debug_info =
+                if o.rust || o.annotate_source || o.inlined || o.debug_info {
+                    "-C debuginfo=2"
+                } else {
+                    ""
+                };
 
+            // The object file has the sizes of the functions (`--sizes`):
             cargo_build.env(
                 "RUSTFLAGS",
                 format!(
-                    "{} --emit asm {} {}",
-                    rustflags, debug_info, asm_syntax
+                    "{} {} --emit asm,obj {} {}",
+                    rustflags, extra_rustflags, debug_info, asm_syntax
                 ),
             );
         }
@@ -109,9 +118,11 @@ pub fn project() -> Vec<::std::path::PathBuf> {
 
     debug!("starting cargo build... {:?}", cargo_build);
     let error_msg = "cargo build failed";
-    process::exec(&mut cargo_build, error_msg, opts.debug_mode())
-        .expect(error_msg);
+    let (messages, _) =
+        process::exec(&mut cargo_build, error_msg, opts.debug_mode())
+            .expect(error_msg);
     debug!("cargo build finished...");
+    let artifacts = Artifacts::new(&messages);
 
     let ext = match *opts.read() {
         crate::options::Options::Asm(_) => "s",
@@ -125,7 +136,7 @@ pub fn project() -> Vec<::std::path::PathBuf> {
     // Scan files in "deps" target dir:
     output_files.append(&mut scan_directory(
         deps_directory.as_path(),
-        |_, extension| extension == Some(ext),
+        |stem, extension| extension == Some(ext) && artifacts.contains(stem),
     ));
 
     if let Some(example) = opts.example() {
@@ -161,6 +172,64 @@ pub fn project() -> Vec<::std::path::PathBuf> {
     output_files
 }
 
+/// Artifacts built by `cargo build`, from its JSON messages.
+#[derive(Default, Debug)]
+struct Artifacts {
+    /// File stems of the artifacts in the `deps` directory, e.g.,
+    /// `foo-1a2b3c4d`.
+    stems: ::std::collections::HashSet<String>,
+    /// Targets whose artifacts are not in the `deps` directory.
+    targets: ::std::collections::HashSet<String>,
+    /// Whether there were any messages at all.
+    any: bool,
+}
+
+impl Artifacts {
+    fn new(messages: &str) -> Self {
+        let mut artifacts = Self::default();
+        for m in messages.lines() {
+            let m: ::serde_json::Value = match ::serde_json::from_str(m) {
+                Ok(m) => m,
+                Err(_) => continue,
+            };
+            if m["reason"] != "compiler-artifact" {
+                continue;
+            }
+            artifacts.any = true;
+            let stems = m["filenames"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|f| f.as_str())
+                .map(::std::path::Path::new)
+                .filter(|f| {
+                    f.parent().and_then(|p| p.file_name())
+                        == Some("deps".as_ref())
+                })
+                .filter_map(|f| f.file_stem()?.to_str())
+                .map(|s| s.strip_prefix("lib").unwrap_or(s).to_string())
+                .collect::<Vec<_>>();
+            if stems.is_empty() {
+                if let Some(name) = m["target"]["name"].as_str() {
+                    artifacts.targets.insert(name.replace('-', "_"));
+                }
+            }
+            artifacts.stems.extend(stems);
+        }
+        artifacts
+    }
+
+    /// Whether the file with the `stem` was built by `cargo build`.
+    fn contains(&self, stem: Option<&str>) -> bool {
+        let stem = match stem {
+            Some(stem) => stem,
+            None => return false,
+        };
+        let target = stem.rsplit_once('-').map_or(stem, |(t, _)| t);
+        !self.any || self.stems.contains(stem) || self.targets.contains(target)
+    }
+}
+
 /// Scan a given output directory for files matching the predicate:
 fn scan_directory<P>(
     target_directory: &::std::path::Path,
@@ -190,3 +259,22 @@ where
     }
     output_files
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Artifacts;
+
+    #[test]
+    fn artifacts() {
+        let messages = r#"{"reason":"compiler-artifact","target":{"name":"foo"},"filenames":["/t/release/libfoo.rlib","/t/release/deps/libfoo-0d3a01e0.rmeta"]}
+{"reason":"compiler-artifact","target":{"name":"lib_crate"},"filenames":["/t/release/deps/liblib_crate-5e6f7a8b.rlib"]}
+{"reason":"compiler-artifact","target":{"name":"my-bin"},"filenames":["/t/release/my-bin"]}
+{"reason":"build-finished","success":true}"#;
+        let a = Artifacts::new(messages);
+        assert!(a.contains(Some("foo-0d3a01e0")));
+        assert!(!a.contains(Some("foo-26bda2e4")));
+        assert!(a.contains(Some("lib_crate-5e6f7a8b")));
+        assert!(a.contains(Some("my_bin-26bda2e4")));
+        assert!(Artifacts::new("").contains(Some("foo-26bda2e4")));
+    }
+}
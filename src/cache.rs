@@ -0,0 +1,275 @@
+//! Persistent index of the functions in the output files.
+//!
+//! Parsing a large assembly or LLVM-IR file is expensive. After a file has
+//! been parsed, an index mapping the function names to their position in the
+//! file is stored in the target directory, so that later invocations can
+//! read the function directly. The index is only used if the path, size, and
+//! modification time of the file match.
+
+use crate::asm::ast::Data;
+use crate::asm::parse::FileTable;
+use crate::error::Error;
+use crate::target::TargetInfo;
+
+use log::debug;
+use serde_derive::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Position of a function in the output file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entry {
+    /// Demangled name of the function.
+    pub name: String,
+    /// Byte offset of the beginning of the function.
+    pub offset: u64,
+    /// Size of the function in bytes.
+    pub len: u64,
+    /// Line of the beginning of the function (0-based).
+    pub line: usize,
+    /// Functions called by the function (assembly only).
+    pub calls: Vec<String>,
+    /// Other symbols referenced by the function (assembly only).
+    pub references: Vec<String>,
+    /// Number of instructions of the function (assembly only).
+    pub instructions: usize,
+    /// Size of the function in bytes, if known (assembly only).
+    pub bytes: Option<u64>,
+}
+
+/// Version of the index format: must be incremented when its contents
+/// change. Indexes of other versions are out-of-date, so that the fields
+/// of the index never need defaults.
+const FORMAT_VERSION: u32 = 2;
+
+/// Identifies an output file.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+struct Key {
+    version: String,
+    target: String,
+    path: PathBuf,
+    size: u64,
+    mtime: (u64, u32),
+}
+
+impl Key {
+    fn new(artifact: &Path, target: &TargetInfo) -> Option<Self> {
+        let metadata = ::std::fs::metadata(artifact).ok()?;
+        let mtime = metadata
+            .modified()
+            .ok()?
+            .duration_since(::std::time::UNIX_EPOCH)
+            .ok()?;
+        Some(Self {
+            version: format!(
+                "{}-{}",
+                env!("CARGO_PKG_VERSION"),
+                FORMAT_VERSION
+            ),
+            target: target.triple().to_string(),
+            path: artifact.to_path_buf(),
+            size: metadata.len(),
+            mtime: (mtime.as_secs(), mtime.subsec_nanos()),
+        })
+    }
+}
+
+/// Index of the functions of an output file.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Index {
+    key: Key,
+    pub functions: Vec<Entry>,
+    /// Table of `.file` directives (assembly only).
+    pub files: FileTable,
+    /// Data objects (assembly only).
+    pub data: Vec<Data>,
+}
+
+/// Path of the index of `artifact`: `target/{build_type}/cargo-asm/...`.
+fn index_path(artifact: &Path) -> Option<PathBuf> {
+    let build_dir = artifact.parent()?.parent()?;
+    let mut file_name = artifact.file_name()?.to_os_string();
+    file_name.push(".json");
+    Some(build_dir.join("cargo-asm").join(file_name))
+}
+
+impl Index {
+    /// Creates an index for `artifact`.
+    ///
+    /// Returns `None` if the `artifact` metadata cannot be read.
+    pub fn new(
+        artifact: &Path,
+        target: &TargetInfo,
+        functions: Vec<Entry>,
+        files: FileTable,
+        data: Vec<Data>,
+    ) -> Option<Self> {
+        Some(Self {
+            key: Key::new(artifact, target)?,
+            functions,
+            files,
+            data,
+        })
+    }
+
+    /// Loads the index of `artifact` if it exists and is up-to-date.
+    pub fn load(artifact: &Path, target: &TargetInfo) -> Option<Self> {
+        let key = Key::new(artifact, target)?;
+        let path = index_path(artifact)?;
+        let contents = ::std::fs::read(&path).ok()?;
+        let index: Self = ::serde_json::from_slice(&contents).ok()?;
+        if index.key != key {
+            debug!("index {} is out-of-date", path.display());
+            return None;
+        }
+        debug!("using index {}", path.display());
+        Some(index)
+    }
+
+    /// Stores the index in the target directory.
+    ///
+    /// Failing to store the index is not an error: the file will just be
+    /// parsed again the next time.
+    pub fn store(&self) {
+        let path = match index_path(&self.key.path) {
+            Some(path) => path,
+            None => return,
+        };
+        let r = path
+            .parent()
+            .map_or(Ok(()), ::std::fs::create_dir_all)
+            .and_then(|_| {
+                let contents = ::serde_json::to_vec(self)?;
+                ::std::fs::write(&path, contents)
+            });
+        if let Err(e) = r {
+            debug!("failed to store index {}: {}", path.display(), e);
+        }
+    }
+
+    /// Names of all functions in the index.
+    pub fn names(&self) -> Vec<String> {
+        self.functions.iter().map(|e| e.name.clone()).collect()
+    }
+
+    /// Returns the entry of the function `name`.
+    pub fn find(&self, name: &str) -> Option<&Entry> {
+        self.functions.iter().find(|e| e.name == name)
+    }
+
+    /// Reads the lines of the function at `entry` from the output file.
+    pub fn read(&self, entry: &Entry) -> Result<Vec<String>, Error> {
+        use std::io::{Read, Seek, SeekFrom};
+        let path = &self.key.path;
+        let mut fh =
+            ::std::fs::File::open(path).map_err(|e| Error::io(path, e))?;
+        let mut buf = vec![0; entry.len as usize];
+        fh.seek(SeekFrom::Start(entry.offset))
+            .and_then(|_| fh.read_exact(&mut buf))
+            .map_err(|e| Error::io(path, e))?;
+        Ok(String::from_utf8_lossy(&buf)
+            .lines()
+            .map(|l| l.trim().to_string())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASM: &str = "\t.text\nfoo:\n\tpush rbp\n\tret\nbar:\n\tret\n";
+
+    /// Entry of the function `name` at the lines `lines` of `ASM`.
+    fn entry(name: &str, lines: ::std::ops::Range<usize>) -> Entry {
+        let offset = |line| {
+            ASM.split_inclusive('\n')
+                .take(line)
+                .map(str::len)
+                .sum::<usize>() as u64
+        };
+        Entry {
+            name: name.to_string(),
+            offset: offset(lines.start),
+            len: offset(lines.end) - offset(lines.start),
+            line: lines.start,
+            calls: Vec::new(),
+            references: Vec::new(),
+            instructions: 0,
+            bytes: None,
+        }
+    }
+
+    #[test]
+    fn index() {
+        let target = TargetInfo::new_from_triple(
+            "x86_64-unknown-linux-gnu".to_string(),
+        );
+        let dir = ::std::env::temp_dir()
+            .join(format!("cargo-asm-cache-{}", ::std::process::id()));
+        let deps = dir.join("deps");
+        ::std::fs::create_dir_all(&deps).unwrap();
+        let artifact = deps.join("foo.s");
+        ::std::fs::write(&artifact, ASM).unwrap();
+        let entries = vec![entry("foo", 1..4), entry("bar", 4..6)];
+        let store = || {
+            Index::new(
+                &artifact,
+                &target,
+                entries.clone(),
+                FileTable::new(),
+                Vec::new(),
+            )
+            .unwrap()
+            .store()
+        };
+
+        // Round-trip, and the lines of the entries at their offsets:
+        assert!(Index::load(&artifact, &target).is_none());
+        store();
+        assert_eq!(
+            index_path(&artifact),
+            Some(dir.join("cargo-asm/foo.s.json"))
+        );
+        let index = Index::load(&artifact, &target).unwrap();
+        assert_eq!(index.names(), ["foo", "bar"]);
+        assert_eq!(index.find("foo").unwrap().line, 1);
+        assert_eq!(
+            index.read(index.find("foo").unwrap()).unwrap(),
+            ["foo:", "push rbp", "ret"]
+        );
+        assert_eq!(
+            index.read(index.find("bar").unwrap()).unwrap(),
+            ["bar:", "ret"]
+        );
+
+        // The index is specific to the target and to the format version:
+        let other =
+            TargetInfo::new_from_triple("aarch64-apple-darwin".to_string());
+        assert!(Index::load(&artifact, &other).is_none());
+        let mut old = Index::load(&artifact, &target).unwrap();
+        old.key.version =
+            format!("{}-{}", env!("CARGO_PKG_VERSION"), FORMAT_VERSION - 1);
+        old.store();
+        assert!(Index::load(&artifact, &target).is_none());
+
+        // Rewriting the file after indexing it invalidates the index, if
+        // either its modification time or its size changes:
+        store();
+        ::std::fs::write(&artifact, ASM.replace("rbp", "rbx")).unwrap();
+        let fh = ::std::fs::File::options()
+            .write(true)
+            .open(&artifact)
+            .unwrap();
+        let mtime = fh.metadata().unwrap().modified().unwrap();
+        let later = mtime + ::std::time::Duration::from_secs(1);
+        fh.set_modified(later).unwrap();
+        assert!(Index::load(&artifact, &target).is_none());
+        store();
+        assert!(Index::load(&artifact, &target).is_some());
+        ::std::fs::write(&artifact, format!("{}\tnop\n", ASM)).unwrap();
+        fh.set_modified(later).unwrap();
+        assert!(Index::load(&artifact, &target).is_none());
+
+        ::std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,470 @@
+//! Checks the assembly of functions against the expectations of a file and
+//! against the `CHECK` directives of the sources.
+
+use crate::asm::ast::{Directive, Statement};
+use crate::asm::parse::Result as ParseResult;
+use crate::error::Error;
+use crate::filecheck::Block;
+use crate::options::*;
+use crate::target::TargetInfo;
+use serde_derive::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Expectations of a function.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Expectation {
+    pub path: String,
+    /// File with the normalized assembly of the function.
+    pub snapshot: Option<PathBuf>,
+    pub max_instructions: Option<usize>,
+    /// Mnemonics of the instructions that the function must not contain.
+    #[serde(default)]
+    pub forbid: Vec<String>,
+    /// Minimum width in bits of the vector registers used by the function.
+    pub min_vector_width: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Checks {
+    #[serde(default)]
+    function: Vec<Expectation>,
+}
+
+/// Width in bits of the vector register `r`.
+fn vector_width(r: &str) -> Option<u32> {
+    let (kind, n) = r.split_at(r.find(|c: char| c.is_ascii_digit())?);
+    if !n.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    match kind {
+        "zmm" => Some(512),
+        "ymm" => Some(256),
+        // x86 and AArch64:
+        "xmm" | "v" | "q" => Some(128),
+        _ => None,
+    }
+}
+
+/// Is the instruction `m` the forbidden mnemonic `f`? In the AT&T syntax,
+/// the mnemonics have a suffix with the size of their operands, e.g.,
+/// `callq` for `call`.
+fn is_mnemonic(m: &str, f: &str, att: bool) -> bool {
+    m == f
+        || (att
+            && m.strip_prefix(f)
+                .is_some_and(|s| ["b", "w", "l", "q"].contains(&s)))
+}
+
+/// Expectations other than the snapshot that the function with the
+/// normalized `lines` does not meet. `att` is true for x86 assembly in the
+/// AT&T syntax.
+pub fn failures(lines: &[String], e: &Expectation, att: bool) -> Vec<String> {
+    let instructions = lines
+        .iter()
+        .filter(|l| l.starts_with(' '))
+        .map(|l| {
+            let mut words = l.trim().splitn(2, ' ');
+            (words.next().unwrap(), words.next().unwrap_or(""))
+        })
+        .collect::<Vec<_>>();
+    let mut failures = Vec::new();
+
+    if let Some(max) = e.max_instructions {
+        if instructions.len() > max {
+            failures.push(format!(
+                "{} instructions, more than the maximum of {}",
+                instructions.len(),
+                max
+            ));
+        }
+    }
+    for f in &e.forbid {
+        let count = instructions
+            .iter()
+            .filter(|(m, _)| is_mnemonic(m, f, att))
+            .count();
+        if count > 0 {
+            failures
+                .push(format!("{} forbidden \"{}\" instruction(s)", count, f));
+        }
+    }
+    if let Some(min) = e.min_vector_width {
+        let width = instructions
+            .iter()
+            .flat_map(|(_, args)| {
+                args.split(|c: char| !c.is_ascii_alphanumeric())
+            })
+            .filter_map(vector_width)
+            .max();
+        let width = width.unwrap_or(0);
+        if width < min {
+            failures.push(format!(
+                "the widest vector registers have {} bits, less than the minimum of {}",
+                width, min
+            ));
+        }
+    }
+    failures
+}
+
+/// Compares the `lines` with the `snapshot` file, or updates it if `bless`.
+/// Returns the failure and the lines of the snapshot, if it exists.
+#[allow(clippy::type_complexity)]
+fn snapshot(
+    lines: &[String],
+    snapshot: &Path,
+    bless: bool,
+) -> Result<Option<(String, Option<Vec<String>>)>, Error> {
+    let mut contents = lines.join("\n");
+    contents.push('\n');
+    if bless {
+        if let Some(dir) = snapshot.parent() {
+            ::std::fs::create_dir_all(dir).map_err(|e| Error::io(dir, e))?;
+        }
+        ::std::fs::write(snapshot, contents)
+            .map_err(|e| Error::io(snapshot, e))?;
+        return Ok(None);
+    }
+    let expected = match ::std::fs::read_to_string(snapshot) {
+        Ok(expected) => expected,
+        Err(_) => {
+            let msg = format!(
+                "the snapshot {} does not exist (run with --bless to create it)",
+                snapshot.display()
+            );
+            return Ok(Some((msg, None)));
+        }
+    };
+    if expected == contents {
+        return Ok(None);
+    }
+    let msg = format!(
+        "the assembly differs from the snapshot {}",
+        snapshot.display()
+    );
+    let expected = expected.lines().map(str::to_string).collect();
+    Ok(Some((msg, Some(expected))))
+}
+
+/// Prints whether the checks of `name` passed, and returns it.
+fn report(name: &str, failures: &[String]) -> bool {
+    use std::io::Write;
+    use termcolor::{Color, ColorSpec, WriteColor};
+
+    let bufwtr = crate::display::stdout_writer();
+    let mut buffer = bufwtr.buffer();
+    let (status, color) = if failures.is_empty() {
+        ("ok", Color::Green)
+    } else {
+        ("FAILED", Color::Red)
+    };
+    buffer
+        .set_color(ColorSpec::new().set_fg(Some(color)))
+        .unwrap();
+    write!(&mut buffer, "{:<8}", status).unwrap();
+    buffer.reset().unwrap();
+    writeln!(&mut buffer, "{}", name).unwrap();
+    for f in failures {
+        writeln!(&mut buffer, "          {}", f).unwrap();
+    }
+    crate::pager::write(buffer.as_slice());
+    failures.is_empty()
+}
+
+/// Reads the expectations of the `file`.
+fn read(file: &Path) -> Result<Vec<Expectation>, Error> {
+    let contents =
+        ::std::fs::read_to_string(file).map_err(|e| Error::io(file, e))?;
+    let checks: Checks =
+        ::toml::from_str(&contents).map_err(|e| Error::Parse {
+            file: Some(file.to_path_buf()),
+            line: None,
+            text: String::new(),
+            msg: e.to_string(),
+        })?;
+    Ok(checks.function)
+}
+
+/// Blocks of `CHECK` directives of the Rust files of the `src` directory.
+fn source_blocks() -> Result<Vec<(PathBuf, Block)>, Error> {
+    let mut blocks = Vec::new();
+    let walk = ::walkdir::WalkDir::new("src")
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()));
+    for entry in walk.into_iter().filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.extension() != Some("rs".as_ref()) {
+            continue;
+        }
+        let source =
+            ::std::fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+        for block in crate::filecheck::blocks(&source) {
+            blocks.push((path.to_path_buf(), block));
+        }
+    }
+    Ok(blocks)
+}
+
+/// Name of the function of the `block` of the source `file`: among the
+/// functions with the name of the block, the one with the closest location
+/// after it.
+fn resolve(
+    file: &Path,
+    block: &Block,
+    entries: &[crate::cache::Entry],
+    files: &[PathBuf],
+    target: &TargetInfo,
+) -> Result<Option<String>, Error> {
+    let suffix = format!("::{}", block.function);
+    let mut names = entries
+        .iter()
+        .map(|e| e.name.as_str())
+        .filter(|n| crate::demangle::generic_name(n).ends_with(&suffix))
+        .collect::<Vec<_>>();
+    names.sort_unstable();
+    names.dedup();
+
+    let file = file.canonicalize().map_err(|e| Error::io(file, e))?;
+    let mut closest: Option<(usize, &str)> = None;
+    for name in names {
+        let (function, file_table) =
+            match crate::asm::parse_files(files, Some(name), target)? {
+                ParseResult::Found(function, file_table) => {
+                    (function, file_table)
+                }
+                ParseResult::NotFound(_) => continue,
+            };
+        // The first location can be the one of an inlined function:
+        let distance = function
+            .statements
+            .iter()
+            .filter_map(|s| match s {
+                Statement::Directive(Directive::Loc(loc)) => Some(loc),
+                _ => None,
+            })
+            .filter(|loc| {
+                file_table
+                    .get(&loc.file_index)
+                    .and_then(|f| f.path.canonicalize().ok())
+                    == Some(file.clone())
+            })
+            .filter(|loc| loc.file_line >= block.line)
+            .map(|loc| loc.file_line - block.line)
+            .min();
+        if let Some(distance) = distance {
+            if !matches!(closest, Some((d, _)) if d <= distance) {
+                closest = Some((distance, name));
+            }
+        }
+    }
+    Ok(closest.map(|(_, name)| name.to_string()))
+}
+
+/// Checks the functions of the `blocks` against their directives, and
+/// returns the number of failed ones.
+fn check_sources(
+    blocks: &[(PathBuf, Block)],
+    c: &Check,
+    files: &[PathBuf],
+    target: &TargetInfo,
+) -> Result<usize, Error> {
+    let entries = crate::asm::index_files(files, target)?;
+    let mut functions = Vec::with_capacity(blocks.len());
+    for (file, block) in blocks {
+        let name = resolve(file, block, &entries, files, target)?;
+        functions.push((file, block, name));
+    }
+
+    // The functions are found in the assembly, which has the locations, and
+    // then in the LLVM IR:
+    let ir_files = if c.args.ir {
+        let o = &c.build;
+        *opts.write() = Options::LlvmIr(LlvmIr {
+            path: None,
+            TRIPLE: o.TRIPLE.clone(),
+            features: o.features.clone(),
+            example: o.example.clone(),
+            no_color: o.no_color,
+            build_type: o.build_type,
+            rust: false,
+            debug_mode: o.debug_mode,
+            manifest_path: o.manifest_path.clone(),
+            lib: o.lib,
+            no_default_features: o.no_default_features,
+            base: None,
+        });
+        Some(crate::build::project())
+    } else {
+        None
+    };
+
+    let mut failed = 0;
+    for (file, block, name) in functions {
+        let location = format!("{}:{}", file.display(), block.line);
+        let name = match name {
+            Some(name) => name,
+            None => {
+                let msg = format!(
+                    "no function {} found in the assembly (is it inlined?)",
+                    block.function
+                );
+                failed += usize::from(!report(&location, &[msg]));
+                continue;
+            }
+        };
+        let lines = match ir_files {
+            Some(ref ir_files) => {
+                crate::llvmir::function(ir_files, Some(&name), target)?.ok()
+            }
+            None => match crate::asm::parse_files(files, Some(&name), target)?
+            {
+                ParseResult::Found(function, _) => {
+                    Some(crate::diff::normalize(&function, true))
+                }
+                ParseResult::NotFound(_) => None,
+            },
+        };
+        let failures = match lines {
+            Some(lines) => crate::filecheck::check(&block.directives, &lines)
+                .err()
+                .into_iter()
+                .collect(),
+            None => vec!["the function was not found".to_string()],
+        };
+        let label = format!("{} ({})", name, location);
+        failed += usize::from(!report(&label, &failures));
+    }
+    Ok(failed)
+}
+
+pub fn run(c: &Check) -> Result<(), Error> {
+    let file = match c.args.checks {
+        Some(ref file) => Some(file.clone()),
+        None => Some(PathBuf::from("asm-checks.toml")).filter(|f| f.exists()),
+    };
+    let expectations = match file {
+        Some(ref file) => read(file)?,
+        None => Vec::new(),
+    };
+    let dir = file
+        .as_ref()
+        .and_then(|f| f.parent())
+        .unwrap_or_else(|| Path::new(""));
+    let blocks = source_blocks()?;
+    if expectations.is_empty() && blocks.is_empty() {
+        crate::display::write_error(
+            "no checks found in asm-checks.toml nor CHECK directives in src/.\n",
+        );
+        ::std::process::exit(1);
+    }
+    // The directives are tied to the functions by their locations:
+    if !blocks.is_empty() {
+        if let Options::Asm(ref mut o) = *opts.write() {
+            o.debug_info = true;
+        }
+    }
+
+    let files = crate::build::project();
+    let target = TargetInfo::new_from_target();
+    let att = target.is_intel()
+        && matches!(opts.asm_style(), Some(crate::asm::Style::ATT));
+    let mut failed = 0;
+    for e in &expectations {
+        let mut failures = Vec::new();
+        let mut mismatch = None;
+        match crate::asm::parse_files(&files, Some(&e.path), &target)? {
+            ParseResult::Found(function, _) => {
+                let lines = crate::diff::normalize(&function, true);
+                failures = self::failures(&lines, e, att);
+                if let Some(ref s) = e.snapshot {
+                    let path = dir.join(s);
+                    let lines = crate::diff::normalize(&function, false);
+                    if let Some((msg, expected)) =
+                        snapshot(&lines, &path, c.args.bless)?
+                    {
+                        failures.push(msg);
+                        mismatch = expected.map(|e| (path, e, lines));
+                    }
+                }
+            }
+            ParseResult::NotFound(_) => {
+                failures.push("the function was not found".to_string())
+            }
+        }
+        failed += usize::from(!report(&e.path, &failures));
+        if let Some((path, expected, lines)) = mismatch {
+            let label = path.display().to_string();
+            crate::diff::print(&expected, &lines, (&label, "current"), 3);
+        }
+    }
+    failed += check_sources(&blocks, c, &files, &target)?;
+    if failed > 0 {
+        return Err(Error::Check { failed });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expectations() {
+        let checks: Checks = ::toml::from_str(
+            r#"
+            [[function]]
+            path = "foo::bar"
+            max_instructions = 3
+            forbid = ["call", "div"]
+            min_vector_width = 256
+            "#,
+        )
+        .unwrap();
+        let e = &checks.function[0];
+        let lines = [
+            ".LBB_0:",
+            "    vmovups ymm0, ymmword ptr [rdi]",
+            "    add rax, 1",
+            "    ret",
+        ];
+        let s =
+            |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(failures(&s(&lines), e, false).is_empty());
+
+        let call = "    call qword ptr [rip + foo::baz@GOTPCREL]";
+        let lines = s(&[&lines[..], &[call, call]].concat());
+        assert_eq!(
+            failures(&lines, e, false),
+            [
+                "5 instructions, more than the maximum of 3",
+                "2 forbidden \"call\" instruction(s)"
+            ]
+        );
+        let e = Expectation {
+            min_vector_width: Some(512),
+            ..Expectation::default()
+        };
+        assert_eq!(failures(&lines, &e, false).len(), 1);
+
+        // The AT&T mnemonics have a size suffix:
+        let lines = s(&[
+            "    callq *foo::baz@GOTPCREL(%rip)",
+            "    divq %rcx",
+            "    divl %ecx",
+            "    divps %xmm1, %xmm0",
+            "    retq",
+        ]);
+        let e = &checks.function[0];
+        assert_eq!(
+            failures(&lines, e, true),
+            [
+                "5 instructions, more than the maximum of 3",
+                "1 forbidden \"call\" instruction(s)",
+                "2 forbidden \"div\" instruction(s)",
+                "the widest vector registers have 128 bits, less than the minimum of 256"
+            ]
+        );
+        assert_eq!(failures(&lines, e, false).len(), 2);
+    }
+}
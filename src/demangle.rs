@@ -4,32 +4,104 @@ use crate::target::TargetInfo;
 
 use rustc_demangle;
 
-fn has_hash(name: &str) -> bool {
-    let mut bytes = name.bytes().rev();
-    for _ in 0..16 {
-        if !bytes.next().map_or(false, is_ascii_hexdigit) {
-            return false;
-        }
-    }
-    bytes.next() == Some(b'h')
-        && bytes.next() == Some(b':')
-        && bytes.next() == Some(b':')
-}
-
-fn is_ascii_hexdigit(byte: u8) -> bool {
-    byte >= b'0' && byte <= b'9' || byte >= b'a' && byte <= b'f'
-}
-
 pub fn demangle(n: &str, target: &TargetInfo) -> String {
     let n = if target.is_linux() {
         n.split("@PLT").nth(0).unwrap().to_string()
     } else {
         n.to_string()
     };
-    let mut name = rustc_demangle::demangle(&n).to_string();
-    if has_hash(&name) {
-        let len = name.len() - 19;
-        name.truncate(len);
+    // The alternate format omits the hashes of legacy symbols (`::h...`)
+    // and the crate disambiguators of v0 symbols (`std[...]`):
+    format!("{:#}", rustc_demangle::demangle(&n))
+}
+
+/// Crate of the demangled function `name`, e.g., `alloc` for
+/// `<alloc::vec::Vec<T> as core::ops::Drop>::drop`.
+pub fn crate_name(name: &str) -> &str {
+    name.trim_start_matches(&['<', '&', '*'][..])
+        .trim_start_matches("mut ")
+        .trim_start_matches("const ")
+        .split("::")
+        .next()
+        .unwrap_or("")
+}
+
+/// Demangled function `name` without generic arguments, e.g.,
+/// `foo::bar` for `foo::bar::<u32>`, or `<alloc::vec::Vec as Drop>::drop`
+/// for `<alloc::vec::Vec<u32> as Drop>::drop`.
+pub fn generic_name(name: &str) -> String {
+    let mut r = String::with_capacity(name.len());
+    // Nesting depth within generic arguments:
+    let mut depth = 0;
+    let mut prev = ' ';
+    for c in name.chars() {
+        if depth > 0 {
+            match c {
+                '<' => depth += 1,
+                '>' if prev != '-' => depth -= 1,
+                _ => {}
+            }
+        } else if c == '<'
+            && (prev.is_alphanumeric() || prev == '_' || prev == ':')
+        {
+            // Generic arguments follow a name or a turbofish `::`:
+            depth = 1;
+            if r.ends_with("::") {
+                r.truncate(r.len() - 2);
+            }
+        } else {
+            r.push(c);
+        }
+        prev = c;
+    }
+    r
+}
+
+/// Path of the demangled function `name` without generic arguments and
+/// without its last component, e.g., `foo::bar` for `foo::bar::baz::<T>`,
+/// or `<foo::Foo as core::ops::Drop>` for `<foo::Foo as core::ops::Drop>::drop`.
+pub fn module_path(name: &str) -> String {
+    let name = generic_name(name);
+    let mut depth = 0;
+    let mut prev = ' ';
+    let mut end = None;
+    for (i, c) in name.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' if prev != '-' => depth -= 1,
+            ':' if depth == 0
+                && name[i..].starts_with("::")
+                && prev != ':' =>
+            {
+                end = Some(i)
+            }
+            _ => {}
+        }
+        prev = c;
+    }
+    match end {
+        Some(end) => name[..end].to_string(),
+        None => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn names() {
+        let name = "<alloc::vec::Vec<u32> as core::ops::Drop>::drop";
+        assert_eq!(super::crate_name(name), "alloc");
+        assert_eq!(
+            super::generic_name(name),
+            "<alloc::vec::Vec as core::ops::Drop>::drop"
+        );
+        assert_eq!(
+            super::module_path(name),
+            "<alloc::vec::Vec as core::ops::Drop>"
+        );
+        let name = "foo::bar::baz::<fn() -> u8, [u8; 4]>";
+        assert_eq!(super::generic_name(name), "foo::bar::baz");
+        assert_eq!(super::module_path(name), "foo::bar");
+        assert_eq!(super::crate_name("&mut foo::bar"), "foo");
     }
-    name
 }
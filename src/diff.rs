@@ -0,0 +1,545 @@
+//! Compares the assembly of a function built with two sets of options.
+
+use crate::asm::ast::{Function, Statement};
+use crate::asm::parse::Result as ParseResult;
+use crate::error::Error;
+use crate::options::*;
+use crate::target::TargetInfo;
+use std::collections::HashMap;
+
+/// Line of a diff.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Edit {
+    /// Line in both sides (left index, right index).
+    Same(usize, usize),
+    /// Line only in the left side.
+    Removed(usize),
+    /// Line only in the right side.
+    Added(usize),
+}
+
+/// Gives the labels and registers names that only depend on the order in
+/// which they first appear in the function.
+#[derive(Default)]
+struct Names {
+    labels: HashMap<String, String>,
+    label_count: HashMap<String, usize>,
+    registers: HashMap<String, usize>,
+    vector_registers: HashMap<String, usize>,
+}
+
+impl Names {
+    fn label(&mut self, id: &str) -> String {
+        if let Some(l) = self.labels.get(id) {
+            return l.clone();
+        }
+        // The prefix tells the kind of label, e.g., `.LBB` or `.LCPI`:
+        let prefix = match id.find("anon.") {
+            Some(i) => &id[..i + 5],
+            None => id
+                .find(|c: char| c.is_ascii_digit())
+                .map_or(id, |i| &id[..i]),
+        };
+        let count = self.label_count.entry(prefix.to_string()).or_insert(0);
+        let l = format!("{}_{}", prefix.trim_end_matches('.'), count);
+        *count += 1;
+        self.labels.insert(id.to_string(), l.clone());
+        l
+    }
+
+    fn register(
+        &mut self,
+        family: &str,
+        suffix: &str,
+        vector: bool,
+    ) -> String {
+        let (map, kind) = if vector {
+            (&mut self.vector_registers, "V")
+        } else {
+            (&mut self.registers, "R")
+        };
+        let len = map.len();
+        let n = *map.entry(family.to_string()).or_insert(len);
+        format!("{}{}{}", kind, n, suffix)
+    }
+}
+
+/// x86 general purpose registers: 64, 32, 16, and 8 bits.
+const X86_REGISTERS: &[[&str; 4]] = &[
+    ["rax", "eax", "ax", "al"],
+    ["rbx", "ebx", "bx", "bl"],
+    ["rcx", "ecx", "cx", "cl"],
+    ["rdx", "edx", "dx", "dl"],
+    ["rsi", "esi", "si", "sil"],
+    ["rdi", "edi", "di", "dil"],
+    ["rbp", "ebp", "bp", "bpl"],
+];
+
+/// Family of the register `r`, the suffix of its width, and whether it is
+/// a vector register. The stack and instruction pointers are not included.
+fn register(r: &str) -> Option<(String, &'static str, bool)> {
+    const SUFFIXES: [&str; 4] = ["", "d", "w", "b"];
+    for regs in X86_REGISTERS {
+        if let Some(i) = regs.iter().position(|x| *x == r) {
+            return Some((regs[0].to_string(), SUFFIXES[i], false));
+        }
+    }
+    if let ["ah" | "bh" | "ch" | "dh"] = [r] {
+        return Some((format!("r{}x", &r[..1]), "h", false));
+    }
+    let (kind, rest) = r.split_at(r.find(|c: char| c.is_ascii_digit())?);
+    let (n, suffix) = rest.split_at(
+        rest.find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len()),
+    );
+    let n = n.to_string();
+    match (kind, suffix) {
+        // x86 r8-r15 and ARM r0-r15:
+        ("r", "") => Some((n, "", false)),
+        ("r", "d") => Some((n, "d", false)),
+        ("r", "w") => Some((n, "w", false)),
+        ("r", "b") => Some((n, "b", false)),
+        ("xmm", "") => Some((n, "x", true)),
+        ("ymm", "") => Some((n, "y", true)),
+        ("zmm", "") => Some((n, "z", true)),
+        // AArch64:
+        ("x", "") => Some((n, "", false)),
+        ("w", "") => Some((n, "w", false)),
+        ("v", "") => Some((n, "", true)),
+        ("q", "") => Some((n, "q", true)),
+        ("d", "") => Some((n, "d", true)),
+        ("s", "") => Some((n, "s", true)),
+        ("h", "") => Some((n, "h", true)),
+        ("b", "") => Some((n, "b", true)),
+        _ => None,
+    }
+}
+
+/// Is `id` a label local to the assembly file, e.g., `.LBB13_7`?
+fn is_local_label(id: &str) -> bool {
+    id.starts_with(".L")
+        || id.starts_with("l_")
+        || (id.starts_with('L') && id.chars().any(|c| c.is_ascii_digit()))
+}
+
+/// Normalizes a token of an instruction argument.
+fn token(t: &str, names: &mut Names, keep_registers: bool) -> String {
+    if is_local_label(t) {
+        return names.label(t);
+    }
+    if t.starts_with("_ZN") || t.starts_with("__ZN") || t.starts_with("_R") {
+        return format!("{:#}", ::rustc_demangle::demangle(t));
+    }
+    if !keep_registers {
+        if let Some((family, suffix, vector)) = register(t) {
+            return names.register(&family, suffix, vector);
+        }
+    }
+    t.to_string()
+}
+
+/// Normalizes the tokens of an instruction argument.
+fn argument(arg: &str, names: &mut Names, keep_registers: bool) -> String {
+    let is_token = |c: char| c.is_alphanumeric() || "_.$".contains(c);
+    let mut result = String::with_capacity(arg.len());
+    let mut rest = arg;
+    while !rest.is_empty() {
+        let end = if rest.starts_with(is_token) {
+            rest.find(|c| !is_token(c)).unwrap_or(rest.len())
+        } else {
+            rest.find(is_token).unwrap_or(rest.len())
+        };
+        let (t, r) = rest.split_at(end);
+        if t.starts_with(is_token) {
+            result.push_str(&token(t, names, keep_registers));
+        } else {
+            result.push_str(t);
+        }
+        rest = r;
+    }
+    result
+}
+
+/// Labels and instructions of the `function`, with the local labels
+/// renumbered and the registers renamed by order of appearance.
+pub fn normalize(function: &Function, keep_registers: bool) -> Vec<String> {
+    let mut names = Names::default();
+    let mut lines = Vec::new();
+    for s in &function.statements {
+        match s {
+            Statement::Label(l) => {
+                if l.id.contains("Lcfi")
+                    || l.id.contains("Ltmp")
+                    || l.id.contains("Lfunc_end")
+                    || l.id == function.label
+                {
+                    continue;
+                }
+                lines.push(format!("{}:", names.label(&l.id)));
+            }
+            Statement::Instruction(i) => {
+                let args = i
+                    .args
+                    .iter()
+                    .map(|a| argument(a, &mut names, keep_registers))
+                    .collect::<Vec<_>>();
+                let line = format!("    {} {}", i.instr, args.join(", "));
+                lines.push(line.trim_end().to_string());
+            }
+            _ => {}
+        }
+    }
+    lines
+}
+
+/// Lines of the LLVM IR of a function, with the numbered values, basic
+/// blocks, attribute groups, and metadata renumbered by order of appearance.
+pub fn normalize_ir(lines: &[String]) -> Vec<String> {
+    let mut names = Names::default();
+    let is_token = |c: char| c.is_alphanumeric() || "_.".contains(c);
+    let is_numbered = |t: &str| {
+        let n = t.trim_start_matches(|c: char| !c.is_ascii_digit());
+        !n.is_empty() && n.chars().all(|c| c.is_ascii_digit())
+    };
+    lines
+        .iter()
+        .map(|line| {
+            let mut result = String::with_capacity(line.len());
+            let mut rest = line.as_str();
+            let mut sigil = false;
+            while !rest.is_empty() {
+                let end = if rest.starts_with(is_token) {
+                    rest.find(|c| !is_token(c)).unwrap_or(rest.len())
+                } else {
+                    rest.find(is_token).unwrap_or(rest.len())
+                };
+                let (t, r) = rest.split_at(end);
+                // Values (`%5`, `%_12`, `%bb3`), attribute groups (`#3`),
+                // metadata (`!12`), and basic block labels (`bb3:`):
+                let renumber = t.starts_with(is_token)
+                    && is_numbered(t)
+                    && ((sigil
+                        && (t.starts_with(|c: char| c.is_ascii_digit())
+                            || t.starts_with('_')
+                            || t.starts_with("bb")))
+                        || (t.starts_with("bb") && r.starts_with(':')));
+                if renumber {
+                    result.push_str(&names.label(t));
+                } else {
+                    result.push_str(t);
+                }
+                sigil = t.ends_with(&['%', '!', '#'][..]);
+                rest = r;
+            }
+            result
+        })
+        .collect()
+}
+
+/// Longest common subsequence diff of the lines `a` and `b`.
+pub fn diff(a: &[String], b: &[String]) -> Vec<Edit> {
+    // The common prefix and suffix do not need the quadratic table:
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (n, m) = (a.len() - prefix - suffix, b.len() - prefix - suffix);
+
+    // lcs[i][j]: length of the LCS of a[prefix + i..] and b[prefix + j..]
+    let mut lcs = vec![vec![0_u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if a[prefix + i] == b[prefix + j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits = (0..prefix).map(|i| Edit::Same(i, i)).collect::<Vec<_>>();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && a[prefix + i] == b[prefix + j] {
+            edits.push(Edit::Same(prefix + i, prefix + j));
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            edits.push(Edit::Removed(prefix + i));
+            i += 1;
+        } else {
+            edits.push(Edit::Added(prefix + j));
+            j += 1;
+        }
+    }
+    edits.extend(
+        (0..suffix).map(|k| Edit::Same(prefix + n + k, prefix + m + k)),
+    );
+    edits
+}
+
+/// Groups the `edits` into hunks with `context` unchanged lines around the
+/// changes.
+pub fn hunks(edits: &[Edit], context: usize) -> Vec<::std::ops::Range<usize>> {
+    let changed = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| !matches!(e, Edit::Same(..)))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let mut hunks = Vec::<(usize, usize)>::new();
+    for i in changed {
+        let start = i.saturating_sub(context);
+        let end = (i + context + 1).min(edits.len());
+        match hunks.last_mut() {
+            Some(h) if start <= h.1 => h.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    hunks.into_iter().map(|(s, e)| s..e).collect()
+}
+
+/// Prints the unified diff of the lines `a` and `b` of the sides with the
+/// `labels`.
+pub fn print(
+    a: &[String],
+    b: &[String],
+    labels: (&str, &str),
+    context: usize,
+) {
+    use std::io::Write;
+    use termcolor::{Color, ColorSpec, WriteColor};
+
+    let bufwtr = crate::display::stdout_writer();
+    let mut buffer = bufwtr.buffer();
+    let color = |c| {
+        let mut spec = ColorSpec::new();
+        spec.set_fg(c);
+        spec
+    };
+    let (removed, added, hunk, plain) = (
+        color(Some(Color::Red)),
+        color(Some(Color::Green)),
+        color(Some(Color::Cyan)),
+        color(None),
+    );
+
+    writeln!(&mut buffer, "--- {}", labels.0).unwrap();
+    writeln!(&mut buffer, "+++ {}", labels.1).unwrap();
+    let edits = diff(a, b);
+    for h in hunks(&edits, context) {
+        // Lines of each side before the hunk, and in the hunk:
+        let count = |edits: &[Edit]| {
+            edits.iter().fold((0, 0), |(l, r), e| match e {
+                Edit::Same(..) => (l + 1, r + 1),
+                Edit::Removed(_) => (l + 1, r),
+                Edit::Added(_) => (l, r + 1),
+            })
+        };
+        let (before_left, before_right) = count(&edits[..h.start]);
+        let (left, right) = count(&edits[h.clone()]);
+        // Empty ranges start at the line before them:
+        let start = |before, len| if len == 0 { before } else { before + 1 };
+        buffer.set_color(&hunk).unwrap();
+        writeln!(
+            &mut buffer,
+            "@@ -{},{} +{},{} @@",
+            start(before_left, left),
+            left,
+            start(before_right, right),
+            right
+        )
+        .unwrap();
+        for e in &edits[h] {
+            let (spec, sign, line) = match *e {
+                Edit::Same(i, _) => (&plain, ' ', &a[i]),
+                Edit::Removed(i) => (&removed, '-', &a[i]),
+                Edit::Added(j) => (&added, '+', &b[j]),
+            };
+            buffer.set_color(spec).unwrap();
+            writeln!(&mut buffer, "{}{}", sign, line).unwrap();
+        }
+    }
+    buffer.set_color(&plain).unwrap();
+    crate::pager::write(buffer.as_slice());
+}
+
+/// Builds the project and returns the normalized assembly or LLVM IR of
+/// the function at `path`, if it is found.
+fn function(
+    path: &str,
+    keep_registers: bool,
+) -> Result<Option<Vec<String>>, Error> {
+    let files = crate::build::project();
+    let target = TargetInfo::new_from_target();
+    let o = { (*opts.read()).clone() };
+    Ok(match o {
+        Options::Asm(_) => {
+            match crate::asm::parse_files(&files, Some(path), &target)? {
+                ParseResult::Found(function, _) => {
+                    Some(normalize(&function, keep_registers))
+                }
+                ParseResult::NotFound(_) => None,
+            }
+        }
+        Options::LlvmIr(_) => {
+            crate::llvmir::function(&files, Some(path), &target)?
+                .ok()
+                .map(|lines| normalize_ir(&lines))
+        }
+    })
+}
+
+/// Builds the project with the options `o`, at the git revision of its
+/// `--base` option if any, and returns the normalized function at `path`.
+fn build(
+    o: &Options,
+    label: &str,
+    path: &str,
+    keep_registers: bool,
+) -> Result<Vec<String>, Error> {
+    *opts.write() = o.clone();
+    let lines = match opts.base() {
+        Some(rev) => {
+            // The artifacts of both builds have the same names, so the
+            // base is built into a directory of its own:
+            let target_dir =
+                crate::target::root_directory().join("cargo-asm-base");
+            let worktree = crate::worktree::Worktree::new(&rev)?;
+            worktree.run(&target_dir, || function(path, keep_registers))??
+        }
+        None => function(path, keep_registers)?,
+    };
+    match lines {
+        Some(lines) => Ok(lines),
+        None => {
+            crate::display::write_error(&format!(
+                "could not find function at path \"{}\" in the build of {}.\n",
+                path, label
+            ));
+            ::std::process::exit(1);
+        }
+    }
+}
+
+/// Path of the function to compare.
+fn path(o: &Options) -> String {
+    let path = match o {
+        Options::Asm(o) => o.path.clone(),
+        Options::LlvmIr(o) => o.path.clone(),
+    };
+    path.unwrap_or_else(|| {
+        crate::display::write_error(
+            "comparing two builds requires the path of a function.\n",
+        );
+        ::std::process::exit(1);
+    })
+}
+
+/// Runs `cargo asm diff`.
+pub fn run(d: &Diff) -> Result<(), Error> {
+    let (left, right) =
+        (Options::Asm(d.left.clone()), Options::Asm(d.right.clone()));
+    let path = path(&left);
+    let labels = (
+        format!("left: {}", d.args.left),
+        format!("right: {}", d.args.right),
+    );
+    let keep_registers = d.args.keep_registers;
+    let left = build(&left, &labels.0, &path, keep_registers)?;
+    let right = build(&right, &labels.1, &path, keep_registers)?;
+    print(&left, &right, (&labels.0, &labels.1), d.args.context);
+    Ok(())
+}
+
+/// Compares the function built at the git revision `rev` of the `--base`
+/// option of `o` with the function built from the working tree.
+pub fn run_base(o: &Options, rev: &str) -> Result<(), Error> {
+    let path = path(o);
+    let mut working_tree = o.clone();
+    match working_tree {
+        Options::Asm(ref mut o) => o.base = None,
+        Options::LlvmIr(ref mut o) => o.base = None,
+    }
+    let labels = (rev.to_string(), "working tree".to_string());
+    let left = build(o, &labels.0, &path, false)?;
+    let right = build(&working_tree, &labels.1, &path, false)?;
+    print(&left, &right, (&labels.0, &labels.1), 3);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_arguments() {
+        let mut names = Names::default();
+        let mut arg = |a| argument(a, &mut names, false);
+        assert_eq!(arg("qword ptr [rsp + 8]"), "qword ptr [rsp + 8]");
+        assert_eq!(arg("rdi"), "R0");
+        assert_eq!(arg("esi"), "R1d");
+        assert_eq!(arg("dword ptr [rdi + 4*rcx]"), "dword ptr [R0 + 4*R2]");
+        assert_eq!(arg("%r8b"), "%R3b");
+        assert_eq!(arg("xmm1"), "V0x");
+        assert_eq!(arg(".LBB13_7"), ".LBB_0");
+        assert_eq!(arg(".LBB13_2"), ".LBB_1");
+        assert_eq!(arg(".LBB13_7"), ".LBB_0");
+        assert_eq!(
+            arg("qword ptr [rip + .LCPI4_0]"),
+            "qword ptr [rip + .LCPI_0]"
+        );
+        assert_eq!(arg("w9"), "R4w");
+        assert_eq!(arg("x9"), "R4");
+    }
+
+    #[test]
+    fn normalize_llvm_ir() {
+        let s =
+            |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let ir = s(&[
+            "start:",
+            "  %_5 = add i32 %x, 7, !dbg !42",
+            "  br label %bb3",
+            "bb3:",
+            "  ret i32 %_5 #12",
+        ]);
+        assert_eq!(
+            normalize_ir(&ir),
+            s(&[
+                "start:",
+                "  %__0 = add i32 %x, 7, !dbg !_0",
+                "  br label %bb_0",
+                "bb_0:",
+                "  ret i32 %__0 #_1",
+            ])
+        );
+    }
+
+    #[test]
+    fn unified_diff() {
+        let s =
+            |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let a = s(&["a", "b", "c", "d", "e", "f", "g"]);
+        let b = s(&["a", "c", "d", "x", "e", "f", "g"]);
+        let edits = diff(&a, &b);
+        assert_eq!(
+            edits,
+            vec![
+                Edit::Same(0, 0),
+                Edit::Removed(1),
+                Edit::Same(2, 1),
+                Edit::Same(3, 2),
+                Edit::Added(3),
+                Edit::Same(4, 4),
+                Edit::Same(5, 5),
+                Edit::Same(6, 6),
+            ]
+        );
+        assert_eq!(hunks(&edits, 0), vec![1..2, 4..5]);
+        assert_eq!(hunks(&edits, 1), vec![0..6]);
+    }
+}
@@ -5,65 +5,222 @@ use serde_derive::Serialize;
 
 /// Formatting of Rust source code:
 #[derive(Clone, Serialize)]
-struct Rust {
-    line: String,
-    path: ::std::path::PathBuf,
-    loc: asm::ast::Loc,
+pub(crate) struct Rust {
+    pub line: String,
+    /// Indentation of the line in the source file.
+    #[serde(skip)]
+    pub indent: String,
+    pub path: ::std::path::PathBuf,
+    pub loc: asm::ast::Loc,
 }
 
 impl Rust {
     fn new(
-        line: String,
+        line: &str,
         path: ::std::path::PathBuf,
         loc: asm::ast::Loc,
     ) -> Self {
-        Self { line, path, loc }
+        let trimmed = line.trim_start();
+        Self {
+            line: trimmed.to_string(),
+            indent: line[..line.len() - trimmed.len()].replace('\t', "    "),
+            path,
+            loc,
+        }
     }
 }
 
 /// Type of node to display
 #[derive(Serialize)]
-enum Kind {
+pub(crate) enum Kind {
     Asm(asm::ast::Statement),
     Rust(Rust),
+    /// Run of code inlined from `origin`, a std or third-party module.
+    Folded {
+        instructions: usize,
+        origin: String,
+    },
+    /// The following statements are inlined from this function, if any.
+    Inlined(Option<String>),
 }
 
-/// Prints `kind` using `opts`.
-#[allow(clippy::items_after_statements)]
-fn write_output(
-    kind: &Kind,
-    function: &asm::ast::Function,
-    target: &TargetInfo,
-) {
-    // Filter out what to print:
+/// How to print the code inlined from std and third-party crates.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Foreign {
+    Show,
+    /// Folds the runs of code into a summary line.
+    Collapse,
+    Hide,
+}
+
+impl ::std::str::FromStr for Foreign {
+    type Err = String;
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "show" => Ok(Foreign::Show),
+            "collapse" => Ok(Foreign::Collapse),
+            "hide" => Ok(Foreign::Hide),
+            v => Err(format!(
+                "\"{}\" is not a valid std mode. Try \"show\", \"collapse\", or \"hide\"",
+                v
+            )),
+        }
+    }
+}
+
+/// Layout of the Rust code and the assembly.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Layout {
+    /// The Rust lines are printed before their assembly.
+    Interleaved,
+    /// The Rust lines are printed on the left of their assembly.
+    SideBySide,
+}
+
+impl ::std::str::FromStr for Layout {
+    type Err = String;
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "interleaved" => Ok(Layout::Interleaved),
+            "side-by-side" => Ok(Layout::SideBySide),
+            v => Err(format!(
+                "\"{}\" is not a valid layout. Try \"interleaved\" or \"side-by-side\"",
+                v
+            )),
+        }
+    }
+}
+
+/// Output format of the functions.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Format {
+    Text,
+    /// Self-contained HTML page.
+    Html,
+}
+
+impl ::std::str::FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "html" => Ok(Format::Html),
+            v => Err(format!(
+                "\"{}\" is not a valid format. Try \"text\" or \"html\"",
+                v
+            )),
+        }
+    }
+}
+
+/// Below this terminal width the side-by-side layout falls back to the
+/// interleaved one.
+const SIDE_BY_SIDE_MIN_WIDTH: usize = 80;
+
+/// Width of the terminal: `COLUMNS` if set, otherwise the size of the
+/// terminal, otherwise 120.
+fn terminal_width() -> usize {
+    if let Some(w) =
+        ::std::env::var("COLUMNS").ok().and_then(|w| w.parse().ok())
+    {
+        return w;
+    }
+    ::crossterm::terminal::size()
+        .map(|(w, _)| usize::from(w))
+        .unwrap_or(120)
+}
+
+/// Returns true if `kind` is printed.
+pub(crate) fn is_visible(kind: &Kind) -> bool {
     match kind {
         Kind::Asm(ref a) => {
             use crate::asm::ast::Statement::*;
             match a {
-                Comment(_) if !opts.print_comments() => return,
-                Directive(_) if !opts.print_directives() => return,
+                Comment(_) => opts.print_comments(),
+                Directive(_) => opts.print_directives(),
                 Label(ref l) => {
-                    if l.id.contains("Lcfi")
+                    !(l.id.contains("Lcfi")
                         || l.id.contains("Ltmp")
-                        || l.id.contains("Lfunc_end")
-                    {
-                        return;
-                    }
+                        || l.id.contains("Lfunc_end"))
                 }
-                _ => {}
-            }
-        }
-        Kind::Rust(_) => {
-            if !opts.rust() {
-                return;
+                Instruction(_) => true,
             }
         }
+        Kind::Rust(_) => opts.rust(),
+        Kind::Folded { .. } => true,
+        Kind::Inlined(_) => false,
+    }
+}
+
+/// Column on the left of the assembly: the Rust code of the side-by-side
+/// layout, or the functions that the assembly was inlined from.
+struct Column<'a> {
+    text: &'a str,
+    width: usize,
+    color: termcolor::Color,
+}
+
+impl<'a> Column<'a> {
+    fn new(text: &'a str, width: usize, color: termcolor::Color) -> Self {
+        Self { text, width, color }
+    }
+}
+
+/// Writes the `column`, and the separator.
+fn write_column(mut buffer: &mut termcolor::Buffer, column: &Column) {
+    use std::io::Write;
+    use termcolor::{ColorSpec, WriteColor};
+    let mut color = ColorSpec::new();
+    color
+        .set_intense(true)
+        .set_fg(Some(column.color))
+        .set_bold(true);
+    buffer.set_color(&color).unwrap();
+    write!(&mut buffer, "{: <1$}", column.text, column.width).unwrap();
+    buffer.set_color(&ColorSpec::new()).unwrap();
+    write!(&mut buffer, " │ ").unwrap();
+}
+
+/// Writer of the buffers of the output to stdout, which are printed with
+/// `crate::pager::write`.
+pub(crate) fn stdout_writer() -> termcolor::BufferWriter {
+    termcolor::BufferWriter::stdout(if opts.use_colors() {
+        termcolor::ColorChoice::Always
+    } else {
+        termcolor::ColorChoice::Never
+    })
+}
+
+/// Writer of the errors and warnings to stderr.
+fn stderr_writer() -> termcolor::BufferWriter {
+    termcolor::BufferWriter::stderr(
+        if opts.use_colors_for(&::std::io::stderr()) {
+            termcolor::ColorChoice::Always
+        } else {
+            termcolor::ColorChoice::Never
+        },
+    )
+}
+
+/// Prints `kind` using `opts`, after the `columns`. The statements that
+/// are `inlined` from other functions are not part of the function.
+#[allow(clippy::items_after_statements)]
+fn write_output(
+    kind: &Kind,
+    function: &asm::ast::Function,
+    target: &TargetInfo,
+    columns: &[Column],
+    inlined: bool,
+) {
+    if !is_visible(kind) {
+        return;
     }
 
     // Is the current code part of the main function?
     let part_of_main_function = match kind {
-        Kind::Asm(ref a) => is_stmt_in_function(function, a),
+        Kind::Asm(ref a) => !inlined && is_stmt_in_function(function, a),
         Kind::Rust(ref r) => is_rust_in_function(function, r),
+        Kind::Folded { .. } | Kind::Inlined(_) => false,
     };
 
     let indent = match kind {
@@ -87,22 +244,21 @@ fn write_output(
                 5
             }
         }
+        Kind::Folded { .. } | Kind::Inlined(_) => 5,
     };
     let indent = (0..indent).map(|_| " ").collect::<String>();
 
     use std::io::Write;
-    use termcolor::{
-        Buffer, BufferWriter, Color, ColorChoice, ColorSpec, WriteColor,
-    };
+    use termcolor::{Buffer, Color, ColorSpec, WriteColor};
 
-    let bufwtr = if opts.use_colors() {
-        BufferWriter::stdout(ColorChoice::Auto)
-    } else {
-        BufferWriter::stdout(ColorChoice::Never)
-    };
+    let bufwtr = stdout_writer();
     let mut buffer = bufwtr.buffer();
     buffer.set_color(&ColorSpec::new()).unwrap();
 
+    for column in columns {
+        write_column(&mut buffer, column);
+    }
+
     // Write the indentation:
     write!(&mut buffer, "{}", indent).unwrap();
 
@@ -193,6 +349,16 @@ fn write_output(
                     if !i.args.is_empty() {
                         write!(&mut buffer, " {}", i.args.join(", ")).unwrap();
                     }
+                    if let Some(ref data) = i.data {
+                        buffer.set_color(&comment_color).unwrap();
+                        write!(
+                            &mut buffer,
+                            "  {} {}",
+                            target.comment_delimiters()[0],
+                            truncate(data, 60)
+                        )
+                        .unwrap();
+                    }
                     if opts.debug_mode() {
                         debug_mode_format(&mut buffer, i.rust_loc());
                     }
@@ -217,16 +383,108 @@ fn write_output(
                 .unwrap();
             }
         }
+        Kind::Folded {
+            instructions,
+            ref origin,
+        } => {
+            buffer.set_color(&comment_color).unwrap();
+            write!(
+                &mut buffer,
+                "[{} instruction{} from {}]",
+                instructions,
+                if *instructions == 1 { "" } else { "s" },
+                origin
+            )
+            .unwrap();
+        }
+        Kind::Inlined(_) => {}
     }
 
     writeln!(&mut buffer).unwrap();
-    bufwtr.print(&buffer).unwrap();
+
+    // Show the jump table of indirect jumps, grouping the entries by target:
+    if let Kind::Asm(asm::ast::Statement::Instruction(ref i)) = kind {
+        for (label, entries) in i.jump_table() {
+            for column in columns {
+                write_column(
+                    &mut buffer,
+                    &Column {
+                        text: "",
+                        ..*column
+                    },
+                );
+            }
+            buffer.set_color(&ColorSpec::new()).unwrap();
+            write!(
+                &mut buffer,
+                "{}    {} => ",
+                indent,
+                entries_text(&entries)
+            )
+            .unwrap();
+            buffer.set_color(&label_color).unwrap();
+            writeln!(&mut buffer, "{}", label).unwrap();
+        }
+    }
+
+    crate::pager::write(buffer.as_slice());
+}
+
+/// Entries of a jump table, e.g., `0 | 2`.
+pub(crate) fn entries_text(entries: &[usize]) -> String {
+    entries
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+/// Truncates `s` to at most `len` characters.
+pub(crate) fn truncate(s: &str, len: usize) -> String {
+    if s.chars().count() <= len {
+        return s.to_string();
+    }
+    let mut s = s.chars().take(len - 1).collect::<String>();
+    s.push('…');
+    s
+}
+
+/// Prints the data objects referenced by the function.
+fn write_data(function: &asm::ast::Function, target: &TargetInfo) {
+    use std::io::Write;
+    use termcolor::{Color, ColorSpec, WriteColor};
+
+    let mut label_color = ColorSpec::new();
+    label_color
+        .set_intense(true)
+        .set_fg(Some(Color::Green))
+        .set_bold(true);
+
+    let bufwtr = stdout_writer();
+    let mut buffer = bufwtr.buffer();
+    for d in &function.data {
+        writeln!(&mut buffer).unwrap();
+        buffer.set_color(&label_color).unwrap();
+        write!(&mut buffer, "{}:", d.id).unwrap();
+        buffer.set_color(&ColorSpec::new()).unwrap();
+        writeln!(
+            &mut buffer,
+            "  {} {}",
+            target.comment_delimiters()[0],
+            truncate(&d.describe(), 60)
+        )
+        .unwrap();
+        for v in &d.values {
+            writeln!(&mut buffer, " {}", v).unwrap();
+        }
+    }
+    crate::pager::write(buffer.as_slice());
 }
 
 fn format_function_name(function: &asm::ast::Function) -> String {
-    if function.file.is_some() && function.loc.is_some() {
+    if function.file.is_some() && own_loc(function).is_some() {
         if let Some(ref file) = &function.file {
-            if let Some(ref loc) = &function.loc {
+            if let Some(ref loc) = own_loc(function) {
                 return format!(
                     "{} ({}:{})",
                     function.id,
@@ -243,17 +501,19 @@ fn format_function_name(function: &asm::ast::Function) -> String {
     }
 }
 
+/// Location of the function `f` itself: its declaration, or its first
+/// location if its debugging information has not been read.
+pub(crate) fn own_loc(f: &asm::ast::Function) -> Option<asm::ast::Loc> {
+    f.decl.or(f.loc)
+}
+
 /// Returns true if the statement is in the function. It returns true if the
 /// question cannot be answered.
-fn is_stmt_in_function(
+pub(crate) fn is_stmt_in_function(
     f: &asm::ast::Function,
     stmt: &asm::ast::Statement,
 ) -> bool {
-    let function_file_index = if let Some(loc) = f.loc {
-        Some(loc.file_index)
-    } else {
-        None
-    };
+    let function_file_index = own_loc(f).map(|l| l.file_index);
 
     if let Some(function_file_index) = function_file_index {
         if let Some(loc) = stmt.rust_loc() {
@@ -266,12 +526,11 @@ fn is_stmt_in_function(
 
 /// Returns true if the rust code belongs to the function `f`. It returns true
 /// if the question cannot be answered.
-fn is_rust_in_function(f: &asm::ast::Function, rust: &Rust) -> bool {
-    let function_file_index = if let Some(loc) = f.loc {
-        Some(loc.file_index)
-    } else {
-        None
-    };
+pub(crate) fn is_rust_in_function(
+    f: &asm::ast::Function,
+    rust: &Rust,
+) -> bool {
+    let function_file_index = own_loc(f).map(|l| l.file_index);
 
     if let Some(function_file_index) = function_file_index {
         return rust.loc.file_index == function_file_index;
@@ -279,7 +538,7 @@ fn is_rust_in_function(f: &asm::ast::Function, rust: &Rust) -> bool {
     true
 }
 
-fn make_path_relative(path: &mut ::std::path::PathBuf) {
+pub(crate) fn make_path_relative(path: &mut ::std::path::PathBuf) {
     // The path might already be relative:
     if !path.is_absolute() {
         return;
@@ -326,20 +585,31 @@ fn make_paths_relative(
     }
 }
 
+/// Prints the `function`. The `origins` are the functions that its
+/// statements are inlined from.
 pub fn print(
     function: &mut asm::ast::Function,
     mut rust: rust::Files,
     target: &TargetInfo,
+    origins: Option<Vec<Option<String>>>,
 ) {
+    let width = terminal_width();
+    // The source of the function is read before its path is made relative:
+    let source = if opts.rust()
+        && opts.layout() == Layout::SideBySide
+        && width >= SIDE_BY_SIDE_MIN_WIDTH
+    {
+        function_source(function, &rust)
+    } else {
+        None
+    };
     make_paths_relative(function, &mut rust);
 
     if !opts.rust() {
         // When emitting assembly without Rust code, print the requested
         // function path (the first function line will not be emitted):
         use std::io::Write;
-        use termcolor::{
-            BufferWriter, Color, ColorChoice, ColorSpec, WriteColor,
-        };
+        use termcolor::{Color, ColorSpec, WriteColor};
 
         let mut rust_color = ColorSpec::new();
         rust_color
@@ -347,26 +617,605 @@ pub fn print(
             .set_fg(Some(Color::Red))
             .set_bold(true);
 
-        let bufwtr = if opts.use_colors() {
-            BufferWriter::stdout(ColorChoice::Auto)
-        } else {
-            BufferWriter::stdout(ColorChoice::Never)
-        };
+        let bufwtr = stdout_writer();
         let mut buffer = bufwtr.buffer();
         buffer.set_color(&rust_color).unwrap();
         writeln!(&mut buffer, "{}:", format_function_name(function)).unwrap();
-        bufwtr.print(&buffer).unwrap();
+        crate::pager::write(buffer.as_slice());
     }
 
-    let output = merge_rust_and_asm(function, &rust);
+    let mut output = merge_rust_and_asm(function, &rust);
+    if let Some(ref origins) = origins {
+        output = mark_inlined(output, origins);
+    }
+    let output = fold_foreign(output, function, &rust, opts.std());
 
-    for o in &output {
-        write_output(o, function, &target);
+    let mut margin = Margin::new(&output);
+    if let Some(ref source) = source {
+        write_side_by_side(
+            &output,
+            function,
+            target,
+            width,
+            source,
+            &mut margin,
+        );
+    } else {
+        for o in &output {
+            if margin.update(o) || !is_visible(o) {
+                continue;
+            }
+            let text = margin.text(o);
+            let columns = margin.column(&text).into_iter().collect::<Vec<_>>();
+            write_output(o, function, target, &columns, margin.inlined());
+        }
+    }
+    if opts.data() {
+        write_data(function, target);
     }
     return;
 }
 
-fn merge_rust_and_asm(
+/// Source of the function: the file of its location and its lines.
+struct Source {
+    file_index: usize,
+    /// Line of the function in the file.
+    first: usize,
+    lines: Vec<String>,
+}
+
+/// Reads the source of the `function`, if it has debug information.
+fn function_source(
+    function: &asm::ast::Function,
+    rust: &rust::Files,
+) -> Option<Source> {
+    let loc = own_loc(function)?;
+    let contents = rust::contents(&rust.files.get(&loc.file_index)?.ast)
+        .map_err(|e| debug!("cannot read the source: {}", e))
+        .ok()?;
+    Some(Source {
+        file_index: loc.file_index,
+        first: loc.file_line.max(1),
+        lines: contents
+            .lines()
+            .map(|l| l.trim_end().replace('\t', "    "))
+            .collect(),
+    })
+}
+
+/// Row of the side-by-side layout: the line of the source that it starts,
+/// if any, and the statement on its right, with the function that the
+/// statement is inlined from.
+struct Row<'a> {
+    line: Option<usize>,
+    statement: Option<(Option<&'a str>, &'a Kind)>,
+}
+
+/// Rows of the statements of `output`, in program order, next to the lines
+/// of the file `file_index`. An instruction starts the line of its location
+/// whenever the line changes, so lines are repeated when the code jumps
+/// back, while code inlined from other files stays on the current line.
+/// The lines without code get their own row the first time that the code
+/// steps over them, starting at the line `first` of the function.
+fn align_to_source(
+    output: &[Kind],
+    file_index: usize,
+    first: usize,
+) -> Vec<Row<'_>> {
+    use crate::asm::ast::Statement;
+    let line_of = |o: &Kind| match o {
+        Kind::Asm(Statement::Instruction(ref i)) => match i.rust_loc() {
+            // Line 0 is code without a line, e.g., generated by the
+            // compiler:
+            Some(loc) if loc.file_index == file_index && loc.file_line > 0 => {
+                Some(loc.file_line)
+            }
+            _ => None,
+        },
+        _ => None,
+    };
+    let with_code = output
+        .iter()
+        .filter_map(line_of)
+        .collect::<::std::collections::BTreeSet<_>>();
+    let mut printed = ::std::collections::BTreeSet::new();
+    let mut without_code = |lines: ::std::ops::Range<usize>| {
+        lines
+            .filter(|n| !with_code.contains(n) && printed.insert(*n))
+            .map(|n| Row {
+                line: Some(n),
+                statement: None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut rows = Vec::new();
+    let mut line = None;
+    let mut origin = None;
+    for o in output {
+        match o {
+            Kind::Inlined(ref f) => {
+                origin = f.as_deref();
+                continue;
+            }
+            Kind::Rust(_) => continue,
+            _ => {}
+        }
+        let start = line_of(o).filter(|&n| Some(n) != line);
+        if let Some(n) = start {
+            rows.extend(without_code(line.map_or(first, |l| l + 1)..n));
+            line = start;
+        }
+        rows.push(Row {
+            line: start,
+            statement: Some((origin, o)),
+        });
+    }
+    if let Some(&last) = with_code.iter().next_back() {
+        rows.extend(without_code(line.map_or(first, |l| l + 1)..last));
+    }
+    rows
+}
+
+/// Prints the assembly of the function on the right, in program order, and
+/// the lines of its `source` that the assembly comes from on the left.
+fn write_side_by_side(
+    output: &[Kind],
+    function: &asm::ast::Function,
+    target: &TargetInfo,
+    width: usize,
+    source: &Source,
+    margin: &mut Margin,
+) {
+    use termcolor::Color;
+
+    // Half of the width minus the separator:
+    let left_width = (width - 3) / 2;
+    // Prints a Rust line without assembly:
+    let write_rust_row = |columns: &[Column]| {
+        use std::io::Write;
+        let bufwtr = stdout_writer();
+        let mut buffer = bufwtr.buffer();
+        for column in columns {
+            write_column(&mut buffer, column);
+        }
+        writeln!(&mut buffer).unwrap();
+        crate::pager::write(buffer.as_slice());
+    };
+
+    let mut origin = None;
+    for row in align_to_source(output, source.file_index, source.first) {
+        let left = row.line.map_or_else(String::new, |n| {
+            let text = source.lines.get(n - 1).map_or("", String::as_str);
+            truncate(&format!("{: >5} {}", n, text), left_width)
+        });
+        if let Some((o, kind)) = row.statement {
+            if o != origin {
+                margin.update(&Kind::Inlined(o.map(str::to_string)));
+                origin = o;
+            }
+            if is_visible(kind) {
+                let text = margin.text(kind);
+                let mut columns =
+                    vec![Column::new(&left, left_width, Color::Red)];
+                columns.extend(margin.column(&text));
+                write_output(
+                    kind,
+                    function,
+                    target,
+                    &columns,
+                    margin.inlined(),
+                );
+                continue;
+            }
+            if row.line.is_none() {
+                continue;
+            }
+        }
+        let mut columns = vec![Column::new(&left, left_width, Color::Red)];
+        columns.extend(margin.column(""));
+        write_rust_row(&columns);
+    }
+}
+
+/// Number of instructions attributed to each line of the file `file_index`,
+/// and number of instructions inlined from other files. The instructions
+/// before the first location are attributed to the line `first`.
+fn line_counts(
+    function: &asm::ast::Function,
+    file_index: usize,
+    first: usize,
+) -> (::std::collections::BTreeMap<usize, usize>, usize) {
+    let mut counts = ::std::collections::BTreeMap::new();
+    let mut inlined = 0;
+    let mut line = Some(first);
+    for stmt in &function.statements {
+        match stmt.rust_loc() {
+            // Line 0 is code without a line, e.g., generated by the
+            // compiler:
+            Some(loc) if loc.file_line == 0 => {}
+            Some(loc) if loc.file_index == file_index => {
+                line = Some(loc.file_line)
+            }
+            Some(_) => line = None,
+            None => {}
+        }
+        if let asm::ast::Statement::Instruction(_) = stmt {
+            match line {
+                Some(line) => *counts.entry(line).or_insert(0) += 1,
+                None => inlined += 1,
+            }
+        }
+    }
+    (counts, inlined)
+}
+
+/// Width of the bars of `annotate_source`.
+const BAR_WIDTH: usize = 10;
+
+/// Bar of `count` relative to `max`, at least one character long if
+/// `count` is not zero.
+fn bar(count: usize, max: usize) -> String {
+    let len = (count * BAR_WIDTH).checked_div(max).unwrap_or(0);
+    "█".repeat(len.max(usize::from(count > 0)))
+}
+
+/// Prints the Rust source of the function, with the number of instructions
+/// of each line and a bar colored by the share of the most expensive line.
+pub fn annotate_source(
+    function: &mut asm::ast::Function,
+    mut rust: rust::Files,
+) -> Result<(), crate::error::Error> {
+    use std::io::Write;
+    use termcolor::{Color, ColorSpec, WriteColor};
+
+    let (file_index, first) = match own_loc(function) {
+        Some(loc) if rust.files.contains_key(&loc.file_index) => {
+            (loc.file_index, loc.file_line.max(1))
+        }
+        _ => {
+            write_warning(&format!(
+                "cannot annotate the source of {}: the function has no debug information\n",
+                function.id
+            ));
+            return Ok(());
+        }
+    };
+    let source = rust::contents(&rust.files[&file_index].ast)?;
+    let (counts, inlined) = line_counts(function, file_index, first);
+    let last = counts.keys().next_back().map_or(first, |&l| l.max(first));
+    let max = counts.values().copied().max().unwrap_or(0);
+    make_paths_relative(function, &mut rust);
+
+    let mut rust_color = ColorSpec::new();
+    rust_color
+        .set_intense(true)
+        .set_fg(Some(Color::Red))
+        .set_bold(true);
+    let heat_color = |count: usize| {
+        let mut color = ColorSpec::new();
+        let fg = if count * 3 >= max * 2 {
+            Color::Red
+        } else if count * 3 >= max {
+            Color::Yellow
+        } else {
+            Color::Green
+        };
+        color.set_intense(true).set_fg(Some(fg));
+        color
+    };
+
+    let bufwtr = stdout_writer();
+    let mut buffer = bufwtr.buffer();
+    buffer.set_color(&rust_color).unwrap();
+    writeln!(&mut buffer, "{}:", format_function_name(function)).unwrap();
+    for (idx, line) in source
+        .lines()
+        .enumerate()
+        .skip(first - 1)
+        .take(last + 1 - first)
+    {
+        let count = counts.get(&(idx + 1)).copied().unwrap_or(0);
+        buffer.set_color(&ColorSpec::new()).unwrap();
+        write!(&mut buffer, "{: >5} ", idx + 1).unwrap();
+        buffer.set_color(&heat_color(count)).unwrap();
+        if count == 0 {
+            write!(&mut buffer, "{: >6} {: <2$}", "", "", BAR_WIDTH).unwrap();
+        } else {
+            write!(
+                &mut buffer,
+                "{: >6} {: <2$}",
+                count,
+                bar(count, max),
+                BAR_WIDTH
+            )
+            .unwrap();
+        }
+        buffer.set_color(&ColorSpec::new()).unwrap();
+        writeln!(&mut buffer, " │ {}", line.trim_end().replace('\t', "    "))
+            .unwrap();
+    }
+    writeln!(
+        &mut buffer,
+        "{: >12} instructions, {} inlined from other files",
+        counts.values().sum::<usize>() + inlined,
+        inlined
+    )
+    .unwrap();
+    crate::pager::write(buffer.as_slice());
+    Ok(())
+}
+
+/// Module of the `path` of a std or third-party file, e.g.,
+/// `core::iter::adapters::map`, or `None` for the other files. The files of
+/// third-party crates are in the cargo registry, in cargo git checkouts, or
+/// in paths that contain one of the `third_party` patterns.
+pub(crate) fn foreign_origin(
+    path: &::std::path::Path,
+    third_party: &[String],
+) -> Option<String> {
+    let components = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    let std_crates = ["libcore", "libstd", "liballoc"];
+    let is_std = path.starts_with("/rustc")
+        || components
+            .iter()
+            .any(|c| c == "rustlib" || std_crates.contains(&c.as_str()));
+    let is_third_party = components
+        .windows(2)
+        .any(|w| w[0] == ".cargo" && (w[1] == "registry" || w[1] == "git"))
+        || third_party
+            .iter()
+            .any(|p| path.to_string_lossy().contains(p.as_str()));
+    if !is_std && !is_third_party {
+        return None;
+    }
+
+    // The crate is the directory that contains `src`, e.g., `core` or
+    // `serde-1.0.100`, and the modules follow it:
+    let src = match components.iter().rposition(|c| c == "src") {
+        Some(i) if i > 0 => i,
+        _ => return Some(path.display().to_string()),
+    };
+    let mut krate = components[src - 1].as_str();
+    if std_crates.contains(&krate) {
+        krate = &krate[3..];
+    }
+    if let Some(i) = krate.rfind('-') {
+        if krate[i + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+            krate = &krate[..i];
+        }
+    }
+    let mut module = vec![krate.replace('-', "_")];
+    for c in &components[src + 1..] {
+        let c = c.trim_end_matches(".rs");
+        if !["mod", "lib", "main"].contains(&c) {
+            module.push(c.to_string());
+        }
+    }
+    Some(module.join("::"))
+}
+
+/// Common module of the `origins`, e.g., `core::slice::...` for
+/// `core::slice::iter` and `core::slice::index`, or the crates if there is
+/// none.
+pub(crate) fn common_origin(origins: &[String]) -> String {
+    let paths = origins
+        .iter()
+        .map(|o| o.split("::").collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let first = &paths[0];
+    let common = (0..first.len())
+        .take_while(|&i| paths.iter().all(|p| p.get(i) == first.get(i)))
+        .count();
+    if common == 0 {
+        let mut crates = Vec::new();
+        for p in &paths {
+            if !crates.contains(&p[0]) {
+                crates.push(p[0]);
+            }
+        }
+        return crates.join(", ");
+    }
+    let mut origin = first[..common].join("::");
+    if paths.iter().any(|p| p.len() > common) {
+        origin.push_str("::...");
+    }
+    origin
+}
+
+/// Folds or hides, depending on the `mode`, the runs of `output` inlined
+/// from std and third-party crates. The printed labels end the runs, and the
+/// directives, comments, and other labels in a run are part of it.
+pub(crate) fn fold_foreign(
+    output: Vec<Kind>,
+    function: &asm::ast::Function,
+    rust: &rust::Files,
+    mode: Foreign,
+) -> Vec<Kind> {
+    use crate::asm::ast::Statement;
+    if mode == Foreign::Show {
+        return output;
+    }
+    let third_party = opts.third_party();
+    let own_file = own_loc(function).map(|l| l.file_index);
+    let origin = |file_index: usize| {
+        if Some(file_index) == own_file {
+            return None;
+        }
+        foreign_origin(rust.paths.get(&file_index)?, &third_party)
+    };
+    // Origins and number of instructions of a run:
+    type Run = (Vec<String>, usize);
+    // Ends the `run`, followed by the last `Kind::Inlined` of the run:
+    let end_run =
+        |folded: &mut Vec<Kind>, run: Option<Run>, inlined: Option<Kind>| {
+            if let Some((origins, instructions)) = run {
+                if mode == Foreign::Collapse && instructions > 0 {
+                    folded.push(Kind::Folded {
+                        instructions,
+                        origin: common_origin(&origins),
+                    });
+                }
+            }
+            folded.extend(inlined);
+        };
+
+    let mut folded = Vec::new();
+    let mut run: Option<Run> = None;
+    let mut inlined = None;
+    for kind in output {
+        if let Kind::Inlined(_) = kind {
+            if run.is_some() {
+                inlined = Some(kind);
+            } else {
+                folded.push(kind);
+            }
+            continue;
+        }
+        let kind_origin = match kind {
+            Kind::Asm(Statement::Label(_))
+            | Kind::Folded { .. }
+            | Kind::Inlined(_) => None,
+            Kind::Asm(ref a) => {
+                a.rust_loc().and_then(|l| origin(l.file_index))
+            }
+            Kind::Rust(ref r) => origin(r.loc.file_index),
+        };
+        let in_run = kind_origin.is_some()
+            || (run.is_some()
+                && match kind {
+                    Kind::Asm(Statement::Directive(_))
+                    | Kind::Asm(Statement::Comment(_)) => true,
+                    Kind::Asm(Statement::Label(_)) => !is_visible(&kind),
+                    _ => false,
+                });
+        if !in_run {
+            end_run(&mut folded, run.take(), inlined.take());
+            folded.push(kind);
+            continue;
+        }
+        let (origins, instructions) =
+            run.get_or_insert_with(|| (Vec::new(), 0));
+        if let Some(o) = kind_origin {
+            if !origins.contains(&o) {
+                origins.push(o);
+            }
+        }
+        if let Kind::Asm(Statement::Instruction(_)) = kind {
+            *instructions += 1;
+        }
+    }
+    end_run(&mut folded, run, inlined);
+    folded
+}
+
+/// Inserts a `Kind::Inlined` before the statements of `output` whose
+/// `origins` differ from the ones of the previous statements.
+pub(crate) fn mark_inlined(
+    output: Vec<Kind>,
+    origins: &[Option<String>],
+) -> Vec<Kind> {
+    let mut marked = Vec::with_capacity(output.len());
+    let mut origins = origins.iter();
+    let mut current = None;
+    for kind in output {
+        if let Kind::Asm(_) = kind {
+            let origin = origins.next().cloned().flatten();
+            if origin != current {
+                // The marker precedes the Rust lines of the statement:
+                let rust = marked
+                    .iter()
+                    .rev()
+                    .take_while(|k| matches!(k, Kind::Rust(_)))
+                    .count();
+                marked.insert(
+                    marked.len() - rust,
+                    Kind::Inlined(origin.clone()),
+                );
+                current = origin;
+            }
+        }
+        marked.push(kind);
+    }
+    marked
+}
+
+/// Truncates `s` to its last `len` characters at most.
+fn truncate_start(s: &str, len: usize) -> String {
+    let count = s.chars().count();
+    if count <= len {
+        return s.to_string();
+    }
+    let mut r = "…".to_string();
+    r.extend(s.chars().skip(count + 1 - len));
+    r
+}
+
+/// Margin with the functions that the assembly is inlined from, shown on
+/// the first row of each of their runs.
+struct Margin {
+    /// The width of the margin, if there is inlined code.
+    width: Option<usize>,
+    origin: Option<String>,
+    shown: bool,
+}
+
+impl Margin {
+    const MAX_WIDTH: usize = 32;
+
+    fn new(output: &[Kind]) -> Self {
+        let width = output
+            .iter()
+            .filter_map(|o| match o {
+                Kind::Inlined(Some(ref f)) => Some(f.chars().count()),
+                _ => None,
+            })
+            .max()
+            .map(|w| w.min(Self::MAX_WIDTH));
+        Self {
+            width,
+            origin: None,
+            shown: false,
+        }
+    }
+
+    /// Updates the margin if `kind` is a `Kind::Inlined`.
+    fn update(&mut self, kind: &Kind) -> bool {
+        if let Kind::Inlined(ref origin) = kind {
+            self.origin = origin.clone();
+            self.shown = false;
+            return true;
+        }
+        false
+    }
+
+    /// Text of the margin of `kind`.
+    fn text(&mut self, kind: &Kind) -> String {
+        use crate::asm::ast::Statement;
+        match (kind, &self.origin, self.width) {
+            (Kind::Asm(Statement::Label(_)), _, _) => String::new(),
+            (Kind::Asm(_), Some(ref f), Some(width)) if !self.shown => {
+                self.shown = true;
+                truncate_start(f, width)
+            }
+            _ => String::new(),
+        }
+    }
+
+    fn column<'a>(&self, text: &'a str) -> Option<Column<'a>> {
+        Some(Column::new(text, self.width?, termcolor::Color::Magenta))
+    }
+
+    /// Returns true if the current statements are inlined.
+    fn inlined(&self) -> bool {
+        self.origin.is_some()
+    }
+}
+
+pub(crate) fn merge_rust_and_asm(
     function: &asm::ast::Function,
     rust_files: &rust::Files,
 ) -> Vec<Kind> {
@@ -375,7 +1224,7 @@ fn merge_rust_and_asm(
         if let Some(rust_loc) = stmt.rust_loc() {
             if let Some(rust_line) = rust_files.line(rust_loc).map(|line| {
                 let path = rust_files.file_path(rust_loc).unwrap();
-                Rust::new(line, path, rust_loc)
+                Rust::new(&line, path, rust_loc)
             }) {
                 let rl = rust_line.line.trim().to_string();
                 if !rl.starts_with("//") {
@@ -416,18 +1265,14 @@ fn merge_rust_and_asm(
 
 pub fn write_error(msg: &str) {
     use std::io::Write;
-    use termcolor::{BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
+    use termcolor::{Color, ColorSpec, WriteColor};
     let mut error_color = ColorSpec::new();
     error_color
         .set_intense(true)
         .set_fg(Some(Color::Red))
         .set_bold(true);
 
-    let bufwtr = if opts.use_colors() {
-        BufferWriter::stderr(ColorChoice::Auto)
-    } else {
-        BufferWriter::stderr(ColorChoice::Never)
-    };
+    let bufwtr = stderr_writer();
     let mut buffer = bufwtr.buffer();
     buffer.set_color(&error_color).unwrap();
     write!(&mut buffer, "[ERROR]: ").unwrap();
@@ -436,6 +1281,24 @@ pub fn write_error(msg: &str) {
     bufwtr.print(&buffer).unwrap();
 }
 
+pub fn write_warning(msg: &str) {
+    use std::io::Write;
+    use termcolor::{Color, ColorSpec, WriteColor};
+    let mut warning_color = ColorSpec::new();
+    warning_color
+        .set_intense(true)
+        .set_fg(Some(Color::Yellow))
+        .set_bold(true);
+
+    let bufwtr = stderr_writer();
+    let mut buffer = bufwtr.buffer();
+    buffer.set_color(&warning_color).unwrap();
+    write!(&mut buffer, "[WARNING]: ").unwrap();
+    buffer.set_color(&ColorSpec::new()).unwrap();
+    write!(&mut buffer, "{}", msg).unwrap();
+    bufwtr.print(&buffer).unwrap();
+}
+
 pub fn to_json(
     function: &asm::ast::Function,
     rust_files: &rust::Files,
@@ -449,3 +1312,131 @@ pub fn to_json(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn foreign_origins() {
+        let origins = |o: &[&str]| {
+            common_origin(&o.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+        };
+        assert_eq!(
+            origins(&["core::slice::iter", "core::slice::index"]),
+            "core::slice::..."
+        );
+        assert_eq!(origins(&["core::ptr", "core::ptr"]), "core::ptr");
+        assert_eq!(
+            origins(&["core::ptr", "alloc::vec", "core::mem"]),
+            "core, alloc"
+        );
+
+        let origin = |path: &str| foreign_origin(Path::new(path), &[]);
+        assert_eq!(
+            origin("/rustc/abc/library/core/src/iter/adapters/map.rs"),
+            Some("core::iter::adapters::map".to_string())
+        );
+        assert_eq!(
+            origin("/home/u/.cargo/registry/src/index/rustc-demangle-0.1.21/src/lib.rs"),
+            Some("rustc_demangle".to_string())
+        );
+        assert_eq!(origin("/home/u/foo/src/bar/mod.rs"), None);
+        assert_eq!(
+            foreign_origin(
+                Path::new("/home/u/vendor/foo/src/bar/mod.rs"),
+                &["vendor/".to_string()]
+            ),
+            Some("foo::bar".to_string())
+        );
+    }
+
+    #[test]
+    fn side_by_side_rows() {
+        use crate::asm::ast::Statement;
+        let target = TargetInfo::new_from_triple(
+            "x86_64-unknown-linux-gnu".to_string(),
+        );
+        let asm = r#"
+	.text
+	.section	.text._ZN3foo3bar17h0123456789abcdefE,"ax",@progbits
+	.globl	_ZN3foo3bar17h0123456789abcdefE
+	.type	_ZN3foo3bar17h0123456789abcdefE,@function
+_ZN3foo3bar17h0123456789abcdefE:
+	.cfi_startproc
+	.loc	1 3 0
+	push	rbx
+	.loc	1 5 9
+	mov	eax, 1
+	.loc	2 10 5
+	add	eax, 2
+.LBB0_1:
+	.loc	1 4 5
+	cmp	eax, 3
+	.loc	1 0 5
+	jne	.LBB0_1
+	.loc	1 7 1
+	pop	rbx
+	ret
+.Lfunc_end0:
+	.size	_ZN3foo3bar17h0123456789abcdefE, .Lfunc_end0-_ZN3foo3bar17h0123456789abcdefE
+	.cfi_endproc
+
+	.file	1 "/comp/dir" "src/lib.rs"
+	.file	2 "/rustc/abc" "library/core/src/num/mod.rs"
+"#;
+        let path = ::std::env::temp_dir()
+            .join(format!("cargo-asm-display-{}.s", ::std::process::id()));
+        ::std::fs::write(&path, asm).unwrap();
+        let module = crate::asm::parse::module(&path, &target).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+
+        let mut output = module.functions[0]
+            .statements
+            .iter()
+            .cloned()
+            .map(Kind::Asm)
+            .collect::<Vec<_>>();
+        // `add` is inlined from `core`:
+        output.insert(5, Kind::Inlined(Some("core::num::add".to_string())));
+        output.insert(7, Kind::Inlined(None));
+
+        // The rows in program order, without the directives:
+        let rows = align_to_source(&output, 1, 3)
+            .into_iter()
+            .filter_map(|row| {
+                let statement = match row.statement {
+                    Some((
+                        origin,
+                        Kind::Asm(Statement::Instruction(ref i)),
+                    )) => Some((origin, i.instr.as_str())),
+                    Some((origin, Kind::Asm(Statement::Label(ref l)))) => {
+                        Some((origin, l.id.as_str()))
+                    }
+                    Some(_) => return None,
+                    None => None,
+                };
+                Some((row.line, statement))
+            })
+            .collect::<Vec<_>>();
+        let core = Some("core::num::add");
+        assert_eq!(
+            rows,
+            [
+                (Some(3), Some((None, "push"))),
+                (Some(5), Some((None, "mov"))),
+                (None, Some((core, "add"))),
+                (None, Some((None, ".LBB0_1"))),
+                // The loop jumps back to line 4:
+                (Some(4), Some((None, "cmp"))),
+                (None, Some((None, "jne"))),
+                // Line 6 has no code:
+                (Some(6), None),
+                (Some(7), Some((None, "pop"))),
+                (None, Some((None, "ret"))),
+                (None, Some((None, ".Lfunc_end0"))),
+            ]
+        );
+    }
+}
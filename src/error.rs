@@ -0,0 +1,141 @@
+//! Error handling.
+
+use std::path::PathBuf;
+
+/// Errors that can happen while parsing the output of the compiler.
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to read a file.
+    Io {
+        path: PathBuf,
+        err: ::std::io::Error,
+    },
+    /// Malformed or unexpected input in an assembly, LLVM-IR, or Rust file.
+    Parse {
+        file: Option<PathBuf>,
+        line: Option<usize>,
+        text: String,
+        msg: String,
+    },
+    /// A `.loc` directive refers to a file index for which no `.file`
+    /// directive exists.
+    FileTable {
+        file: PathBuf,
+        function: String,
+        index: usize,
+    },
+    /// An external command failed.
+    Command { msg: String },
+    /// Functions of `cargo asm check` do not meet their expectations.
+    Check { failed: usize },
+    /// The function exceeds the budgets of `--max-instructions`, etc.
+    Budget { exceeded: Vec<String> },
+}
+
+/// Result type used by the parsers.
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+impl Error {
+    /// Parse error for the raw `text` without file or line context.
+    ///
+    /// The context is added by the caller via `Error::at`.
+    pub fn parse<T: Into<String>, M: Into<String>>(text: T, msg: M) -> Self {
+        Error::Parse {
+            file: None,
+            line: None,
+            text: text.into(),
+            msg: msg.into(),
+        }
+    }
+
+    /// Adds the `file` and `line` context to a parse error if it is missing.
+    pub fn at(self, path: &::std::path::Path, line_no: usize) -> Self {
+        match self {
+            Error::Parse {
+                file,
+                line,
+                text,
+                msg,
+            } => Error::Parse {
+                file: file.or_else(|| Some(path.to_path_buf())),
+                line: line.or(Some(line_no)),
+                text,
+                msg,
+            },
+            e => e,
+        }
+    }
+
+    pub fn io(path: &::std::path::Path, err: ::std::io::Error) -> Self {
+        Error::Io {
+            path: path.to_path_buf(),
+            err,
+        }
+    }
+
+    /// Exit code of the process when it terminates with this error.
+    ///
+    /// Exit code `1` is reserved for usage errors (e.g. function not found).
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io { .. } => 2,
+            Error::Parse { .. } => 3,
+            Error::FileTable { .. } => 4,
+            Error::Command { .. } => 5,
+            Error::Check { .. } | Error::Budget { .. } => 6,
+        }
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match self {
+            Error::Io { path, err } => {
+                write!(f, "failed to read file {}: {}", path.display(), err)
+            }
+            Error::Parse {
+                file,
+                line,
+                text,
+                msg,
+            } => {
+                match (file, line) {
+                    (Some(file), Some(line)) => {
+                        write!(f, "{}:{}: ", file.display(), line)?
+                    }
+                    (Some(file), None) => write!(f, "{}: ", file.display())?,
+                    _ => {}
+                }
+                write!(f, "{}", msg)?;
+                if !text.is_empty() {
+                    write!(f, "\n  {}", text)?;
+                }
+                Ok(())
+            }
+            Error::FileTable {
+                file,
+                function,
+                index,
+            } => write!(
+                f,
+                "{}: no .file directive with index {} found for function {}",
+                file.display(),
+                index,
+                function
+            ),
+            Error::Command { msg } => write!(f, "{}", msg),
+            Error::Check { failed } => {
+                write!(f, "{} function(s) failed the checks", failed)
+            }
+            Error::Budget { exceeded } => {
+                write!(f, "the function exceeds its budget:")?;
+                for e in exceeded {
+                    write!(f, "\n  {}", e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl ::std::error::Error for Error {}
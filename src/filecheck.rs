@@ -0,0 +1,235 @@
+//! FileCheck-style directives in the comments of the Rust sources, e.g.:
+//!
+//! ```text
+//! // CHECK: imul
+//! // CHECK-NOT: call
+//! // CHECK-NEXT: ret
+//! pub fn foo(x: u32) -> u32 { ... }
+//! ```
+
+/// Kind of a directive.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Kind {
+    /// `CHECK`: a later line contains the pattern.
+    Check,
+    /// `CHECK-NEXT`: the line after the previous match contains the
+    /// pattern.
+    Next,
+    /// `CHECK-NOT`: no line between the previous and the next match
+    /// contains the pattern.
+    Not,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Check => "CHECK",
+            Kind::Next => "CHECK-NEXT",
+            Kind::Not => "CHECK-NOT",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Directive {
+    pub kind: Kind,
+    pub pattern: String,
+    /// Line of the directive in the source file (1-based).
+    pub line: usize,
+}
+
+/// Directives above a function.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Block {
+    /// Name of the function, e.g., `foo` for `pub fn foo()`.
+    pub function: String,
+    /// Line of the function in the source file (1-based).
+    pub line: usize,
+    pub directives: Vec<Directive>,
+}
+
+/// Directive of the source `line`, if any.
+fn directive(line: &str, line_no: usize) -> Option<Directive> {
+    let comment = line.trim_start().strip_prefix("//")?.trim_start();
+    let kinds = [
+        ("CHECK:", Kind::Check),
+        ("CHECK-NEXT:", Kind::Next),
+        ("CHECK-NOT:", Kind::Not),
+    ];
+    kinds.iter().find_map(|&(prefix, kind)| {
+        Some(Directive {
+            kind,
+            pattern: comment.strip_prefix(prefix)?.trim().to_string(),
+            line: line_no,
+        })
+    })
+}
+
+/// Name of the function declared in the source `line`, if any.
+fn function_name(line: &str) -> Option<&str> {
+    let mut rest = line;
+    loop {
+        let i = rest.find("fn ")?;
+        if i == 0 || rest[..i].ends_with(char::is_whitespace) {
+            let name = rest[i + 3..].trim_start();
+            let end = name
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(name.len());
+            return Some(&name[..end]).filter(|n| !n.is_empty());
+        }
+        rest = &rest[i + 3..];
+    }
+}
+
+/// Blocks of directives of the Rust `source`: each block belongs to the
+/// next function.
+pub fn blocks(source: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut directives = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        if let Some(d) = directive(line, i + 1) {
+            directives.push(d);
+        } else if !directives.is_empty() {
+            if let Some(name) = function_name(line) {
+                blocks.push(Block {
+                    function: name.to_string(),
+                    line: i + 1,
+                    directives: ::std::mem::take(&mut directives),
+                });
+            }
+        }
+    }
+    blocks
+}
+
+/// Removes the commas and collapses the whitespace, so that the patterns
+/// do not depend on how the arguments of the instructions are separated.
+fn canonical(s: &str) -> String {
+    s.replace(',', " ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Checks the `lines` of a function against the `directives`.
+pub fn check(
+    directives: &[Directive],
+    lines: &[String],
+) -> Result<(), String> {
+    let lines = lines.iter().map(|l| canonical(l)).collect::<Vec<_>>();
+    let error = |d: &Directive, msg: String| {
+        Err(format!(
+            "line {}: {}: {}: {}",
+            d.line,
+            d.kind.name(),
+            d.pattern,
+            msg
+        ))
+    };
+    // The `CHECK-NOT` directives must not match any of the lines between
+    // the previous match and `end`:
+    let check_nots = |nots: &mut Vec<&Directive>, start: usize, end: usize| {
+        for d in nots.drain(..) {
+            let pattern = canonical(&d.pattern);
+            if let Some(l) =
+                lines[start..end].iter().find(|l| l.contains(&pattern))
+            {
+                return error(d, format!("found \"{}\"", l));
+            }
+        }
+        Ok(())
+    };
+
+    let mut next = 0;
+    let mut matched = false;
+    let mut nots = Vec::new();
+    for d in directives {
+        let pattern = canonical(&d.pattern);
+        match d.kind {
+            Kind::Not => nots.push(d),
+            Kind::Check => {
+                let found =
+                    (next..lines.len()).find(|&i| lines[i].contains(&pattern));
+                let i = match found {
+                    Some(i) => i,
+                    None => return error(d, "not found".to_string()),
+                };
+                check_nots(&mut nots, next, i)?;
+                next = i + 1;
+                matched = true;
+            }
+            Kind::Next => {
+                if !matched {
+                    return error(d, "there is no previous match".to_string());
+                }
+                match lines.get(next) {
+                    Some(l) if l.contains(&pattern) => {}
+                    Some(l) => {
+                        return error(d, format!("the next line is \"{}\"", l))
+                    }
+                    None => {
+                        return error(d, "there is no next line".to_string())
+                    }
+                }
+                check_nots(&mut nots, next, next)?;
+                next += 1;
+            }
+        }
+    }
+    check_nots(&mut nots, next, lines.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directives() {
+        let source = r#"
+// CHECK: imul
+// CHECK-NEXT: ret
+#[inline(never)]
+pub fn foo(x: u32) -> u32 {
+    x * 7
+}
+
+/// CHECK-NOT: call
+// CHECK-NOT: call
+pub(crate) fn  bar() {}
+"#;
+        let blocks = blocks(source);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!((blocks[0].function.as_str(), blocks[0].line), ("foo", 5));
+        assert_eq!(blocks[0].directives[1].kind, Kind::Next);
+        assert_eq!((blocks[1].function.as_str(), blocks[1].line), ("bar", 11));
+        assert_eq!(blocks[1].directives.len(), 1);
+
+        let lines =
+            ["    lea eax, [rdi + 8*rdi]", "    imul eax, edi", "    ret"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>();
+        assert_eq!(check(&blocks[0].directives, &lines), Ok(()));
+        assert_eq!(check(&blocks[1].directives, &lines), Ok(()));
+        let d = |kind, pattern: &str| Directive {
+            kind,
+            pattern: pattern.to_string(),
+            line: 1,
+        };
+        assert_eq!(
+            check(&[d(Kind::Check, "lea eax, [rdi+8*rdi]")], &lines),
+            Err("line 1: CHECK: lea eax, [rdi+8*rdi]: not found".to_string())
+        );
+        assert!(
+            check(&[d(Kind::Check, "lea eax [rdi + 8*rdi]")], &lines).is_ok()
+        );
+        assert_eq!(
+            check(&[d(Kind::Not, "imul"), d(Kind::Check, "ret")], &lines),
+            Err("line 1: CHECK-NOT: imul: found \"imul eax edi\"".to_string())
+        );
+        assert!(
+            check(&[d(Kind::Check, "lea"), d(Kind::Next, "ret")], &lines)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,494 @@
+//! Self-contained HTML report of functions.
+
+use crate::asm::ast::{Directive, Function, Instruction, Statement};
+use crate::display::{self, Foreign, Kind};
+use crate::options::*;
+use crate::rust;
+use crate::target::TargetInfo;
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; }
+h2 { font-size: 1.1em; }
+h2 .loc { float: none; margin-left: 1em; }
+.code { font-family: monospace; white-space: pre; }
+.asm, .directive, .comment, .table { padding-left: 4ch; }
+.rust { color: #b22222; font-weight: bold; padding-left: 1ch; }
+.label { color: #2a7d2a; font-weight: bold; }
+.instr { color: #1f4fbf; font-weight: bold; }
+.jump { color: #2a7d2a; }
+.call { color: #b22222; }
+.directive, .comment, .data, .lineno { color: #777; }
+.loc { float: right; color: #999; text-decoration: none; }
+a:hover { text-decoration: underline; }
+details { border-left: 2px solid #ccc; margin-left: 2ch; padding-left: 1ch; }
+summary { color: #777; cursor: pointer; }
+:target { background: #ffffa0; }
+";
+
+/// Function of the report, and the functions that its statements are
+/// inlined from.
+struct Entry {
+    function: Function,
+    rust: rust::Files,
+    origins: Option<Vec<Option<String>>>,
+}
+
+/// HTML report of one or more functions. The calls between the functions
+/// of the report are links.
+#[derive(Default)]
+pub struct Report {
+    entries: Vec<Entry>,
+}
+
+/// Escapes the HTML special characters of `s`.
+fn escape(s: &str) -> String {
+    let mut r = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => r.push_str("&amp;"),
+            '<' => r.push_str("&lt;"),
+            '>' => r.push_str("&gt;"),
+            '"' => r.push_str("&quot;"),
+            '\'' => r.push_str("&#39;"),
+            c => r.push(c),
+        }
+    }
+    r
+}
+
+impl Report {
+    /// Adds the `function` with the Rust code of the `rust` files. The
+    /// `origins` are the functions that its statements are inlined from.
+    pub fn add(
+        &mut self,
+        function: &Function,
+        rust: rust::Files,
+        origins: Option<Vec<Option<String>>>,
+    ) {
+        self.entries.push(Entry {
+            function: function.clone(),
+            rust,
+            origins,
+        });
+    }
+
+    /// Index of the function `name` in the report, if any.
+    fn find(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|e| e.function.id == name)
+    }
+
+    /// The HTML page of the report.
+    pub fn to_html(&self, target: &TargetInfo) -> String {
+        let mut html = String::new();
+        let title = self
+            .entries
+            .first()
+            .map(|e| e.function.id.as_str())
+            .unwrap_or_default();
+        write!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>cargo asm: {}</title>\n<style>{}</style>\n</head>\n<body>\n",
+            escape(title),
+            STYLE
+        )
+        .unwrap();
+        if self.entries.len() > 1 {
+            html.push_str("<nav>\n<ul>\n");
+            for (n, e) in self.entries.iter().enumerate() {
+                writeln!(
+                    html,
+                    "<li><a href=\"#f{}\">{}</a></li>",
+                    n,
+                    escape(&e.function.id)
+                )
+                .unwrap();
+            }
+            html.push_str("</ul>\n</nav>\n");
+        }
+        for n in 0..self.entries.len() {
+            self.write_function(&mut html, n, target);
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    /// Writes the `n`-th function. The runs of code inlined from other
+    /// functions, or from std and third-party crates, are collapsible.
+    fn write_function(
+        &self,
+        html: &mut String,
+        n: usize,
+        target: &TargetInfo,
+    ) {
+        let entry = &self.entries[n];
+        let function = &entry.function;
+        write!(
+            html,
+            "<section id=\"f{}\">\n<h2>{}",
+            n,
+            escape(&function.id)
+        )
+        .unwrap();
+        if let (Some(ref file), Some(loc)) =
+            (&function.file, display::own_loc(function))
+        {
+            html.push_str(&loc_link(&file.path, loc.file_line, None));
+        }
+        html.push_str("</h2>\n<div class=\"code\">\n");
+
+        let mut output = display::merge_rust_and_asm(function, &entry.rust);
+        if let Some(ref origins) = entry.origins {
+            output = display::mark_inlined(output, origins);
+        }
+        let third_party = opts.third_party();
+        let own_file = display::own_loc(function).map(|l| l.file_index);
+        let foreign = |file_index: usize| {
+            if Some(file_index) == own_file {
+                return None;
+            }
+            display::foreign_origin(
+                entry.rust.paths.get(&file_index)?,
+                &third_party,
+            )
+        };
+        let labels = function
+            .statements
+            .iter()
+            .filter_map(|s| match s {
+                Statement::Label(ref l) => Some(l.id.as_str()),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+
+        // Origins, number of instructions, and HTML of a run:
+        let mut run: Option<(Vec<String>, usize, String)> = None;
+        let mut inlined = None;
+        for kind in &output {
+            let origin = match kind {
+                Kind::Inlined(ref origin) => {
+                    inlined = origin.clone();
+                    continue;
+                }
+                Kind::Asm(Statement::Label(_)) | Kind::Folded { .. } => None,
+                Kind::Asm(ref a) => inlined.clone().or_else(|| {
+                    a.rust_loc().and_then(|l| foreign(l.file_index))
+                }),
+                Kind::Rust(ref r) => {
+                    inlined.clone().or_else(|| foreign(r.loc.file_index))
+                }
+            };
+            let part_of_run = match kind {
+                Kind::Asm(Statement::Directive(_))
+                | Kind::Asm(Statement::Comment(_)) => true,
+                Kind::Asm(Statement::Label(_)) => !display::is_visible(kind),
+                _ => false,
+            };
+            let mut line = String::new();
+            self.write_kind(&mut line, n, kind, &labels, target);
+            match (origin, &mut run) {
+                (Some(origin), _) => {
+                    let (origins, instructions, lines) =
+                        run.get_or_insert_with(Default::default);
+                    if !origins.contains(&origin) {
+                        origins.push(origin);
+                    }
+                    if let Kind::Asm(Statement::Instruction(_)) = kind {
+                        *instructions += 1;
+                    }
+                    lines.push_str(&line);
+                }
+                (None, Some((_, _, ref mut lines))) if part_of_run => {
+                    lines.push_str(&line);
+                }
+                (None, _) => {
+                    write_run(html, run.take());
+                    html.push_str(&line);
+                }
+            }
+        }
+        write_run(html, run);
+        html.push_str("</div>\n</section>\n");
+    }
+
+    /// Writes the line of `kind` in the `n`-th function, if it is visible.
+    fn write_kind(
+        &self,
+        html: &mut String,
+        n: usize,
+        kind: &Kind,
+        labels: &HashSet<&str>,
+        target: &TargetInfo,
+    ) {
+        if !display::is_visible(kind) {
+            return;
+        }
+        let entry = &self.entries[n];
+        match kind {
+            Kind::Rust(ref r) => {
+                html.push_str("<div class=\"rust\">");
+                if display::is_rust_in_function(&entry.function, r) {
+                    write!(
+                        html,
+                        "<span class=\"lineno\">{:>5}</span> {}{}",
+                        r.loc.file_line,
+                        escape(&r.indent),
+                        escape(&r.line)
+                    )
+                    .unwrap();
+                } else {
+                    html.push_str(&escape(&r.line));
+                    html.push_str(&loc_link(&r.path, r.loc.file_line, None));
+                }
+                html.push_str("</div>\n");
+            }
+            Kind::Asm(Statement::Label(ref l)) => {
+                writeln!(
+                    html,
+                    "<div class=\"label\" id=\"f{}-{}\">{}:</div>",
+                    n,
+                    escape(&l.id),
+                    escape(&l.id)
+                )
+                .unwrap();
+            }
+            Kind::Asm(Statement::Directive(ref d)) => {
+                let d = match d {
+                    Directive::File(ref f) => {
+                        format!(".file {} \"{}\"", f.index, f.path.display())
+                    }
+                    Directive::Loc(ref l) => format!(
+                        ".loc {} {} {}",
+                        l.file_index, l.file_line, l.file_column
+                    ),
+                    Directive::Generic(ref g) => g.string.clone(),
+                };
+                writeln!(
+                    html,
+                    "<div class=\"directive\">{}</div>",
+                    escape(&d)
+                )
+                .unwrap();
+            }
+            Kind::Asm(Statement::Comment(ref c)) => {
+                writeln!(
+                    html,
+                    "<div class=\"comment\">{}</div>",
+                    escape(&c.string)
+                )
+                .unwrap();
+            }
+            Kind::Asm(Statement::Instruction(ref i)) => {
+                self.write_instruction(html, n, i, labels, target);
+            }
+            Kind::Folded { .. } | Kind::Inlined(_) => {}
+        }
+    }
+
+    /// Writes the instruction `i` of the `n`-th function: its jump targets
+    /// are links to the labels, and its calls are links to the functions of
+    /// the report.
+    fn write_instruction(
+        &self,
+        html: &mut String,
+        n: usize,
+        i: &Instruction,
+        labels: &HashSet<&str>,
+        target: &TargetInfo,
+    ) {
+        let label_link = |label: &str| {
+            format!(
+                "<a class=\"jump\" href=\"#f{}-{}\">{}</a>",
+                n,
+                escape(label),
+                escape(label)
+            )
+        };
+        let function_link = |f: usize, name: &str| {
+            format!("<a class=\"call\" href=\"#f{}\">{}</a>", f, escape(name))
+        };
+        let is_call = i.is_call(target);
+        let args = i
+            .args
+            .iter()
+            .enumerate()
+            .map(|(idx, arg)| {
+                if labels.contains(arg.as_str()) {
+                    return label_link(arg);
+                }
+                if let Some(f) = self.find(arg) {
+                    return function_link(f, arg);
+                }
+                // Symbols of the functions of the report within the
+                // argument, e.g., `[rip + _ZN...@GOTPCREL]`:
+                let mut html = escape(arg);
+                for symbol in crate::asm::parse::referenced_symbols(arg) {
+                    let name = crate::demangle::demangle(symbol, target);
+                    if let Some(f) = self.find(&name) {
+                        html = html.replace(symbol, &function_link(f, &name));
+                    }
+                }
+                if is_call && idx == 0 {
+                    html = format!("<span class=\"call\">{}</span>", html);
+                }
+                html
+            })
+            .collect::<Vec<_>>();
+
+        html.push_str("<div class=\"asm\">");
+        if args.is_empty() {
+            write!(html, "<span class=\"instr\">{}</span>", escape(&i.instr))
+                .unwrap();
+        } else {
+            write!(
+                html,
+                "<span class=\"instr\">{: <7}</span> {}",
+                escape(&i.instr),
+                args.join(", ")
+            )
+            .unwrap();
+        }
+        if let Some(ref data) = i.data {
+            write!(
+                html,
+                "  <span class=\"data\">{} {}</span>",
+                target.comment_delimiters()[0],
+                escape(&display::truncate(data, 60))
+            )
+            .unwrap();
+        }
+        if let Some(loc) = i.rust_loc() {
+            let rust = &self.entries[n].rust;
+            if let Some(path) = rust.paths.get(&loc.file_index) {
+                let line = rust.line(loc);
+                html.push_str(&loc_link(path, loc.file_line, line.as_deref()));
+            }
+        }
+        html.push_str("</div>\n");
+
+        // The jump table of indirect jumps, grouping the entries by target:
+        for (label, entries) in i.jump_table() {
+            writeln!(
+                html,
+                "<div class=\"table\">    {} =&gt; {}</div>",
+                display::entries_text(&entries),
+                label_link(label)
+            )
+            .unwrap();
+        }
+    }
+}
+
+/// Link to the `line` of the Rust file at `path`, showing the `source` of
+/// the line on hover.
+fn loc_link(
+    path: &::std::path::Path,
+    line: usize,
+    source: Option<&str>,
+) -> String {
+    let mut relative = path.to_path_buf();
+    display::make_path_relative(&mut relative);
+    let title = match source {
+        Some(source) => format!(" title=\"{}\"", escape(source.trim())),
+        None => String::new(),
+    };
+    let href = if path.is_absolute() && path.exists() {
+        format!(
+            " href=\"file://{}#L{}\"",
+            escape(&path.to_string_lossy()),
+            line
+        )
+    } else {
+        String::new()
+    };
+    format!(
+        "<a class=\"loc\"{}{}>{}:{}</a>",
+        href,
+        title,
+        escape(&relative.to_string_lossy()),
+        line
+    )
+}
+
+/// Writes the `run` as a collapsible section, which is open unless the
+/// std code is collapsed, or nothing if it is hidden.
+fn write_run(html: &mut String, run: Option<(Vec<String>, usize, String)>) {
+    let (origins, instructions, lines) = match run {
+        Some(run) => run,
+        None => return,
+    };
+    if instructions == 0 {
+        html.push_str(&lines);
+        return;
+    }
+    let open = match opts.std() {
+        Foreign::Show => " open",
+        Foreign::Collapse => "",
+        Foreign::Hide => return,
+    };
+    write!(
+        html,
+        "<details{}><summary>{} instruction{} from {}</summary>\n{}</details>\n",
+        open,
+        instructions,
+        if instructions == 1 { "" } else { "s" },
+        escape(&display::common_origin(&origins)),
+        lines
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links() {
+        let target = TargetInfo::new_from_triple(
+            "x86_64-unknown-linux-gnu".to_string(),
+        );
+        let asm = r#"
+	.section	.text._ZN1a1f17h0123456789abcdefE,"ax",@progbits
+	.globl	_ZN1a1f17h0123456789abcdefE
+	.type	_ZN1a1f17h0123456789abcdefE,@function
+_ZN1a1f17h0123456789abcdefE:
+	test	edi, edi
+	je	.LBB0_2
+	call	_ZN1a1g17h0123456789abcdefE
+.LBB0_2:
+	ret
+.Lfunc_end0:
+	.size	_ZN1a1f17h0123456789abcdefE, .Lfunc_end0-_ZN1a1f17h0123456789abcdefE
+
+	.section	.text._ZN1a1g17h0123456789abcdefE,"ax",@progbits
+	.globl	_ZN1a1g17h0123456789abcdefE
+	.type	_ZN1a1g17h0123456789abcdefE,@function
+_ZN1a1g17h0123456789abcdefE:
+	ret
+.Lfunc_end1:
+	.size	_ZN1a1g17h0123456789abcdefE, .Lfunc_end1-_ZN1a1g17h0123456789abcdefE
+"#;
+        let path = ::std::env::temp_dir()
+            .join(format!("cargo-asm-html-{}.s", ::std::process::id()));
+        ::std::fs::write(&path, asm).unwrap();
+        let module = crate::asm::parse::module(&path, &target).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+
+        let mut report = Report::default();
+        for f in &module.functions {
+            report.add(f, rust::Files::default(), None);
+        }
+        let labels = [".LBB0_2"].iter().cloned().collect::<HashSet<_>>();
+        let mut html = String::new();
+        for s in &module.functions[0].statements {
+            if let Statement::Instruction(ref i) = s {
+                report.write_instruction(&mut html, 0, i, &labels, &target);
+            }
+        }
+        assert!(html.contains("<a class=\"jump\" href=\"#f0-.LBB0_2\">"));
+        assert!(html.contains("<a class=\"call\" href=\"#f1\">a::g</a>"));
+        assert_eq!(escape("<a & \"b\">"), "&lt;a &amp; &quot;b&quot;&gt;");
+    }
+}
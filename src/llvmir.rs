@@ -1,43 +1,69 @@
+use crate::error::Error;
 use crate::options::*;
 use crate::target::TargetInfo;
 
 use log::debug;
 
-pub fn run(files: &[::std::path::PathBuf], target: &TargetInfo) {
-    let mut function_table: Option<Vec<String>> = None;
-
-    for f in files {
+/// Finds the LLVM IR of the function at `path` in the `files`. Returns the
+/// names of all functions if it is not found.
+#[allow(clippy::type_complexity)]
+pub fn function(
+    files: &[::std::path::PathBuf],
+    path: Option<&str>,
+    target: &TargetInfo,
+) -> Result<Result<Vec<String>, Vec<String>>, Error> {
+    // Scan the files concurrently until the function is found:
+    let results = crate::parallel::map_until(files, |f| {
         debug!("Scanning file: {:?}", f);
-        assert!(f.exists(), "path does not exist: {}", f.display());
-        let r = print_function(f, &target);
+        let r = find_function(f, path, target);
+        let done = match r {
+            Ok(Ok(_)) | Err(_) => true,
+            Ok(Err(_)) => false,
+        };
+        (r, done)
+    });
 
-        if r.is_ok() {
-            debug!("Function found, we are done!");
-            function_table = None;
-            break;
-        }
-        debug!("Function not found, appending all function names in the file to the table...");
-        let mut r = r.unwrap_err();
-        if let Some(ref mut function_table) = function_table {
-            function_table.append(&mut r);
-        } else {
-            function_table = Some(r);
+    let mut function_table = Vec::<String>::new();
+    for r in results {
+        match r? {
+            Ok(lines) => {
+                debug!("Function found, we are done!");
+                return Ok(Ok(lines));
+            }
+            Err(mut names) => {
+                debug!("Function not found, appending all function names in the file to the table...");
+                function_table.append(&mut names);
+            }
         }
     }
+    Ok(Err(function_table))
+}
 
-    if function_table.is_none() {
-        debug!("Function found!");
-        return;
-    }
+pub fn run(
+    files: &[::std::path::PathBuf],
+    target: &TargetInfo,
+) -> Result<(), Error> {
+    let mut function_table =
+        match function(files, opts.path().as_deref(), target)? {
+            Ok(lines) => {
+                for line in lines {
+                    crate::pager::print(format_args!("{}\n", line));
+                }
+                return Ok(());
+            }
+            Err(function_table) => function_table,
+        };
 
     debug!("Function not found. Showing functions in the table...");
-    let mut function_table = function_table.unwrap();
+    function_table.sort();
+    function_table.dedup();
 
     match opts.path() {
         None => {
             for f in function_table {
-                println!("{}", f);
+                crate::pager::print(format_args!("{}\n", f));
             }
+            Ok(())
         }
         Some(path) => {
             use edit_distance::edit_distance;
@@ -83,108 +109,173 @@ Tips:
     }
 }
 
-fn print_function(
-    file_name: &::std::path::PathBuf,
+/// Returns the mangled name of the function defined in the `define` line.
+fn function_name(line: &str) -> Option<&str> {
+    let first = line.find('@')?;
+    let last = line[first..].find('(')? + first;
+    Some(&line[first + 1..last])
+}
+
+/// Builds the index of the functions defined in the `lines` of `file_name`.
+///
+/// A function spans from its `define` line to the last `}` before the next
+/// function.
+fn index(
+    lines: &[String],
+    offsets: &[u64],
+    file_name: &::std::path::Path,
     target: &TargetInfo,
-) -> Result<(), Vec<String>> {
-    use std::io::BufRead;
-
-    let path = if let Some(path) = opts.path() {
-        path
-    } else {
-        "".to_owned()
-    };
-    let fh = ::std::fs::File::open(file_name).unwrap();
-    let file_buf = ::std::io::BufReader::new(&fh);
-
-    let line_iter = file_buf.lines();
-    let mut function_names: Vec<String> = Vec::new();
-    let mut function_lines: Option<Vec<String>> = None;
-    for line in line_iter {
-        let line = line.unwrap().trim().to_string();
-
-        if let Some(ref mut function_lines) = function_lines {
-            if line.starts_with("define") {
-                debug!("End of function");
-                break;
-            }
+) -> Result<Vec<crate::cache::Entry>, Error> {
+    let defines = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| l.starts_with("define"))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
 
-            debug!("    {:?}", line);
-            function_lines.push(line);
-        } else {
-            if !line.starts_with("define") {
-                continue;
-            }
+    let mut entries = Vec::new();
+    for (i, &start) in defines.iter().enumerate() {
+        let line = &lines[start];
+        let mangled_name = function_name(line).ok_or_else(|| {
+            let msg = "cannot parse the name of the function";
+            Error::parse(line.as_str(), msg).at(file_name, start + 1)
+        })?;
+        let next = defines.get(i + 1).cloned().unwrap_or(lines.len());
+        // Find last }
+        let end = lines[start..next]
+            .iter()
+            .rposition(|s| s == "}")
+            .map_or(next, |r| start + r + 1);
+        entries.push(crate::cache::Entry {
+            name: crate::demangle::demangle(mangled_name, target),
+            offset: offsets[start],
+            len: offsets[end] - offsets[start],
+            line: start,
+            calls: Vec::new(),
+            references: Vec::new(),
+            instructions: 0,
+            bytes: None,
+        });
+    }
+    Ok(entries)
+}
 
-            let first = line.find('@').unwrap();
-            let last = (&line[first..]).find('(').unwrap() + first;
-            assert!(
-                first < last,
-                "first: {:?}, last: {:?}, line:\n{:?}",
-                first,
-                last,
-                line
-            );
-            let mangled_name = &line[first + 1..last];
-            let demangled_name =
-                crate::demangle::demangle(&mangled_name, &target);
-            if demangled_name != path {
-                function_names.push(demangled_name);
-                continue;
+/// Returns the demangled lines of the function `path` if it is found in
+/// `file_name`, otherwise returns the names of all functions in the file.
+///
+/// If the file has been scanned before, the function is read directly using
+/// the index of the file.
+#[allow(clippy::type_complexity)]
+fn find_function(
+    file_name: &::std::path::Path,
+    path: Option<&str>,
+    target: &TargetInfo,
+) -> Result<Result<Vec<String>, Vec<String>>, Error> {
+    use crate::cache::Index;
+
+    if let Some(index) = Index::load(file_name, target) {
+        return match path.and_then(|path| index.find(path)) {
+            Some(entry) => {
+                let lines = index.read(entry)?;
+                demangle_function(&lines, file_name, target).map(Ok)
             }
-            function_lines =
-                Some(vec![line.replace(mangled_name, &demangled_name)]);
+            None => Ok(Err(index.names())),
+        };
+    }
+
+    let (lines, offsets) = crate::asm::parse::read_lines(file_name)?;
+    let entries = index(&lines, &offsets, file_name, target)?;
+    let entry = path.and_then(|path| entries.iter().find(|e| e.name == path));
+    let function_lines = entry.map(|entry| {
+        let end = offsets
+            .binary_search(&(entry.offset + entry.len))
+            .unwrap_or_else(|i| i);
+        &lines[entry.line..end]
+    });
+    let function_names = entries.iter().map(|e| e.name.clone()).collect();
+
+    let index = Index::new(
+        file_name,
+        target,
+        entries,
+        crate::asm::parse::FileTable::new(),
+        Vec::new(),
+    );
+    if let Some(index) = index {
+        index.store();
+    }
+
+    match function_lines {
+        Some(function_lines) => {
             debug!("Found function with path: {:?}", path);
-            continue;
+            demangle_function(function_lines, file_name, target).map(Ok)
         }
+        None => Ok(Err(function_names)),
     }
+}
 
-    if let Some(function_lines) = function_lines {
-        debug!("Function found! Displaying function...");
-        // Find last }
-        let r = function_lines.iter().rposition(|s| s.trim() == "}");
-        let r = r.unwrap_or(function_lines.len() - 1);
-        for line in &function_lines[0..r + 1] {
-            let mut demangled_line = String::new();
-            let mut start = 0;
-            while let Some(f) = &line[start..].find('"') {
-                if start == 0 {
-                    debug!("line to demangle: {}", line);
+/// Demangles the names in the `function_lines`.
+fn demangle_function(
+    function_lines: &[String],
+    file_name: &::std::path::Path,
+    target: &TargetInfo,
+) -> Result<Vec<String>, Error> {
+    debug!("Function found! Demangling function...");
+    let mut output = Vec::new();
+    for (line_idx, line) in function_lines.iter().enumerate() {
+        // Demangle the name of the function in the `define` line:
+        let line = match function_name(line) {
+            Some(mangled_name) if line_idx == 0 => line.replace(
+                mangled_name,
+                &crate::demangle::demangle(mangled_name, target),
+            ),
+            _ => line.clone(),
+        };
+        let mut demangled_line = String::new();
+        let mut start = 0;
+        while let Some(f) = &line[start..].find('"') {
+            if start == 0 {
+                debug!("line to demangle: {}", line);
+            }
+            debug!("s: {}, f: {}, dl: {}", start, f, demangled_line);
+            let f = f + start + 1;
+            let l = match line[f..].find('"') {
+                Some(l) => l + f,
+                None => {
+                    return Err(Error::Parse {
+                        file: Some(file_name.to_path_buf()),
+                        line: None,
+                        text: line.clone(),
+                        msg: "unterminated string in function".to_string(),
+                    })
                 }
-                debug!("s: {}, f: {}, dl: {}", start, f, demangled_line);
-                let f = f + start + 1;
-                let l = line[f..].find('"').unwrap() + f;
-                let mangled_name = &line[f..l];
-                let demangled_name = if mangled_name.ends_with(".exit") {
-                    let mut v = crate::demangle::demangle(
-                        &mangled_name[0..mangled_name.len() - 5],
-                        &target,
-                    );
-                    v += ".exit";
-                    v
-                } else {
-                    crate::demangle::demangle(&mangled_name, &target)
-                };
-                debug!(
-                    "  f: {}, l: {}, mn: {}, dm: {}",
-                    f, l, mangled_name, demangled_name
+            };
+            let mangled_name = &line[f..l];
+            let demangled_name = if mangled_name.ends_with(".exit") {
+                let mut v = crate::demangle::demangle(
+                    &mangled_name[0..mangled_name.len() - 5],
+                    target,
                 );
-                demangled_line += &line[start..f];
-                demangled_line += &demangled_name;
-                demangled_line.push('"');
-                start = l + 1;
-                debug!("  ns: {}, ndl: {}", start, demangled_line);
-            }
-            if demangled_line.is_empty() {
-                println!("{}", line);
+                v += ".exit";
+                v
             } else {
-                println!("{}", demangled_line);
-            }
+                crate::demangle::demangle(mangled_name, target)
+            };
+            debug!(
+                "  f: {}, l: {}, mn: {}, dm: {}",
+                f, l, mangled_name, demangled_name
+            );
+            demangled_line += &line[start..f];
+            demangled_line += &demangled_name;
+            demangled_line.push('"');
+            start = l + 1;
+            debug!("  ns: {}, ndl: {}", start, demangled_line);
+        }
+        if demangled_line.is_empty() {
+            output.push(line);
+        } else {
+            output.push(demangled_line);
         }
-        Ok(())
-    } else {
-        function_names.sort();
-        function_names.dedup();
-        Err(function_names)
     }
+    Ok(output)
 }
@@ -8,15 +8,25 @@
 
 mod asm;
 mod build;
+mod cache;
+mod check;
 mod demangle;
+mod diff;
 mod display;
+mod error;
+mod filecheck;
+mod html;
 mod llvmir;
 mod logger;
 mod options;
+mod pager;
+mod parallel;
 mod path;
 mod process;
 mod rust;
 mod target;
+mod tui;
+mod worktree;
 
 use crate::options::*;
 use log::{debug, error};
@@ -76,6 +86,41 @@ fn main() {
         debug!("manifest path changed to {}", new_path.display());
     }
 
+    if let Some(ref c) = *check_opts {
+        if let Err(err) = check::run(c) {
+            display::write_error(&format!("{}\n", err));
+            ::std::process::exit(err.exit_code());
+        }
+        return;
+    }
+    if let Some(ref t) = *tui_opts {
+        if let Err(err) = tui::run(t) {
+            display::write_error(&format!("{}\n", err));
+            ::std::process::exit(err.exit_code());
+        }
+        return;
+    }
+
+    // The output is paged when stdout is a terminal:
+    pager::start();
+    let result = if let Some(ref d) = *diff_opts {
+        diff::run(d)
+    } else if let Some(rev) = opts.base() {
+        let o = { (*opts.read()).clone() };
+        diff::run_base(&o, &rev)
+    } else {
+        run()
+    };
+    pager::finish();
+
+    if let Err(err) = result {
+        display::write_error(&format!("{}\n", err));
+        ::std::process::exit(err.exit_code());
+    }
+}
+
+/// Builds the project and prints the function, or the list of functions.
+fn run() -> Result<(), error::Error> {
     // Builds the project and returns a list of all relevant assembly files:
     let files = build::project();
 
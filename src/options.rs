@@ -10,6 +10,15 @@ use structopt::StructOpt;
 lazy_static! {
     pub static ref opts: ::parking_lot::RwLock<Options> =
         { ::parking_lot::RwLock::new(read()) };
+    /// Options of `cargo asm diff`, if that is the command being run.
+    pub static ref diff_opts: Option<Diff> =
+        read_diff(&::std::env::args().collect::<Vec<_>>());
+    /// Options of `cargo asm check`, if that is the command being run.
+    pub static ref check_opts: Option<Check> =
+        read_check(&::std::env::args().collect::<Vec<_>>());
+    /// Options of `cargo asm tui`, if that is the command being run.
+    pub static ref tui_opts: Option<Tui> =
+        read_tui(&::std::env::args().collect::<Vec<_>>());
 }
 
 /// CLI options of cargo asm.
@@ -43,15 +52,112 @@ pub struct Asm {
     pub example: Option<String>,
     #[structopt(long = "rust", help = "Print interleaved Rust code.")]
     pub rust: bool,
+    #[structopt(
+        long = "layout",
+        help = "Layout of the Rust code printed by --rust: interleaved, side-by-side.",
+        default_value = "interleaved"
+    )]
+    pub layout: crate::display::Layout,
+    #[structopt(
+        long = "annotate-source",
+        help = "Print the Rust source of the function with the number of instructions of each line."
+    )]
+    pub annotate_source: bool,
+    #[structopt(
+        long = "inlined",
+        help = "Annotates the assembly with the functions it was inlined from."
+    )]
+    pub inlined: bool,
+    #[structopt(
+        long = "std",
+        help = "Code inlined from std and third-party crates: show, collapse, hide.",
+        default_value = "show"
+    )]
+    pub std: crate::display::Foreign,
+    #[structopt(
+        long = "third-party",
+        help = "Treats the code of the paths that contain this pattern as third-party code (the cargo registry and git checkouts are always third-party)."
+    )]
+    pub third_party: Vec<String>,
     #[structopt(long = "comments", help = "Print assembly comments.")]
     pub comments: bool,
     #[structopt(long = "directives", help = "Print assembly directives.")]
     pub directives: bool,
+    #[structopt(
+        long = "data",
+        help = "Print the data referenced by the function."
+    )]
+    pub data: bool,
+    #[structopt(
+        long = "depth",
+        help = "Also print the functions called by the function, recursively up to this depth. Also limits --call-graph and --callers, which are unlimited without it."
+    )]
+    pub depth: Option<usize>,
+    #[structopt(
+        long = "inline-std",
+        help = "Print the std, core, and alloc functions called by the function when using --depth.",
+        default_value = "true",
+        parse(try_from_str)
+    )]
+    pub inline_std: bool,
+    #[structopt(
+        long = "call-graph",
+        help = "Prints the call graph of all functions, or of the functions reachable from the function, limited by --depth: dot, json."
+    )]
+    pub call_graph: Option<crate::asm::graph::Format>,
+    #[structopt(
+        long = "callers",
+        help = "Prints the functions that call the function, limited by --depth."
+    )]
+    pub callers: bool,
+    #[structopt(
+        long = "sizes",
+        help = "Prints the number of instructions and the size in bytes (when known) of the functions."
+    )]
+    pub sizes: bool,
+    #[structopt(
+        long = "sort-by",
+        help = "Sorts the sizes by: instructions, bytes.",
+        default_value = "instructions"
+    )]
+    pub sort_by: crate::asm::sizes::SortBy,
+    #[structopt(
+        long = "group-by",
+        help = "Groups the sizes by: function, crate, module, generic.",
+        default_value = "function"
+    )]
+    pub group_by: crate::asm::sizes::GroupBy,
+    #[structopt(
+        long = "monomorphizations",
+        help = "Prints the generic functions with more than one copy, and the number of instructions of all copies."
+    )]
+    pub monomorphizations: bool,
+    #[structopt(
+        long = "max-instructions",
+        help = "Fails if the function has more instructions than this."
+    )]
+    pub max_instructions: Option<usize>,
+    #[structopt(
+        long = "max-calls",
+        help = "Fails if the function has more calls than this."
+    )]
+    pub max_calls: Option<usize>,
+    #[structopt(
+        long = "max-stack",
+        help = "Fails if the function allocates more bytes on the stack than this."
+    )]
+    pub max_stack: Option<u64>,
     #[structopt(
         long = "json",
         help = "Serialize asm AST to json (ignores most other options)."
     )]
     pub json: bool,
+    #[structopt(
+        long = "format",
+        help = "Output format of the function and of the functions printed by --depth: text, html (a self-contained page).",
+        default_value = "text"
+    )]
+    pub format: crate::display::Format,
     #[structopt(
         long = "debug-mode",
         help = "Prints output useful for debugging."
@@ -75,6 +181,21 @@ pub struct Asm {
         help = "Disables all cargo features when building the project."
     )]
     pub no_default_features: bool,
+    #[structopt(
+        long = "rustflags",
+        help = "Additional flags passed to rustc, e.g., \"-C target-cpu=native\"."
+    )]
+    pub rustflags: Option<String>,
+    #[structopt(
+        long = "toolchain",
+        help = "Builds with this rustup toolchain, e.g., nightly."
+    )]
+    pub toolchain: Option<String>,
+    #[structopt(
+        long = "base",
+        help = "Compares the function with its version at this git revision, e.g., main."
+    )]
+    pub base: Option<String>,
 }
 
 /// CLI options of cargo llvm-ir.
@@ -120,6 +241,11 @@ pub struct LlvmIr {
         help = "Disables all cargo features when building the project."
     )]
     pub no_default_features: bool,
+    #[structopt(
+        long = "base",
+        help = "Compares the function with its version at this git revision, e.g., main."
+    )]
+    pub base: Option<String>,
 }
 
 pub trait Ext {
@@ -129,12 +255,31 @@ pub trait Ext {
     fn asm_style(&self) -> Option<Style>;
     fn build_type(&self) -> Type;
     fn rust(&self) -> bool;
+    fn layout(&self) -> crate::display::Layout;
+    fn annotate_source(&self) -> bool;
+    fn inlined(&self) -> bool;
+    fn std(&self) -> crate::display::Foreign;
+    fn third_party(&self) -> Vec<String>;
     fn comments(&self) -> Option<bool>;
     fn directives(&self) -> Option<bool>;
+    fn data(&self) -> bool;
+    fn depth(&self) -> Option<usize>;
+    fn inline_std(&self) -> bool;
+    fn call_graph(&self) -> Option<crate::asm::graph::Format>;
+    fn callers(&self) -> bool;
+    fn sizes(&self) -> bool;
+    fn sort_by(&self) -> crate::asm::sizes::SortBy;
+    fn group_by(&self) -> crate::asm::sizes::GroupBy;
+    fn monomorphizations(&self) -> bool;
+    fn max_instructions(&self) -> Option<usize>;
+    fn max_calls(&self) -> Option<usize>;
+    fn max_stack(&self) -> Option<u64>;
     fn json(&self) -> bool;
+    fn format(&self) -> crate::display::Format;
     fn debug_mode(&self) -> bool;
     fn manifest_path(&self) -> Option<::std::path::PathBuf>;
     fn use_colors(&self) -> bool;
+    fn use_colors_for<T: ::std::io::IsTerminal>(&self, stream: &T) -> bool;
     fn print_comments(&self) -> bool;
     fn print_directives(&self) -> bool;
     fn set_rust(&self, value: bool);
@@ -142,6 +287,9 @@ pub trait Ext {
     fn example(&self) -> Option<String>;
     fn lib(&self) -> bool;
     fn no_default_features(&self) -> bool;
+    fn rustflags(&self) -> Option<String>;
+    fn toolchain(&self) -> Option<String>;
+    fn base(&self) -> Option<String>;
 }
 
 impl Ext for ::parking_lot::RwLock<Options> {
@@ -181,6 +329,36 @@ impl Ext for ::parking_lot::RwLock<Options> {
             Options::LlvmIr(ref o) => o.rust,
         }
     }
+    fn layout(&self) -> crate::display::Layout {
+        match *self.read() {
+            Options::Asm(ref o) => o.layout,
+            Options::LlvmIr(_) => crate::display::Layout::Interleaved,
+        }
+    }
+    fn annotate_source(&self) -> bool {
+        match *self.read() {
+            Options::Asm(ref o) => o.annotate_source,
+            Options::LlvmIr(_) => false,
+        }
+    }
+    fn inlined(&self) -> bool {
+        match *self.read() {
+            Options::Asm(ref o) => o.inlined,
+            Options::LlvmIr(_) => false,
+        }
+    }
+    fn std(&self) -> crate::display::Foreign {
+        match *self.read() {
+            Options::Asm(ref o) => o.std,
+            Options::LlvmIr(_) => crate::display::Foreign::Show,
+        }
+    }
+    fn third_party(&self) -> Vec<String> {
+        match *self.read() {
+            Options::Asm(ref o) => o.third_party.clone(),
+            Options::LlvmIr(_) => Vec::new(),
+        }
+    }
     fn comments(&self) -> Option<bool> {
         match *self.read() {
             Options::Asm(ref o) => Some(o.comments),
@@ -193,12 +371,90 @@ impl Ext for ::parking_lot::RwLock<Options> {
             Options::LlvmIr(_) => None,
         }
     }
+    fn data(&self) -> bool {
+        match *self.read() {
+            Options::Asm(ref o) => o.data,
+            Options::LlvmIr(_) => false,
+        }
+    }
+    fn depth(&self) -> Option<usize> {
+        match *self.read() {
+            Options::Asm(ref o) => o.depth,
+            Options::LlvmIr(_) => None,
+        }
+    }
+    fn inline_std(&self) -> bool {
+        match *self.read() {
+            Options::Asm(ref o) => o.inline_std,
+            Options::LlvmIr(_) => true,
+        }
+    }
+    fn call_graph(&self) -> Option<crate::asm::graph::Format> {
+        match *self.read() {
+            Options::Asm(ref o) => o.call_graph,
+            Options::LlvmIr(_) => None,
+        }
+    }
+    fn callers(&self) -> bool {
+        match *self.read() {
+            Options::Asm(ref o) => o.callers,
+            Options::LlvmIr(_) => false,
+        }
+    }
+    fn sizes(&self) -> bool {
+        match *self.read() {
+            Options::Asm(ref o) => o.sizes,
+            Options::LlvmIr(_) => false,
+        }
+    }
+    fn sort_by(&self) -> crate::asm::sizes::SortBy {
+        match *self.read() {
+            Options::Asm(ref o) => o.sort_by,
+            Options::LlvmIr(_) => crate::asm::sizes::SortBy::Instructions,
+        }
+    }
+    fn group_by(&self) -> crate::asm::sizes::GroupBy {
+        match *self.read() {
+            Options::Asm(ref o) => o.group_by,
+            Options::LlvmIr(_) => crate::asm::sizes::GroupBy::Function,
+        }
+    }
+    fn monomorphizations(&self) -> bool {
+        match *self.read() {
+            Options::Asm(ref o) => o.monomorphizations,
+            Options::LlvmIr(_) => false,
+        }
+    }
+    fn max_instructions(&self) -> Option<usize> {
+        match *self.read() {
+            Options::Asm(ref o) => o.max_instructions,
+            Options::LlvmIr(_) => None,
+        }
+    }
+    fn max_calls(&self) -> Option<usize> {
+        match *self.read() {
+            Options::Asm(ref o) => o.max_calls,
+            Options::LlvmIr(_) => None,
+        }
+    }
+    fn max_stack(&self) -> Option<u64> {
+        match *self.read() {
+            Options::Asm(ref o) => o.max_stack,
+            Options::LlvmIr(_) => None,
+        }
+    }
     fn json(&self) -> bool {
         match *self.read() {
             Options::Asm(ref o) => o.json,
             Options::LlvmIr(ref _o) => false,
         }
     }
+    fn format(&self) -> crate::display::Format {
+        match *self.read() {
+            Options::Asm(ref o) => o.format,
+            Options::LlvmIr(_) => crate::display::Format::Text,
+        }
+    }
     fn debug_mode(&self) -> bool {
         match *self.read() {
             Options::Asm(ref o) => o.debug_mode,
@@ -212,8 +468,15 @@ impl Ext for ::parking_lot::RwLock<Options> {
         }
     }
 
+    /// Colors the output to stdout?
     fn use_colors(&self) -> bool {
-        !self.no_color()
+        self.use_colors_for(&::std::io::stdout())
+    }
+    /// Colors the output to the `stream`?
+    fn use_colors_for<T: ::std::io::IsTerminal>(&self, stream: &T) -> bool {
+        colors(self.no_color(), stream.is_terminal(), |name| {
+            ::std::env::var_os(name)
+        })
     }
     fn print_comments(&self) -> bool {
         if self.debug_mode() {
@@ -259,6 +522,24 @@ impl Ext for ::parking_lot::RwLock<Options> {
             Options::LlvmIr(ref o) => o.no_default_features,
         }
     }
+    fn rustflags(&self) -> Option<String> {
+        match *self.read() {
+            Options::Asm(ref o) => o.rustflags.clone(),
+            Options::LlvmIr(_) => None,
+        }
+    }
+    fn toolchain(&self) -> Option<String> {
+        match *self.read() {
+            Options::Asm(ref o) => o.toolchain.clone(),
+            Options::LlvmIr(_) => None,
+        }
+    }
+    fn base(&self) -> Option<String> {
+        match *self.read() {
+            Options::Asm(ref o) => o.base.clone(),
+            Options::LlvmIr(ref o) => o.base.clone(),
+        }
+    }
 }
 
 #[derive(StructOpt, Debug, Clone)]
@@ -296,21 +577,378 @@ Quick start: given a crate named \"crate\", to search:
     LlvmIr(LlvmIr),
 }
 
-fn read() -> Options {
-    let mut o = Options::from_args();
-    match o {
-        Options::Asm(ref mut o) => {
-            // In debug mode we always print the associated Rust code.
-            if o.debug_mode {
-                o.rust = true;
+/// Options of `cargo asm diff`, which builds the project twice and compares
+/// the assembly of a function.
+#[derive(StructOpt, Debug, Clone)]
+#[structopt(
+    name = "cargo asm diff",
+    about = "\
+Compares the assembly of a function built with two sets of options.
+
+The options that are not listed below are common to both builds, e.g.:
+  cargo asm diff crate::foo --left=\"--build-type=debug\" --right=\"\"
+  cargo asm diff crate::foo --right='--rustflags=\"-C target-cpu=native\"'
+"
+)]
+pub struct DiffArgs {
+    #[structopt(
+        long = "left",
+        help = "Options of the left build.",
+        default_value = ""
+    )]
+    pub left: String,
+    #[structopt(
+        long = "right",
+        help = "Options of the right build.",
+        default_value = ""
+    )]
+    pub right: String,
+    #[structopt(
+        long = "context",
+        help = "Number of unchanged lines shown around the changes.",
+        default_value = "3"
+    )]
+    pub context: usize,
+    #[structopt(
+        long = "keep-registers",
+        help = "Does not normalize the register names."
+    )]
+    pub keep_registers: bool,
+}
+
+/// Options of `cargo asm diff` and of its two builds.
+#[derive(Debug, Clone)]
+pub struct Diff {
+    pub args: DiffArgs,
+    pub left: Asm,
+    pub right: Asm,
+}
+
+/// Options of `cargo asm check`, which checks the assembly of functions
+/// against the expectations of a file and against the `CHECK` directives of
+/// the sources.
+#[derive(StructOpt, Debug, Clone)]
+#[structopt(
+    name = "cargo asm check",
+    about = "\
+Checks the assembly of functions against the expectations of a file, e.g.:
+
+  [[function]]
+  path = \"crate::foo\"
+  # Normalized assembly, relative to the file (updated by --bless):
+  snapshot = \"snapshots/foo.s\"
+  max_instructions = 40
+  forbid = [\"call\", \"div\"]
+  # The function must use vector registers of at least this many bits:
+  min_vector_width = 256
+
+and against the FileCheck-style directives above the functions in src/:
+
+  // CHECK: imul
+  // CHECK-NOT: call
+  // CHECK-NEXT: ret
+  pub fn foo(x: u32) -> u32 { ... }
+
+The options that are not listed below are passed to the build.
+"
+)]
+pub struct CheckArgs {
+    #[structopt(
+        long = "checks",
+        help = "File with the expectations [default: asm-checks.toml, if it exists].",
+        parse(from_os_str)
+    )]
+    pub checks: Option<::std::path::PathBuf>,
+    #[structopt(
+        long = "bless",
+        help = "Updates the snapshots instead of comparing them."
+    )]
+    pub bless: bool,
+    #[structopt(
+        long = "ir",
+        help = "Checks the CHECK directives against the LLVM IR instead of the assembly."
+    )]
+    pub ir: bool,
+}
+
+/// Options of `cargo asm check` and of its build.
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub args: CheckArgs,
+    pub build: Asm,
+}
+
+/// Options of `cargo asm tui`, which browses the functions interactively.
+#[derive(StructOpt, Debug, Clone)]
+#[structopt(
+    name = "cargo asm tui",
+    about = "\
+Browses the assembly of the functions interactively, starting at the
+function path if any, e.g.: cargo asm tui crate::foo
+
+Keys:
+  type, up/down, enter   search and open a function
+  enter                  jump to the label or follow the call of the line
+  backspace, left        go back
+  /                      search
+  c, d, r                toggle the comments, directives, and Rust code
+  f                      cycle the std code: show, collapse, hide
+  s                      toggle the Rust source pane
+  q                      quit
+
+The options that are not listed below are passed to the build.
+"
+)]
+pub struct TuiArgs {
+    #[structopt(
+        long = "no-source",
+        help = "Starts without the Rust source pane."
+    )]
+    pub no_source: bool,
+}
+
+/// Options of `cargo asm tui` and of its build.
+#[derive(Debug, Clone)]
+pub struct Tui {
+    pub args: TuiArgs,
+    pub build: Asm,
+}
+
+/// Splits the arguments of `cargo asm <command>` into the arguments of the
+/// builds and those of the command itself, i.e., its `options` with a value
+/// and its `flags`. Returns `None` if the command is another one.
+fn split_command_args(
+    args: &[String],
+    command: &str,
+    options: &[&str],
+    flags: &[&str],
+) -> Option<(Vec<String>, Vec<String>)> {
+    if args.len() < 3 || args[1] != "asm" || args[2] != command {
+        return None;
+    }
+    let mut common = Vec::new();
+    let mut own = Vec::new();
+    let mut args = args[3..].iter();
+    while let Some(a) = args.next() {
+        let name = a.split('=').next().unwrap();
+        if options.contains(&name) {
+            own.push(a.clone());
+            if !a.contains('=') {
+                own.extend(args.next().cloned());
             }
+        } else if flags.contains(&name) || name == "--help" || name == "-h" {
+            own.push(a.clone());
+        } else {
+            common.push(a.clone());
         }
-        Options::LlvmIr(ref mut o) => {
-            // In debug mode we always print the associated Rust code.
-            if o.debug_mode {
-                o.rust = true;
+    }
+    Some((common, own))
+}
+
+/// Splits `s` into words at whitespace, except within quotes.
+fn split_words(s: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in s.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
             }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// Parses the arguments of a build of `cargo asm diff` or `cargo asm check`.
+fn read_asm<I: Iterator<Item = String>>(args: I) -> Asm {
+    let args = ["cargo", "asm"].iter().map(|s| s.to_string()).chain(args);
+    match Options::from_iter_safe(args).unwrap_or_else(|e| e.exit()) {
+        Options::Asm(mut o) => {
+            adjust(&mut o.debug_mode, &mut o.rust);
+            o
         }
+        Options::LlvmIr(_) => unreachable!(),
+    }
+}
+
+fn read_diff(args: &[String]) -> Option<Diff> {
+    let (common, diff) = split_command_args(
+        args,
+        "diff",
+        &["--left", "--right", "--context"],
+        &["--keep-registers"],
+    )?;
+    let diff_args = DiffArgs::from_iter_safe(
+        ::std::iter::once("cargo asm diff".to_string()).chain(diff),
+    )
+    .unwrap_or_else(|e| e.exit());
+    let side = |extra: &str| {
+        read_asm(common.iter().cloned().chain(split_words(extra)))
+    };
+    let left = side(&diff_args.left);
+    let right = side(&diff_args.right);
+    Some(Diff {
+        args: diff_args,
+        left,
+        right,
+    })
+}
+
+fn read_check(args: &[String]) -> Option<Check> {
+    let (common, check) = split_command_args(
+        args,
+        "check",
+        &["--checks"],
+        &["--bless", "--ir"],
+    )?;
+    let args = CheckArgs::from_iter_safe(
+        ::std::iter::once("cargo asm check".to_string()).chain(check),
+    )
+    .unwrap_or_else(|e| e.exit());
+    Some(Check {
+        args,
+        build: read_asm(common.into_iter()),
+    })
+}
+
+fn read_tui(args: &[String]) -> Option<Tui> {
+    let (common, tui) =
+        split_command_args(args, "tui", &[], &["--no-source"])?;
+    let args = TuiArgs::from_iter_safe(
+        ::std::iter::once("cargo asm tui".to_string()).chain(tui),
+    )
+    .unwrap_or_else(|e| e.exit());
+    Some(Tui {
+        args,
+        build: read_asm(common.into_iter()),
+    })
+}
+
+/// In debug mode we always print the associated Rust code.
+fn adjust(debug_mode: &mut bool, rust: &mut bool) {
+    if *debug_mode {
+        *rust = true;
+    }
+}
+
+fn read() -> Options {
+    if let Some(ref d) = *diff_opts {
+        return Options::Asm(d.left.clone());
+    }
+    if let Some(ref c) = *check_opts {
+        return Options::Asm(c.build.clone());
+    }
+    if let Some(ref t) = *tui_opts {
+        return Options::Asm(t.build.clone());
+    }
+    let mut o = Options::from_args();
+    match o {
+        Options::Asm(ref mut o) => adjust(&mut o.debug_mode, &mut o.rust),
+        Options::LlvmIr(ref mut o) => adjust(&mut o.debug_mode, &mut o.rust),
     };
     o
 }
+
+/// Colors the output? `--no-color` and `NO_COLOR` disable the colors,
+/// `CLICOLOR_FORCE` enables them, otherwise they are enabled if the output
+/// is a terminal other than `TERM=dumb`. Empty variables are ignored.
+fn colors<F>(no_color: bool, is_terminal: bool, var: F) -> bool
+where
+    F: Fn(&str) -> Option<::std::ffi::OsString>,
+{
+    let var = |name| var(name).filter(|v| !v.is_empty());
+    if no_color || var("NO_COLOR").is_some() {
+        return false;
+    }
+    if var("CLICOLOR_FORCE").is_some_and(|v| v != "0") {
+        return true;
+    }
+    is_terminal && var("TERM").is_none_or(|t| t != "dumb")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_args() {
+        let s =
+            |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            split_command_args(
+                &s(&["cargo-asm", "asm", "foo"]),
+                "diff",
+                &[],
+                &[]
+            ),
+            None
+        );
+        let (common, diff) = split_command_args(
+            &s(&[
+                "cargo-asm",
+                "asm",
+                "diff",
+                "foo::bar",
+                "--left",
+                "--build-type=debug",
+                "--rust",
+                "--right=--lib",
+                "--keep-registers",
+            ]),
+            "diff",
+            &["--left", "--right"],
+            &["--keep-registers"],
+        )
+        .unwrap();
+        assert_eq!(common, s(&["foo::bar", "--rust"]));
+        assert_eq!(
+            diff,
+            s(&[
+                "--left",
+                "--build-type=debug",
+                "--right=--lib",
+                "--keep-registers"
+            ])
+        );
+        assert_eq!(
+            split_words(r#"--lib --rustflags="-C target-cpu=native" ''"#),
+            s(&["--lib", "--rustflags=-C target-cpu=native", ""])
+        );
+    }
+
+    #[test]
+    fn colors_from_env() {
+        let colors = |no_color, is_terminal, vars: &[(&str, &str)]| {
+            let vars = vars.to_vec();
+            colors(no_color, is_terminal, move |name| {
+                vars.iter().find(|v| v.0 == name).map(|v| v.1.into())
+            })
+        };
+        // A terminal, unless it is dumb:
+        assert!(colors(false, true, &[]));
+        assert!(!colors(false, false, &[]));
+        assert!(!colors(false, true, &[("TERM", "dumb")]));
+        assert!(colors(false, true, &[("TERM", "xterm")]));
+        // `CLICOLOR_FORCE` wins over the terminal detection:
+        assert!(colors(false, false, &[("CLICOLOR_FORCE", "1")]));
+        assert!(colors(
+            false,
+            true,
+            &[("CLICOLOR_FORCE", "1"), ("TERM", "dumb")]
+        ));
+        assert!(!colors(false, false, &[("CLICOLOR_FORCE", "0")]));
+        assert!(!colors(false, false, &[("CLICOLOR_FORCE", "")]));
+        // `--no-color` and `NO_COLOR` win over `CLICOLOR_FORCE`:
+        let force = ("CLICOLOR_FORCE", "1");
+        assert!(!colors(false, true, &[("NO_COLOR", "1"), force]));
+        assert!(!colors(true, true, &[force]));
+        assert!(colors(false, true, &[("NO_COLOR", ""), force]));
+    }
+}
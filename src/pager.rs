@@ -0,0 +1,108 @@
+//! Pages the output through `$PAGER` when stdout is a terminal.
+
+use lazy_static::lazy_static;
+use log::debug;
+use std::io::{IsTerminal, Write};
+
+lazy_static! {
+    /// Output buffered for the pager, if stdout is paged.
+    static ref listing: ::parking_lot::Mutex<Option<Vec<u8>>> =
+        ::parking_lot::Mutex::new(None);
+}
+
+/// The pager command: `$PAGER`, or `less -R`. An empty `$PAGER` disables
+/// the pager.
+fn command() -> Option<Vec<String>> {
+    command_of(::std::env::var("PAGER").ok())
+}
+
+/// The pager command of the `pager` variable.
+fn command_of(pager: Option<String>) -> Option<Vec<String>> {
+    let pager = pager.unwrap_or_else(|| "less -R".into());
+    let words = pager
+        .split_whitespace()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if words.is_empty() {
+        None
+    } else {
+        Some(words)
+    }
+}
+
+/// Starts buffering the output for the pager if stdout is a terminal.
+pub fn start() {
+    if ::std::io::stdout().is_terminal() && command().is_some() {
+        *listing.lock() = Some(Vec::new());
+    }
+}
+
+/// Writes `bytes` to the output.
+pub fn write(bytes: &[u8]) {
+    match *listing.lock() {
+        Some(ref mut l) => l.extend_from_slice(bytes),
+        None => {
+            let stdout = ::std::io::stdout();
+            let mut stdout = stdout.lock();
+            // Like `print!`, but a closed pipe (e.g. `| head`) is not an
+            // error:
+            let _ = stdout.write_all(bytes).and_then(|()| stdout.flush());
+        }
+    }
+}
+
+/// Prints the formatted `args` to the output.
+pub fn print(args: ::std::fmt::Arguments) {
+    write(args.to_string().as_bytes());
+}
+
+/// Shows the buffered output: through the pager if it does not fit in the
+/// terminal, otherwise directly.
+pub fn finish() {
+    let bytes = match listing.lock().take() {
+        Some(bytes) => bytes,
+        None => return,
+    };
+    let height = ::crossterm::terminal::size()
+        .map(|(_, h)| usize::from(h))
+        .unwrap_or(0);
+    let lines = bytes.iter().filter(|&&b| b == b'\n').count();
+    if lines >= height {
+        if let Some(words) = command() {
+            let child = ::std::process::Command::new(&words[0])
+                .args(&words[1..])
+                .stdin(::std::process::Stdio::piped())
+                .spawn();
+            match child {
+                Ok(mut child) => {
+                    if let Some(mut stdin) = child.stdin.take() {
+                        // The pager may quit before reading everything:
+                        let _ = stdin.write_all(&bytes);
+                    }
+                    let _ = child.wait();
+                    return;
+                }
+                Err(e) => debug!("failed to run the pager {:?}: {}", words, e),
+            }
+        }
+    }
+    write(&bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pager_command() {
+        let command = |pager: Option<&str>| command_of(pager.map(Into::into));
+        assert_eq!(command(None).unwrap(), ["less", "-R"]);
+        assert_eq!(command(Some("")), None);
+        assert_eq!(command(Some("  ")), None);
+        assert_eq!(command(Some("more")).unwrap(), ["more"]);
+        assert_eq!(
+            command(Some(" less  -R -F ")).unwrap(),
+            ["less", "-R", "-F"]
+        );
+    }
+}
@@ -0,0 +1,68 @@
+//! Parallel processing utilities
+
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Applies `f` to the `items` on a pool of threads and returns the results
+/// in the order of the `items`.
+///
+/// `f` returns a result and whether processing can stop at this item. The
+/// results are truncated after the first item that stops: the output is the
+/// same as that of a sequential loop that breaks at that item, but items
+/// after it might still be (partially) processed.
+pub fn map_until<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> (R, bool) + Sync,
+{
+    let next = AtomicUsize::new(0);
+    // Index of the first item that stopped:
+    let stop = AtomicUsize::new(items.len());
+    let results = items.iter().map(|_| Mutex::new(None)).collect::<Vec<_>>();
+
+    let threads = ::std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(items.len());
+
+    ::std::thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                // Items after the first stop are not needed:
+                if i >= items.len() || i > stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                let (r, done) = f(&items[i]);
+                if done {
+                    stop.fetch_min(i, Ordering::SeqCst);
+                }
+                *results[i].lock() = Some(r);
+            });
+        }
+    });
+
+    // All items up to the first stop have been processed:
+    let stop = stop.load(Ordering::SeqCst);
+    results
+        .into_iter()
+        .take(stop.saturating_add(1))
+        .map(|r| r.into_inner().expect("item was not processed"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn map_until() {
+        let items = (0..100).collect::<Vec<usize>>();
+        let r = super::map_until(&items, |i| (i * 2, false));
+        assert_eq!(r, items.iter().map(|i| i * 2).collect::<Vec<_>>());
+
+        let r = super::map_until(&items, |i| (*i, *i == 42 || *i == 70));
+        assert_eq!(r, (0..=42).collect::<Vec<_>>());
+
+        assert!(super::map_until(&[] as &[usize], |i| (*i, false)).is_empty());
+    }
+}
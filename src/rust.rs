@@ -1,6 +1,7 @@
 //! Parses Rust code
 
 use crate::asm;
+use crate::error::Error;
 use crate::options::*;
 
 use log::{debug, info};
@@ -22,9 +23,12 @@ impl File {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Files {
     pub files: ::std::collections::HashMap<usize, File>,
+    /// Paths of all the files of the function, including the ones that
+    /// cannot be read.
+    pub paths: ::std::collections::HashMap<usize, ::std::path::PathBuf>,
 }
 
 impl Files {
@@ -57,10 +61,11 @@ impl Files {
 pub fn parse(
     function: &asm::ast::Function,
     file_table: &::std::collections::HashMap<usize, asm::ast::File>,
-) -> Files {
+) -> Result<Files, Error> {
     use crate::asm::ast::Directive;
     use crate::asm::ast::Statement;
     let mut files = ::std::collections::HashMap::<usize, File>::new();
+    let mut paths = ::std::collections::HashMap::new();
 
     // Go through all locations in the function and build a map of file indices
     // to files. The files contain a map of line indices to lines, the map is
@@ -68,18 +73,24 @@ pub fn parse(
     for s in &function.statements {
         if let Statement::Directive(Directive::Loc(ref l)) = s {
             debug!("inserting locs: {:?}", l);
-            files.entry(l.file_index).or_insert_with(|| {
-                let ast = file_table.get(&l.file_index).unwrap_or_else(||
-                    panic!("[ERROR]: incomplete file table. Location {:?} 's file is not in the file table:\n{:?}",
-                             l, file_table));
-                File {
-                    ast: ast.clone(),
-                    lines: ::std::collections::BTreeMap::new(),
+            let ast = file_table.get(&l.file_index).ok_or_else(|| {
+                Error::FileTable {
+                    file: function
+                        .file
+                        .as_ref()
+                        .map(|f| f.path.clone())
+                        .unwrap_or_default(),
+                    function: function.id.clone(),
+                    index: l.file_index,
                 }
-            });
+            })?;
+            paths.insert(l.file_index, ast.path.clone());
             files
-                .get_mut(&l.file_index)
-                .unwrap()
+                .entry(l.file_index)
+                .or_insert_with(|| File {
+                    ast: ast.clone(),
+                    lines: ::std::collections::BTreeMap::new(),
+                })
                 .lines
                 .insert(l.file_line, None);
             debug!("files: {:?}", files);
@@ -109,30 +120,20 @@ pub fn parse(
     debug!("Done filing holes in files: {:?}", files);
 
     // Corrects paths to Rust std library components:
-    correct_rust_paths(&mut files);
+    correct_rust_paths(&mut files)?;
 
     debug!("Done correcting paths in files: {:?}", files);
 
     // Read the required lines from each Rust file:
     for f in files.values_mut() {
-        use std::{
-            fs::File,
-            io::{BufRead, BufReader},
-        };
-        let fh = File::open(&f.ast.path).unwrap_or_else(|e| {
-            panic!(
-                "[ERROR]: failed to open file: {}\ncause: {:?}",
-                f.ast.path.display(),
-                e
-            )
-        });
-        let file_buf = BufReader::new(&fh);
-
-        for (line_idx, line) in file_buf.lines().enumerate() {
+        let contents = contents(&f.ast)?;
+        if f.ast.path.exists() {
+            check_md5(&f.ast, &contents);
+        }
+        for (line_idx, line) in contents.lines().enumerate() {
             let line_idx = line_idx + 1;
             if f.lines.contains_key(&line_idx) {
-                let line = line.unwrap().trim().to_string();
-                *f.lines.get_mut(&line_idx).unwrap() = Some(line);
+                f.lines.insert(line_idx, Some(line.trim_end().to_string()));
             }
         }
     }
@@ -142,19 +143,51 @@ pub fn parse(
     for f in files.values_mut() {
         for (l_idx, line) in &f.lines {
             if line.is_none() && *l_idx != 0 {
-                panic!(
-                    "[ERROR]: could not read line {} of file {} ",
-                    l_idx,
-                    f.ast.path.display()
-                );
+                return Err(Error::Parse {
+                    file: Some(f.ast.path.clone()),
+                    line: Some(*l_idx),
+                    text: String::new(),
+                    msg: "could not read line: the file is too short"
+                        .to_string(),
+                });
             }
         }
     }
 
-    Files { files }
+    Ok(Files { files, paths })
+}
+
+/// Contents of the Rust `file`.
+pub fn contents(file: &asm::ast::File) -> Result<String, Error> {
+    match file.source {
+        // Use the source embedded in the .file directive if the file is not
+        // available:
+        Some(ref source) if !file.path.exists() => Ok(source.clone()),
+        _ => ::std::fs::read_to_string(&file.path)
+            .map_err(|e| Error::io(&file.path, e)),
+    }
+}
+
+/// Warns if the checksum of the `.file` directive does not match the
+/// `contents` of the file: the source code has changed since the assembly was
+/// generated.
+fn check_md5(file: &asm::ast::File, contents: &str) {
+    if let Some(ref md5) = file.md5 {
+        let digest = format!("{:x}", ::md5::compute(contents));
+        if &digest != md5 {
+            crate::display::write_warning(&format!(
+                "the file {} has changed since it was compiled (md5 {} != {}), the Rust source lines might be wrong: try to do a --clean build\n",
+                file.path.display(),
+                digest,
+                md5
+            ));
+        }
+    }
 }
 
-fn correct_rust_paths(files: &mut ::std::collections::HashMap<usize, File>) {
+fn correct_rust_paths(
+    files: &mut ::std::collections::HashMap<usize, File>,
+) -> Result<(), Error> {
     let rust =
         ::std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
 
@@ -169,7 +202,11 @@ fn correct_rust_paths(files: &mut ::std::collections::HashMap<usize, File>) {
 
     let mut sysroot = match r {
         Ok((stdout, _stderr)) => ::std::path::PathBuf::from(stdout.trim()),
-        Err(()) => panic!(),
+        Err(()) => {
+            return Err(Error::Command {
+                msg: format!("failed to call {} --print sysroot", rust),
+            })
+        }
     };
 
     debug!("sysroot: {}", sysroot.display());
@@ -222,11 +259,15 @@ fn correct_rust_paths(files: &mut ::std::collections::HashMap<usize, File>) {
         }
     }
     files.retain(|_k: &usize, f: &mut File| {
-        if f.ast.path.exists() {
+        if f.ast.path.exists() || f.ast.source.is_some() {
             true
         } else {
-            println!("file {} does not exist!", f.ast.path.display());
+            crate::pager::print(format_args!(
+                "file {} does not exist!\n",
+                f.ast.path.display()
+            ));
             false
         }
     });
+    Ok(())
 }
@@ -27,6 +27,10 @@ impl TargetInfo {
         ti
     }
 
+    pub fn triple(&self) -> &str {
+        &self.triple
+    }
+
     pub fn is_intel(&self) -> bool {
         self.triple.contains("86")
     }
@@ -78,6 +82,34 @@ impl TargetInfo {
     pub fn is_mips(&self) -> bool {
         self.triple.contains("mips")
     }
+
+    /// Strings that start a comment in the assembly of the target.
+    pub fn comment_delimiters(&self) -> &'static [&'static str] {
+        if self.is_aarch64() {
+            if self.is_apple() {
+                &[";"]
+            } else {
+                &["//"]
+            }
+        } else if self.is_arm() {
+            &["@"]
+        } else if self.is_sparc() {
+            &["!"]
+        } else {
+            // x86, PowerPC, MIPS, RISC-V, ...
+            &["#"]
+        }
+    }
+
+    /// String that separates multiple statements on the same line of
+    /// assembly.
+    pub fn statement_separator(&self) -> &'static str {
+        if self.is_aarch64() && self.is_apple() {
+            "%%"
+        } else {
+            ";"
+        }
+    }
 }
 
 /// Returns the target that is being compiled.
@@ -148,26 +180,29 @@ pub fn rust_src_path_component() -> ::std::path::PathBuf {
     ::std::path::PathBuf::from(p)
 }
 
+/// Target directory of the project, e.g., `target`.
+pub fn root_directory() -> ::std::path::PathBuf {
+    debug!("obtaining the target directory...");
+    // Run cargo metadata to get the target directory
+    let mut cargo = ::std::process::Command::new("cargo");
+    cargo.arg("metadata");
+    cargo.arg("--format-version");
+    cargo.arg("1");
+    let error_msg = "cargo metadata failed";
+    let (stdout, _stderr) =
+        crate::process::exec(&mut cargo, error_msg, opts.debug_mode())
+            .expect(error_msg);
+
+    // Parse the metadata format
+    let v: ::serde_json::Value = ::serde_json::from_str(&stdout)
+        .expect("failed to parse cargo metadata's output as json");
+    ::std::path::PathBuf::from(v["target_directory"].as_str().expect("could not find key \"target_directory\" in the output of `cargo metadata`"))
+}
+
 pub fn directory<P: AsRef<::std::path::Path>>(
     sub_path: P,
 ) -> ::std::path::PathBuf {
-    debug!("obtaining the target directory...");
-    // Run cargo metadata to get the target directory
-    let mut target_directory = {
-        let mut cargo = ::std::process::Command::new("cargo");
-        cargo.arg("metadata");
-        cargo.arg("--format-version");
-        cargo.arg("1");
-        let error_msg = "cargo metadata failed";
-        let (stdout, _stderr) =
-            crate::process::exec(&mut cargo, error_msg, opts.debug_mode())
-                .expect(error_msg);
-
-        // Parse the metadata format
-        let v: ::serde_json::Value = ::serde_json::from_str(&stdout)
-            .expect("failed to parse cargo metadata's output as json");
-        ::std::path::PathBuf::from(v["target_directory"].as_str().expect("could not find key \"target_directory\" in the output of `cargo metadata`"))
-    };
+    let mut target_directory = root_directory();
 
     // Generate build type path component:
     let build_type = match opts.build_type() {
@@ -0,0 +1,763 @@
+//! Interactive terminal browser of the functions of the assembly files.
+
+use crate::asm::ast::{Directive, Function, Loc, Statement};
+use crate::display::{self, Foreign, Kind};
+use crate::error::Error;
+use crate::options::*;
+use crate::target::TargetInfo;
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{
+    Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor,
+};
+use crossterm::{cursor, queue, terminal};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Below this terminal width the Rust source pane is not shown.
+const SPLIT_MIN_WIDTH: usize = 80;
+
+/// Where Enter goes from a line.
+#[derive(Clone, Debug, PartialEq)]
+enum Link {
+    Label(String),
+    Function(String),
+}
+
+/// Line of the assembly pane.
+struct Line {
+    spans: Vec<(String, Color)>,
+    loc: Option<Loc>,
+    link: Option<Link>,
+    /// Label defined by the line.
+    label: Option<String>,
+}
+
+impl Line {
+    fn new(text: String, color: Color, loc: Option<Loc>) -> Self {
+        Self {
+            spans: vec![(text, color)],
+            loc,
+            link: None,
+            label: None,
+        }
+    }
+}
+
+/// Function shown in the assembly pane.
+struct View {
+    function: Function,
+    rust: crate::rust::Files,
+    lines: Vec<Line>,
+    cursor: usize,
+    scroll: usize,
+    /// Lines of the Rust files, by file index.
+    sources: HashMap<usize, Vec<String>>,
+}
+
+/// Function search.
+#[derive(Default)]
+struct Search {
+    query: String,
+    /// Indices of the names that match the query, best match first.
+    matches: Vec<usize>,
+    selected: usize,
+    scroll: usize,
+}
+
+struct App<'a> {
+    files: &'a [PathBuf],
+    target: &'a TargetInfo,
+    names: Vec<String>,
+    known: HashSet<String>,
+    /// Functions that have been opened, the current one last.
+    views: Vec<View>,
+    search: Option<Search>,
+    split: bool,
+    message: Option<String>,
+}
+
+/// Score of `name` for the fuzzy `query`, higher is better: the characters
+/// of the query must appear in order in the name, and score more if they
+/// are consecutive or start a path component or word.
+fn fuzzy_score(query: &str, name: &str) -> Option<usize> {
+    let name = name.to_lowercase().chars().collect::<Vec<_>>();
+    let query = query
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<Vec<_>>();
+    let first = match query.first() {
+        Some(&first) => first,
+        None => return Some(0),
+    };
+    // Matches the query greedily after each occurrence of its first
+    // character, and keeps the best score:
+    let score_from = |start: usize| {
+        let mut score = 0;
+        let mut next = start;
+        let mut last: Option<usize> = None;
+        for &q in &query {
+            let i = next + name[next..].iter().position(|&c| c == q)?;
+            score += 1;
+            if last.is_some() && last == i.checked_sub(1) {
+                score += 5;
+            }
+            if i == 0 || name[i - 1] == ':' || name[i - 1] == '_' {
+                score += 3;
+            }
+            last = Some(i);
+            next = i + 1;
+        }
+        Some(score)
+    };
+    (0..name.len())
+        .filter(|&i| name[i] == first)
+        .filter_map(score_from)
+        .max()
+}
+
+/// Text of the directive `d`.
+fn directive_text(d: &Directive) -> String {
+    match d {
+        Directive::File(ref f) => {
+            format!(".file {} \"{}\"", f.index, f.path.display())
+        }
+        Directive::Loc(ref l) => {
+            format!(".loc {} {} {}", l.file_index, l.file_line, l.file_column)
+        }
+        Directive::Generic(ref g) => g.string.clone(),
+    }
+}
+
+/// Lines of the assembly pane of the `function`. The calls and references
+/// to the `known` functions are links.
+fn lines(
+    function: &Function,
+    rust: &crate::rust::Files,
+    known: &HashSet<String>,
+    target: &TargetInfo,
+) -> Vec<Line> {
+    let output = display::fold_foreign(
+        display::merge_rust_and_asm(function, rust),
+        function,
+        rust,
+        opts.std(),
+    );
+    let labels = function
+        .statements
+        .iter()
+        .filter_map(|s| match s {
+            Statement::Label(ref l) => Some(l.id.as_str()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let function_link = |arg: &str| {
+        if known.contains(arg) {
+            return Some(Link::Function(arg.to_string()));
+        }
+        crate::asm::parse::referenced_symbols(arg)
+            .map(|s| crate::demangle::demangle(s, target))
+            .find(|name| known.contains(name))
+            .map(Link::Function)
+    };
+
+    let mut lines = Vec::new();
+    for kind in output.iter().filter(|k| display::is_visible(k)) {
+        match kind {
+            Kind::Rust(ref r) => {
+                let indent = if display::is_rust_in_function(function, r) {
+                    " "
+                } else {
+                    "     "
+                };
+                lines.push(Line::new(
+                    format!("{}{}", indent, r.line),
+                    Color::Red,
+                    Some(r.loc),
+                ));
+            }
+            Kind::Asm(Statement::Label(ref l)) => {
+                let mut line = Line::new(
+                    format!("{}:", l.id),
+                    Color::Green,
+                    l.rust_loc(),
+                );
+                line.label = Some(l.id.clone());
+                lines.push(line);
+            }
+            Kind::Asm(Statement::Directive(ref d)) => lines.push(Line::new(
+                format!(" {}", directive_text(d)),
+                Color::DarkGrey,
+                None,
+            )),
+            Kind::Asm(Statement::Comment(ref c)) => lines.push(Line::new(
+                format!(" {}", c.string),
+                Color::DarkGrey,
+                None,
+            )),
+            Kind::Asm(ref s @ Statement::Instruction(ref i)) => {
+                let indent = if !opts.rust()
+                    || display::is_stmt_in_function(function, s)
+                {
+                    " "
+                } else {
+                    "     "
+                };
+                let (color, link) = if i.is_jump(target) {
+                    let link = i.args.first().and_then(|a| {
+                        if labels.contains(a.as_str()) {
+                            Some(Link::Label(a.clone()))
+                        } else {
+                            function_link(a)
+                        }
+                    });
+                    (Color::Green, link)
+                } else if i.is_call(target) {
+                    (Color::Red, i.args.iter().find_map(|a| function_link(a)))
+                } else {
+                    (
+                        Color::Reset,
+                        i.args.iter().find_map(|a| function_link(a)),
+                    )
+                };
+                let mut line =
+                    Line::new(indent.to_string(), Color::Reset, i.rust_loc());
+                if i.args.is_empty() {
+                    line.spans.push((i.instr.clone(), Color::Blue));
+                } else {
+                    line.spans.push((format!("{: <7}", i.instr), Color::Blue));
+                    line.spans
+                        .push((format!(" {}", i.args.join(", ")), color));
+                }
+                if let Some(ref data) = i.data {
+                    line.spans.push((
+                        format!(
+                            "  {} {}",
+                            target.comment_delimiters()[0],
+                            display::truncate(data, 60)
+                        ),
+                        Color::DarkGrey,
+                    ));
+                }
+                line.link = link;
+                lines.push(line);
+
+                for (label, entries) in i.jump_table() {
+                    let mut line = Line::new(
+                        format!(
+                            "{}    {} => ",
+                            indent,
+                            display::entries_text(&entries)
+                        ),
+                        Color::Reset,
+                        i.rust_loc(),
+                    );
+                    line.spans.push((label.to_string(), Color::Green));
+                    line.link = Some(Link::Label(label.to_string()));
+                    lines.push(line);
+                }
+            }
+            Kind::Folded {
+                instructions,
+                ref origin,
+            } => lines.push(Line::new(
+                format!(
+                    "     [{} instruction{} from {}]",
+                    instructions,
+                    if *instructions == 1 { "" } else { "s" },
+                    origin
+                ),
+                Color::DarkGrey,
+                None,
+            )),
+            Kind::Inlined(_) => {}
+        }
+    }
+    lines
+}
+
+/// Changes the options of the build with `f`.
+fn set_options<F: FnOnce(&mut Asm)>(f: F) {
+    if let Options::Asm(ref mut o) = *opts.write() {
+        f(o);
+    }
+}
+
+/// Error of the terminal `e`.
+fn terminal_error(e: ::std::io::Error) -> Error {
+    Error::Command {
+        msg: format!("failed to draw the terminal user interface: {}", e),
+    }
+}
+
+/// Raw mode and alternate screen of the terminal, restored on drop.
+struct Terminal;
+
+impl Terminal {
+    fn enter() -> ::std::io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut stdout = ::std::io::stdout();
+        crossterm::execute!(
+            stdout,
+            terminal::EnterAlternateScreen,
+            cursor::Hide
+        )?;
+        Ok(Terminal)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut stdout = ::std::io::stdout();
+        let _ = crossterm::execute!(
+            stdout,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Writes the `spans` at `x`, `y`, truncated to `width` characters.
+fn write_spans<W: Write>(
+    out: &mut W,
+    (x, y): (usize, usize),
+    width: usize,
+    spans: &[(String, Color)],
+    selected: bool,
+) -> ::std::io::Result<()> {
+    queue!(out, cursor::MoveTo(x as u16, y as u16))?;
+    if selected {
+        queue!(out, SetAttribute(Attribute::Reverse))?;
+    }
+    let mut left = width;
+    for (text, color) in spans {
+        let text = text.chars().take(left).collect::<String>();
+        left -= text.chars().count();
+        queue!(out, SetForegroundColor(*color), Print(text))?;
+    }
+    if selected {
+        queue!(out, Print(" ".repeat(left)))?;
+    }
+    queue!(out, SetAttribute(Attribute::Reset), ResetColor)
+}
+
+/// Scrolls so that `cursor` is visible in `height` lines.
+fn scroll_to(scroll: &mut usize, cursor: usize, height: usize) {
+    if cursor < *scroll {
+        *scroll = cursor;
+    } else if height > 0 && cursor >= *scroll + height {
+        *scroll = cursor + 1 - height;
+    }
+}
+
+impl<'a> App<'a> {
+    fn new(
+        files: &'a [PathBuf],
+        target: &'a TargetInfo,
+    ) -> Result<Self, Error> {
+        let mut names = crate::asm::index_files(files, target)?
+            .into_iter()
+            .map(|e| e.name)
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        let known = names.iter().cloned().collect();
+        Ok(Self {
+            files,
+            target,
+            names,
+            known,
+            views: Vec::new(),
+            search: None,
+            split: true,
+            message: None,
+        })
+    }
+
+    /// Opens the function `name` in a new view.
+    fn open(&mut self, name: &str) -> Result<(), Error> {
+        use crate::asm::parse::Result as ParseResult;
+        let (mut function, file_table) = match crate::asm::parse_files(
+            self.files,
+            Some(name),
+            self.target,
+        )? {
+            ParseResult::Found(function, file_table) => (function, file_table),
+            ParseResult::NotFound(_) => {
+                self.message = Some(format!("could not find {}", name));
+                return Ok(());
+            }
+        };
+        let info = crate::asm::dwarf::info(&function.asm_file, self.target)?;
+        crate::asm::resolve_declaration(&mut function, &info, &file_table);
+        let rust = crate::rust::parse(&function, &file_table)?;
+        let lines = lines(&function, &rust, &self.known, self.target);
+        self.views.push(View {
+            function: *function,
+            rust,
+            lines,
+            cursor: 0,
+            scroll: 0,
+            sources: HashMap::new(),
+        });
+        self.search = None;
+        Ok(())
+    }
+
+    /// Recomputes the lines of the views after a change of the options.
+    fn refresh(&mut self) {
+        for v in &mut self.views {
+            v.lines = lines(&v.function, &v.rust, &self.known, self.target);
+            v.cursor = v.cursor.min(v.lines.len().saturating_sub(1));
+        }
+    }
+
+    fn start_search(&mut self) {
+        self.search = Some(Search {
+            matches: (0..self.names.len()).collect(),
+            ..Search::default()
+        });
+    }
+
+    fn update_matches(&mut self) {
+        let names = &self.names;
+        if let Some(ref mut s) = self.search {
+            let mut matches = names
+                .iter()
+                .enumerate()
+                .filter_map(|(i, n)| Some((fuzzy_score(&s.query, n)?, i)))
+                .collect::<Vec<_>>();
+            matches.sort_by(|(a, i), (b, j)| {
+                b.cmp(a).then(names[*i].len().cmp(&names[*j].len()))
+            });
+            s.matches = matches.into_iter().map(|(_, i)| i).collect();
+            s.selected = 0;
+            s.scroll = 0;
+        }
+    }
+
+    /// Follows the link of the current line of the current view.
+    fn follow(&mut self) -> Result<(), Error> {
+        let view = match self.views.last_mut() {
+            Some(view) => view,
+            None => return Ok(()),
+        };
+        match view.lines.get(view.cursor).and_then(|l| l.link.clone()) {
+            Some(Link::Label(label)) => {
+                match view
+                    .lines
+                    .iter()
+                    .position(|l| l.label.as_ref() == Some(&label))
+                {
+                    Some(i) => view.cursor = i,
+                    None => {
+                        self.message = Some(format!("{} is hidden", label));
+                    }
+                }
+            }
+            Some(Link::Function(name)) => self.open(&name)?,
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Handles the key, returns false to quit.
+    fn key(&mut self, key: KeyEvent, height: usize) -> Result<bool, Error> {
+        self.message = None;
+        if key.modifiers.contains(KeyModifiers::CONTROL)
+            && key.code == KeyCode::Char('c')
+        {
+            return Ok(false);
+        }
+        let page = height.max(1);
+
+        if let Some(ref mut s) = self.search {
+            match key.code {
+                KeyCode::Esc => {
+                    if self.views.is_empty() {
+                        return Ok(false);
+                    }
+                    self.search = None;
+                }
+                KeyCode::Enter => {
+                    if let Some(&i) = s.matches.get(s.selected) {
+                        let name = self.names[i].clone();
+                        self.open(&name)?;
+                    }
+                }
+                KeyCode::Up => s.selected = s.selected.saturating_sub(1),
+                KeyCode::Down => {
+                    s.selected = (s.selected + 1)
+                        .min(s.matches.len().saturating_sub(1));
+                }
+                KeyCode::PageUp => {
+                    s.selected = s.selected.saturating_sub(page)
+                }
+                KeyCode::PageDown => {
+                    s.selected = (s.selected + page)
+                        .min(s.matches.len().saturating_sub(1));
+                }
+                KeyCode::Backspace => {
+                    s.query.pop();
+                    self.update_matches();
+                }
+                KeyCode::Char(c) => {
+                    s.query.push(c);
+                    self.update_matches();
+                }
+                _ => {}
+            }
+            return Ok(true);
+        }
+
+        let view = match self.views.last_mut() {
+            Some(view) => view,
+            None => {
+                self.start_search();
+                return Ok(true);
+            }
+        };
+        let last = view.lines.len().saturating_sub(1);
+        match key.code {
+            KeyCode::Char('q') => return Ok(false),
+            KeyCode::Up | KeyCode::Char('k') => {
+                view.cursor = view.cursor.saturating_sub(1)
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                view.cursor = (view.cursor + 1).min(last)
+            }
+            KeyCode::PageUp => view.cursor = view.cursor.saturating_sub(page),
+            KeyCode::PageDown => view.cursor = (view.cursor + page).min(last),
+            KeyCode::Home | KeyCode::Char('g') => view.cursor = 0,
+            KeyCode::End | KeyCode::Char('G') => view.cursor = last,
+            KeyCode::Enter | KeyCode::Right => self.follow()?,
+            KeyCode::Backspace | KeyCode::Left | KeyCode::Esc => {
+                self.views.pop();
+                if self.views.is_empty() {
+                    self.start_search();
+                }
+            }
+            KeyCode::Char('/') => self.start_search(),
+            KeyCode::Char('s') => self.split = !self.split,
+            KeyCode::Char('c') => {
+                set_options(|o| o.comments = !o.comments);
+                self.refresh();
+            }
+            KeyCode::Char('d') => {
+                set_options(|o| o.directives = !o.directives);
+                self.refresh();
+            }
+            KeyCode::Char('r') => {
+                set_options(|o| o.rust = !o.rust);
+                self.refresh();
+            }
+            KeyCode::Char('f') => {
+                set_options(|o| {
+                    o.std = match o.std {
+                        Foreign::Show => Foreign::Collapse,
+                        Foreign::Collapse => Foreign::Hide,
+                        Foreign::Hide => Foreign::Show,
+                    }
+                });
+                self.refresh();
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn draw<W: Write>(&mut self, out: &mut W) -> ::std::io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (usize::from(width), usize::from(height));
+        let body = height.saturating_sub(2);
+        queue!(out, terminal::Clear(terminal::ClearType::All))?;
+
+        let (title, help) = if let Some(ref mut s) = self.search {
+            scroll_to(&mut s.scroll, s.selected, body);
+            for (row, &i) in
+                s.matches.iter().skip(s.scroll).take(body).enumerate()
+            {
+                let name = vec![(self.names[i].clone(), Color::Reset)];
+                let selected = s.scroll + row == s.selected;
+                write_spans(out, (0, row + 1), width, &name, selected)?;
+            }
+            (
+                format!("search: {}", s.query),
+                format!(
+                    "{} of {} functions | enter: open  esc: back",
+                    s.matches.len(),
+                    self.names.len()
+                ),
+            )
+        } else if let Some(view) = self.views.last_mut() {
+            let split = self.split && width >= SPLIT_MIN_WIDTH;
+            let asm_x = if split { width / 2 + 1 } else { 0 };
+            scroll_to(&mut view.scroll, view.cursor, body);
+            for (row, line) in
+                view.lines.iter().skip(view.scroll).take(body).enumerate()
+            {
+                let selected = view.scroll + row == view.cursor;
+                write_spans(
+                    out,
+                    (asm_x, row + 1),
+                    width - asm_x,
+                    &line.spans,
+                    selected,
+                )?;
+            }
+            // The location of the current line, or of the closest line
+            // before it (none if the function has no lines):
+            let loc = view
+                .lines
+                .get(..=view.cursor)
+                .unwrap_or(&[])
+                .iter()
+                .rev()
+                .find_map(|l| l.loc)
+                .filter(|l| l.file_line > 0);
+            let mut title = view.function.id.clone();
+            if let Some(loc) = loc {
+                if let Some(path) = view.rust.paths.get(&loc.file_index) {
+                    let mut path = path.clone();
+                    display::make_path_relative(&mut path);
+                    title = format!(
+                        "{} | {}:{}",
+                        title,
+                        path.display(),
+                        loc.file_line
+                    );
+                }
+            }
+            if split {
+                if let Some(loc) = loc {
+                    self::draw_source(out, view, loc, width / 2, body)?;
+                }
+                for row in 1..=body {
+                    queue!(
+                        out,
+                        cursor::MoveTo((width / 2) as u16, row as u16),
+                        SetForegroundColor(Color::DarkGrey),
+                        Print("│"),
+                        ResetColor
+                    )?;
+                }
+            }
+            (
+                title,
+                "enter: follow  backspace: back  /: search  c/d/r: comments/directives/rust  f: std  s: source  q: quit".to_string(),
+            )
+        } else {
+            (String::new(), String::new())
+        };
+
+        let bar = |text: String| vec![(text, Color::Reset)];
+        write_spans(out, (0, 0), width, &bar(title), true)?;
+        let status = self.message.clone().unwrap_or(help);
+        write_spans(
+            out,
+            (0, height.saturating_sub(1)),
+            width,
+            &bar(status),
+            true,
+        )?;
+        out.flush()
+    }
+}
+
+/// Draws the Rust source of the `view` around the location `loc` in the
+/// left pane of `width` and `height`.
+fn draw_source<W: Write>(
+    out: &mut W,
+    view: &mut View,
+    loc: Loc,
+    width: usize,
+    height: usize,
+) -> ::std::io::Result<()> {
+    let rust = &view.rust;
+    let source = view.sources.entry(loc.file_index).or_insert_with(|| {
+        rust.files
+            .get(&loc.file_index)
+            .and_then(|f| crate::rust::contents(&f.ast).ok())
+            .map(|c| c.lines().map(str::to_string).collect())
+            .unwrap_or_default()
+    });
+    let first = loc.file_line.saturating_sub(height / 2).max(1);
+    for (row, n) in (first..first + height).enumerate() {
+        let line = match source.get(n - 1) {
+            Some(line) => line.replace('\t', "    "),
+            None => break,
+        };
+        let spans =
+            vec![(format!("{:>5} ", n), Color::DarkGrey), (line, Color::Red)];
+        write_spans(out, (0, row + 1), width, &spans, n == loc.file_line)?;
+    }
+    Ok(())
+}
+
+/// Builds the project and browses its functions.
+pub fn run(tui: &Tui) -> Result<(), Error> {
+    // The source pane needs the locations of the statements:
+    set_options(|o| o.debug_info = true);
+    let files = crate::build::project();
+    if files.is_empty() {
+        display::write_error("cargo asm could not find any output files!");
+        ::std::process::exit(1);
+    }
+    let target = TargetInfo::new_from_target();
+    let mut app = App::new(&files, &target)?;
+    app.split = !tui.args.no_source;
+    match opts.path() {
+        Some(path) => {
+            app.open(&path)?;
+            if app.views.is_empty() {
+                app.start_search();
+                if let Some(ref mut s) = app.search {
+                    s.query = path;
+                }
+                app.update_matches();
+            }
+        }
+        None => app.start_search(),
+    }
+
+    let terminal = Terminal::enter().map_err(terminal_error)?;
+    let mut stdout = ::std::io::stdout();
+    loop {
+        app.draw(&mut stdout).map_err(terminal_error)?;
+        let height = terminal::size()
+            .map(|(_, h)| usize::from(h).saturating_sub(2))
+            .unwrap_or(0);
+        if let Event::Key(key) =
+            crossterm::event::read().map_err(terminal_error)?
+        {
+            if key.kind == KeyEventKind::Press && !app.key(key, height)? {
+                break;
+            }
+        }
+    }
+    drop(terminal);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_search() {
+        assert_eq!(fuzzy_score("xyz", "dcrate::top"), None);
+        assert_eq!(fuzzy_score("", "dcrate::top"), Some(0));
+        let better = |query, a, b| {
+            fuzzy_score(query, a).unwrap() > fuzzy_score(query, b).unwrap()
+        };
+        // Consecutive characters:
+        assert!(better("top", "dcrate::top", "dcrate::t_o_p"));
+        // Start of a path component:
+        assert!(better("h", "dcrate::helper", "dcrate::chelper"));
+        // Case insensitive:
+        assert!(fuzzy_score("DISP", "dcrate::dispatch").is_some());
+    }
+}
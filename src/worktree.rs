@@ -0,0 +1,78 @@
+//! Temporary git worktrees to build other revisions of the project.
+
+use crate::error::Error;
+use log::debug;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Checkout of a git revision in a temporary directory, which is removed
+/// when dropped.
+pub struct Worktree {
+    path: PathBuf,
+    /// Path of the current directory relative to the root of the repository.
+    prefix: PathBuf,
+}
+
+/// Runs `git` with the `args` in the current directory.
+fn git(args: &[&str]) -> Result<String, Error> {
+    let mut cmd = Command::new("git");
+    cmd.args(args);
+    let msg = format!("git {} failed", args.join(" "));
+    crate::process::exec(&mut cmd, &msg, false)
+        .map(|(stdout, _)| stdout.trim().to_string())
+        .map_err(|()| Error::Command { msg })
+}
+
+impl Worktree {
+    /// Checks out the revision `rev` of the repository of the current
+    /// directory.
+    pub fn new(rev: &str) -> Result<Self, Error> {
+        let prefix = PathBuf::from(git(&["rev-parse", "--show-prefix"])?);
+        let path = ::std::env::temp_dir()
+            .join(format!("cargo-asm-{}", ::std::process::id()));
+        let path_str = path.to_string_lossy().to_string();
+        git(&["worktree", "add", "--detach", &path_str, rev])?;
+        debug!("checked out {} into {}", rev, path.display());
+        Ok(Self { path, prefix })
+    }
+
+    /// Directory of the worktree that corresponds to the current directory.
+    pub fn current_dir(&self) -> PathBuf {
+        self.path.join(&self.prefix)
+    }
+
+    /// Runs `f` in the worktree, building into the `target_dir`.
+    pub fn run<T, F: FnOnce() -> T>(
+        &self,
+        target_dir: &Path,
+        f: F,
+    ) -> Result<T, Error> {
+        let cwd = ::std::env::current_dir()
+            .map_err(|e| Error::io(Path::new("."), e))?;
+        let target_dir_var = ::std::env::var_os("CARGO_TARGET_DIR");
+        ::std::env::set_current_dir(self.current_dir())
+            .map_err(|e| Error::io(&self.current_dir(), e))?;
+        ::std::env::set_var("CARGO_TARGET_DIR", target_dir);
+
+        let result = f();
+
+        match target_dir_var {
+            Some(v) => ::std::env::set_var("CARGO_TARGET_DIR", v),
+            None => ::std::env::remove_var("CARGO_TARGET_DIR"),
+        }
+        ::std::env::set_current_dir(&cwd).map_err(|e| Error::io(&cwd, e))?;
+        Ok(result)
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        let path = self.path.to_string_lossy().to_string();
+        if git(&["worktree", "remove", "--force", &path]).is_err() {
+            crate::display::write_warning(&format!(
+                "failed to remove the git worktree at {}\n",
+                path
+            ));
+        }
+    }
+}